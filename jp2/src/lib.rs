@@ -59,6 +59,12 @@ pub enum JP2Error {
     /// Some boxes are required to be present. If a required
     /// box is not present, this error will be returned.
     BoxMissing { box_type: BoxType },
+
+    /// Unsupported number of components.
+    ///
+    /// At this time only greyscale images of one component and sRGB images
+    /// of three components are encoded.
+    ComponentsUnsupported { no_components: usize },
 }
 
 impl error::Error for JP2Error {}
@@ -104,6 +110,13 @@ impl fmt::Display for JP2Error {
             Self::BoxMissing { box_type } => {
                 write!(f, "box type {:?} missing", box_type)
             }
+            Self::ComponentsUnsupported { no_components } => {
+                write!(
+                    f,
+                    "{} components are neither greyscale nor sRGB",
+                    no_components
+                )
+            }
             Self::Unsupported => {
                 write!(
                     f,
@@ -446,36 +459,36 @@ impl JBox for FileTypeBox {
 /// defined within ISO/IEC 15444-1 | ITU T-800 are as follows:
 ///
 /// - Image Header box - This box specifies information about the image, such
-/// as its height and width.
+///   as its height and width.
 ///
 /// - Bits Per Component box - This box specifies the bit depth of each
-/// component in the codestream after decompression. This box may be found
-/// anywhere in the JP2 Header box provided that it comes after the Image Header
-/// box.
+///   component in the codestream after decompression. This box may be found
+///   anywhere in the JP2 Header box provided that it comes after the Image Header
+///   box.
 ///
 /// - Colour Specification boxes - These boxes specify the colourspace of the
-/// decompressed image. The use of multiple Colour Specification boxes
-/// provides the ability for a decoder to be given multiple optimization or
-/// compatibility options for colour processing. These boxes may be found
-/// anywhere in the JP2 Header box provided that they come after the Image Header
-/// box. All Colour Specification boxes shall be contiguous within the JP2 Header
-/// box.
+///   decompressed image. The use of multiple Colour Specification boxes
+///   provides the ability for a decoder to be given multiple optimization or
+///   compatibility options for colour processing. These boxes may be found
+///   anywhere in the JP2 Header box provided that they come after the Image Header
+///   box. All Colour Specification boxes shall be contiguous within the JP2 Header
+///   box.
 ///
 /// - Palette box - This box defines the palette to use to create multiple
-/// components from a single component. This box may be found anywhere in the JP2
-/// Header box provided that it comes after the Image Header box.
+///   components from a single component. This box may be found anywhere in the JP2
+///   Header box provided that it comes after the Image Header box.
 ///
 /// - Component Mapping box - This box defines how image channels are identified
-/// from the actual components in the codestream. This box may be found anywhere
-/// in the JP2 Header box provided that it comes after the Image Header box.
+///   from the actual components in the codestream. This box may be found anywhere
+///   in the JP2 Header box provided that it comes after the Image Header box.
 ///
 /// - Channel Definition box - This box defines the channels in the image. This
-/// box may be found anywhere in the JP2 Header box provided that it comes after
-/// the ImageHeader box.
+///   box may be found anywhere in the JP2 Header box provided that it comes after
+///   the ImageHeader box.
 ///
 /// - Resolution box - This box specifies the capture and default display grid
-/// resolutions of the image. This box may be found anywhere in the JP2 Header
-/// box provided that it comes after the Image Header box.
+///   resolutions of the image. This box may be found anywhere in the JP2 Header
+///   box provided that it comes after the Image Header box.
 ///
 /// For more information, see ISO/IEC 15444-1 | ITU T-800 Appendix I.5.3.
 #[derive(Debug, Default)]
@@ -3198,3 +3211,114 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
 
     Ok(result)
 }
+
+/// Encode the components of an image into a JP2 file with a losslessly
+/// encoded codestream, see jpc::encode_image.
+///
+/// One component is a greyscale image and three components are an sRGB
+/// image, as signalled by the enumerated colourspace of the Colour
+/// Specification box.
+pub fn encode_jp2<W: io::Write>(
+    writer: &mut W,
+    components: &[jpc::Component],
+    options: &jpc::EncodeOptions,
+) -> Result<(), Box<dyn error::Error>> {
+    let enumerated_colour_space = match components.len() {
+        1 => ENUMERATED_COLOUR_SPACE_GREYSCALE,
+        3 => ENUMERATED_COLOUR_SPACE_SRGB,
+        no_components => return Err(JP2Error::ComponentsUnsupported { no_components }.into()),
+    };
+
+    let mut codestream = vec![];
+    jpc::encode_image(&mut codestream, components, options)?;
+
+    // I.5.1 - Signature box
+    encode_box(writer, BOX_TYPE_SIGNATURE, &SIGNATURE_MAGIC)?;
+
+    // I.5.2 - File Type box, with the JP2 brand, a minor version of 0 and
+    // the JP2 brand as the only compatible brand
+    let mut file_type = vec![];
+    file_type.extend_from_slice(&BRAND_JP2);
+    file_type.extend_from_slice(&0u32.to_be_bytes());
+    file_type.extend_from_slice(&BRAND_JP2);
+    encode_box(writer, BOX_TYPE_FILE_TYPE, &file_type)?;
+
+    // I.5.3 - JP2 Header box
+    let mut header = vec![];
+
+    // I.5.3.1 - Image Header box, with a BPC of 255 when the components
+    // differ in bit depth, which the Bits Per Component box then specifies
+    let bits_per_component: Vec<u8> = components
+        .iter()
+        .map(|component| (component.precision() - 1) | ((component.values_are_signed() as u8) << 7))
+        .collect();
+    let components_bits = if bits_per_component
+        .iter()
+        .all(|bits| *bits == bits_per_component[0])
+    {
+        bits_per_component[0]
+    } else {
+        255
+    };
+
+    let first = &components[0];
+    let mut image_header = vec![];
+    image_header.extend_from_slice(&first.height().to_be_bytes());
+    image_header.extend_from_slice(&first.width().to_be_bytes());
+    image_header.extend_from_slice(&(components.len() as u16).to_be_bytes());
+    image_header.push(components_bits);
+    image_header.push(COMPRESSION_TYPE_WAVELET);
+    // The colourspace is known and there is no intellectual property
+    image_header.push(0);
+    image_header.push(0);
+    encode_box(&mut header, BOX_TYPE_IMAGE_HEADER, &image_header)?;
+
+    // I.5.3.2 - Bits Per Component box
+    if components_bits == 255 {
+        encode_box(
+            &mut header,
+            BOX_TYPE_BITS_PER_COMPONENT,
+            &bits_per_component,
+        )?;
+    }
+
+    // I.5.3.3 - Colour Specification box, with an enumerated colourspace of
+    // precedence 0 and without approximation
+    let mut colour_specification = vec![];
+    colour_specification.extend_from_slice(&METHOD_ENUMERATED_COLOUR_SPACE);
+    colour_specification.push(0);
+    colour_specification.push(0);
+    colour_specification.extend_from_slice(&enumerated_colour_space);
+    encode_box(
+        &mut header,
+        BOX_TYPE_COLOUR_SPECIFICATION,
+        &colour_specification,
+    )?;
+    encode_box(writer, BOX_TYPE_HEADER, &header)?;
+
+    // I.5.4 - Contiguous Codestream box
+    encode_box(writer, BOX_TYPE_CONTIGUOUS_CODESTREAM, &codestream)?;
+
+    Ok(())
+}
+
+// I.4 - A box with its length (LBox), type (TBox) and contents, with the
+// length in XLBox when it does not fit in LBox
+fn encode_box<W: io::Write>(
+    writer: &mut W,
+    box_type: BoxType,
+    contents: &[u8],
+) -> Result<(), Box<dyn error::Error>> {
+    let box_length = 8 + contents.len() as u64;
+    if box_length <= u32::MAX as u64 {
+        writer.write_all(&(box_length as u32).to_be_bytes())?;
+        writer.write_all(&box_type)?;
+    } else {
+        writer.write_all(&1u32.to_be_bytes())?;
+        writer.write_all(&box_type)?;
+        writer.write_all(&(box_length + 8).to_be_bytes())?;
+    }
+    writer.write_all(contents)?;
+
+    Ok(())
+}
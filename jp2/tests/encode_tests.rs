@@ -0,0 +1,145 @@
+use std::io::Cursor;
+
+use jp2::{
+    decode_jp2, encode_jp2, BitDepth, ColourSpecificationMethods, EnumeratedColourSpaces,
+    JBox as _, JP2File,
+};
+use jpc::{decode_image, Component, DecodeOptions, EncodeOptions, ProgressionOrder};
+
+fn components(no_components: u16, width: u32, height: u32, precisions: &[u8]) -> Vec<Component> {
+    (0..no_components)
+        .map(|c| {
+            let precision = precisions[c as usize % precisions.len()];
+            let max = (1u32 << precision) - 1;
+            let samples = (0..width * height)
+                .map(|i| ((i * 37 + c as u32 * 101) % (max + 1)) as i32)
+                .collect();
+            Component::new(width, height, precision, false, samples)
+        })
+        .collect()
+}
+
+fn encode(components: &[Component], options: &EncodeOptions) -> Vec<u8> {
+    let mut file = vec![];
+    encode_jp2(&mut file, components, options).expect("image should encode");
+    file
+}
+
+// Decode the boxes of a JP2 file and the image of its codestream, which is
+// the same as the components encoded
+fn decode(file: &[u8], components: &[Component]) -> JP2File {
+    let boxes = decode_jp2(&mut Cursor::new(file)).expect("file should decode");
+
+    let codestream_box = &boxes.contiguous_codestreams_boxes()[0];
+    assert_eq!(codestream_box.identifier(), *b"jp2c");
+    let start = codestream_box.offset() as usize;
+    let codestream = &file[start..start + codestream_box.length() as usize];
+    let image = decode_image(&mut Cursor::new(codestream), &DecodeOptions::default())
+        .expect("codestream should decode");
+
+    assert_eq!(image.components().len(), components.len());
+    for (decoded, component) in image.components().iter().zip(components) {
+        assert_eq!(decoded.precision(), component.precision());
+        assert_eq!(decoded.samples(), component.samples());
+    }
+
+    boxes
+}
+
+#[test]
+fn test_encode_greyscale() {
+    let components = components(1, 31, 17, &[8]);
+    let file = encode(&components, &EncodeOptions::default());
+
+    // Signature box followed by the File Type box
+    assert_eq!(
+        &file[..12],
+        &[0, 0, 0, 12, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A]
+    );
+    assert_eq!(&file[12..20], &[0, 0, 0, 20, b'f', b't', b'y', b'p']);
+
+    let boxes = decode(&file, &components);
+    assert_eq!(boxes.length(), file.len() as u64);
+
+    let file_type = boxes.file_type_box().as_ref().unwrap();
+    assert_eq!(file_type.brand(), "jp2 ");
+    assert_eq!(file_type.min_version(), 0);
+    assert_eq!(file_type.compatibility_list(), vec!["jp2 ".to_string()]);
+
+    let header_box = boxes.header_box().as_ref().unwrap();
+    let image_header_box = &header_box.image_header_box;
+    assert_eq!(image_header_box.height(), 17);
+    assert_eq!(image_header_box.width(), 31);
+    assert_eq!(image_header_box.components_num(), 1);
+    assert_eq!(image_header_box.components_bits(), 8);
+    assert!(!image_header_box.values_are_signed());
+    assert_eq!(image_header_box.compression_type(), 7);
+    assert_eq!(image_header_box.colourspace_unknown(), 0);
+    assert_eq!(image_header_box.intellectual_property(), 0);
+    assert!(header_box.bits_per_component_box.is_none());
+
+    assert_eq!(header_box.colour_specification_boxes.len(), 1);
+    let colour_specification_box = &header_box.colour_specification_boxes[0];
+    assert_eq!(
+        colour_specification_box.method(),
+        ColourSpecificationMethods::EnumeratedColourSpace
+    );
+    assert_eq!(colour_specification_box.precedence(), 0);
+    assert_eq!(colour_specification_box.colourspace_approximation(), 0);
+    assert_eq!(
+        colour_specification_box.enumerated_colour_space(),
+        Some(EnumeratedColourSpaces::Greyscale)
+    );
+}
+
+#[test]
+fn test_encode_rgb() {
+    for precision in [8, 16].iter() {
+        let components = components(3, 40, 27, &[*precision]);
+        let options = EncodeOptions {
+            tile_size: Some((16, 16)),
+            code_block_size: (16, 16),
+            no_decomposition_levels: 3,
+            progression_order: ProgressionOrder::RLPCLP,
+        };
+        let boxes = decode(&encode(&components, &options), &components);
+
+        let header_box = boxes.header_box().as_ref().unwrap();
+        assert_eq!(header_box.image_header_box.components_num(), 3);
+        assert_eq!(header_box.image_header_box.components_bits(), *precision);
+        assert_eq!(
+            header_box.colour_specification_boxes[0].enumerated_colour_space(),
+            Some(EnumeratedColourSpaces::sRGB)
+        );
+    }
+}
+
+#[test]
+fn test_encode_bits_per_component() {
+    // Components of different bit depths are specified by the Bits Per
+    // Component box
+    let components = components(3, 9, 11, &[8, 12, 5]);
+    let boxes = decode(&encode(&components, &EncodeOptions::default()), &components);
+
+    let header_box = boxes.header_box().as_ref().unwrap();
+    assert_eq!(header_box.image_header_box.components_bits(), 255);
+    let bits_per_component_box = header_box.bits_per_component_box.as_ref().unwrap();
+    assert_eq!(
+        bits_per_component_box.bits_per_component(),
+        vec![
+            BitDepth::Unsigned { value: 8 },
+            BitDepth::Unsigned { value: 12 },
+            BitDepth::Unsigned { value: 5 }
+        ]
+    );
+}
+
+#[test]
+fn test_encode_components_unsupported() {
+    let mut file = vec![];
+    for no_components in [0, 2, 4].iter() {
+        let components = components(*no_components, 4, 4, &[8]);
+        assert!(encode_jp2(&mut file, &components, &EncodeOptions::default()).is_err());
+    }
+    assert!(file.is_empty());
+}
@@ -785,7 +785,7 @@ fn test_sample_jp2_file(filename: &str, expected: ExpectedConfiguration) -> JP2F
     assert_eq!(image_header_box.colourspace_unknown(), 0);
     assert_eq!(image_header_box.intellectual_property(), 0);
     assert_eq!(image_header_box.components_bits(), expected.bit_depth);
-    assert_eq!(image_header_box.values_are_signed(), false);
+    assert!(!image_header_box.values_are_signed());
 
     assert!(header_box.bits_per_component_box.is_none());
 
@@ -814,13 +814,10 @@ fn test_sample_jp2_file(filename: &str, expected: ExpectedConfiguration) -> JP2F
         ColourSpecificationMethods::RestrictedICCProfile => {
             assert!(colour_specification_box.enumerated_colour_space().is_none());
             assert!(colour_specification_box.restricted_icc_profile().is_some());
-            assert!(
-                colour_specification_box
-                    .restricted_icc_profile()
-                    .unwrap()
-                    .len()
-                    > 0
-            );
+            assert!(!colour_specification_box
+                .restricted_icc_profile()
+                .unwrap()
+                .is_empty());
         }
         ColourSpecificationMethods::Reserved { value } => {
             panic!(
@@ -914,7 +911,7 @@ fn test_jp2_file(filename: &str, expected: ExpectedConfiguration) -> JP2File {
     assert_eq!(image_header_box.colourspace_unknown(), 0);
     assert_eq!(image_header_box.intellectual_property(), 0);
     assert_eq!(image_header_box.components_bits(), expected.bit_depth);
-    assert_eq!(image_header_box.values_are_signed(), false);
+    assert!(!image_header_box.values_are_signed());
 
     assert!(header_box.bits_per_component_box.is_none());
 
@@ -967,7 +964,7 @@ fn test_j2pi() {
     assert_eq!(image_header_box.components_num(), 1);
     assert_eq!(image_header_box.intellectual_property(), 1);
     assert_eq!(image_header_box.components_bits(), 8);
-    assert_eq!(image_header_box.values_are_signed(), false);
+    assert!(!image_header_box.values_are_signed());
 
     assert_eq!(boxes.contiguous_codestreams_boxes().len(), 1);
 
@@ -1001,7 +998,7 @@ fn test_res_boxes() {
     assert_eq!(image_header_box.components_num(), 1);
     assert_eq!(image_header_box.intellectual_property(), 0);
     assert_eq!(image_header_box.components_bits(), 8);
-    assert_eq!(image_header_box.values_are_signed(), false);
+    assert!(!image_header_box.values_are_signed());
 
     assert!(header_box.channel_definition_box.is_none());
     assert!(header_box.resolution_box.is_some());
@@ -1083,7 +1080,7 @@ fn test_hirise_modified() {
     assert_eq!(image_header_box.colourspace_unknown(), 1);
     assert_eq!(image_header_box.intellectual_property(), 0);
     assert_eq!(image_header_box.components_bits(), 10);
-    assert_eq!(image_header_box.values_are_signed(), false);
+    assert!(!image_header_box.values_are_signed());
 
     assert!(header_box.bits_per_component_box.is_none());
 
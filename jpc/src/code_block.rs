@@ -0,0 +1,1217 @@
+use std::cmp;
+use std::error;
+
+use crate::coder::{
+    MqDecoder, MqEncoder, RawDecoder, RawEncoder, Termination, CONTEXT_INDEX_RUN_LENGTH,
+    CONTEXT_INDEX_SIGNIFICANCE, CONTEXT_INDEX_UNIFORM,
+};
+use crate::{CodestreamError, CodingBlockStyle, SubbandOrientation};
+
+// Table D.3 - Sign contexts follow the nine significance propagation contexts
+const CONTEXT_SIGN: usize = 9;
+
+// Table D.4 - Magnitude refinement contexts follow the five sign contexts
+const CONTEXT_REFINEMENT: usize = 14;
+
+// D.5 - Segmentation symbol decoded at the end of each cleanup pass
+const SEGMENTATION_SYMBOL: u8 = 0b1010;
+
+// Coefficient state, see D.3
+const FLAG_SIGNIFICANT: u8 = 0b0001;
+const FLAG_NEGATIVE: u8 = 0b0010;
+// Coded in the significance propagation pass of the current bit-plane
+const FLAG_VISITED: u8 = 0b0100;
+// Coded at least once in a magnitude refinement pass
+const FLAG_REFINED: u8 = 0b1000;
+
+// Code-blocks are scanned in stripes of four rows, see D.1
+const STRIPE_HEIGHT: usize = 4;
+
+// D.6 - With selective arithmetic coding bypass the coding passes of the first
+// four bit-planes, the cleanup pass of the first one and the three coding
+// passes of the next three, are always arithmetically coded
+const NO_ARITHMETIC_PASSES: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CodingPass {
+    SignificancePropagation,
+    MagnitudeRefinement,
+    Cleanup,
+}
+
+impl CodingPass {
+    // The coding passes start with a cleanup pass, followed by the three
+    // coding passes of each bit-plane below it
+    fn new(pass: u8) -> CodingPass {
+        match pass.checked_sub(1).map(|pass| pass % 3) {
+            Some(0) => CodingPass::SignificancePropagation,
+            Some(1) => CodingPass::MagnitudeRefinement,
+            _ => CodingPass::Cleanup,
+        }
+    }
+}
+
+/// Whether the codeword segment ends with the coding pass with the given
+/// index, counting from the first cleanup pass, see Table D.9
+///
+/// Every coding pass is terminated with termination on each coding pass.
+/// With selective arithmetic coding bypass the arithmetically coded passes
+/// are terminated before each raw segment of a significance propagation and
+/// magnitude refinement pass, and each raw segment is terminated in turn.
+pub fn is_terminated(
+    pass: u8,
+    selective_arithmetic_coding_bypass: bool,
+    termination_on_each_coding_pass: bool,
+) -> bool {
+    termination_on_each_coding_pass
+        || (selective_arithmetic_coding_bypass
+            && pass >= NO_ARITHMETIC_PASSES - 1
+            && CodingPass::new(pass) != CodingPass::SignificancePropagation)
+}
+
+// D.6 - Whether the coding pass is coded with raw bits
+fn is_raw(pass: u8, selective_arithmetic_coding_bypass: bool) -> bool {
+    selective_arithmetic_coding_bypass
+        && pass >= NO_ARITHMETIC_PASSES
+        && CodingPass::new(pass) != CodingPass::Cleanup
+}
+
+// The significance propagation and magnitude refinement passes decode their
+// symbols with the MQ decoder in the context given, or as raw bits
+trait SymbolDecoder {
+    fn decode_symbol(&mut self, context: usize) -> u8;
+}
+
+impl SymbolDecoder for MqDecoder<'_> {
+    fn decode_symbol(&mut self, context: usize) -> u8 {
+        self.decode(context)
+    }
+}
+
+impl SymbolDecoder for RawDecoder<'_> {
+    fn decode_symbol(&mut self, _context: usize) -> u8 {
+        self.decode()
+    }
+}
+
+// The contexts of the coefficients are formed from the state of their
+// neighbours the same way when encoding and decoding, see D.3
+trait ContextModel {
+    fn stride(&self) -> usize;
+    fn flags(&self) -> &[u8];
+    fn orientation(&self) -> SubbandOrientation;
+    fn vertically_causal_context(&self) -> bool;
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * self.stride() + x + 1
+    }
+
+    // D.7 - With vertically causal context formation the coefficients of the
+    // next stripe are treated as insignificant
+    fn is_causal(&self, y: usize) -> bool {
+        self.vertically_causal_context() && y % STRIPE_HEIGHT == STRIPE_HEIGHT - 1
+    }
+
+    fn significant(&self, i: usize) -> u8 {
+        self.flags()[i] & FLAG_SIGNIFICANT
+    }
+
+    // Table D.1 - Contexts for the significance propagation and cleanup
+    // coding passes
+    fn significance_context(&self, i: usize, causal: bool) -> usize {
+        let s = self.stride();
+
+        let h = self.significant(i - 1) + self.significant(i + 1);
+        let mut v = self.significant(i - s);
+        let mut d = self.significant(i - s - 1) + self.significant(i - s + 1);
+        if !causal {
+            v += self.significant(i + s);
+            d += self.significant(i + s - 1) + self.significant(i + s + 1);
+        }
+
+        match self.orientation() {
+            SubbandOrientation::LL | SubbandOrientation::LH => significance_context_lh(h, v, d),
+            SubbandOrientation::HL => significance_context_lh(v, h, d),
+            SubbandOrientation::HH => match (d, h + v) {
+                (3.., _) => 8,
+                (2, 1..) => 7,
+                (2, 0) => 6,
+                (1, 2..) => 5,
+                (1, 1) => 4,
+                (1, 0) => 3,
+                (0, 2..) => 2,
+                (0, 1) => 1,
+                _ => 0,
+            },
+        }
+    }
+
+    fn sign_contribution(&self, i: usize) -> i8 {
+        match self.flags()[i] & (FLAG_SIGNIFICANT | FLAG_NEGATIVE) {
+            FLAG_SIGNIFICANT => 1,
+            0 => 0,
+            _ => -1,
+        }
+    }
+
+    // Table D.3 - Contexts and the XOR bit for the sign bit decoding
+    fn sign_context(&self, i: usize, causal: bool) -> (usize, u8) {
+        let s = self.stride();
+
+        let h = (self.sign_contribution(i - 1) + self.sign_contribution(i + 1)).clamp(-1, 1);
+        let mut v = self.sign_contribution(i - s);
+        if !causal {
+            v += self.sign_contribution(i + s);
+        }
+
+        match (h, v.clamp(-1, 1)) {
+            (1, 1) => (CONTEXT_SIGN + 4, 0),
+            (1, 0) => (CONTEXT_SIGN + 3, 0),
+            (1, _) => (CONTEXT_SIGN + 2, 0),
+            (0, 1) => (CONTEXT_SIGN + 1, 0),
+            (0, 0) => (CONTEXT_SIGN, 0),
+            (0, _) => (CONTEXT_SIGN + 1, 1),
+            (_, 1) => (CONTEXT_SIGN + 2, 1),
+            (_, 0) => (CONTEXT_SIGN + 3, 1),
+            _ => (CONTEXT_SIGN + 4, 1),
+        }
+    }
+
+    // Table D.4 - Contexts for the magnitude refinement coding passes
+    fn refinement_context(&self, i: usize, causal: bool) -> usize {
+        if self.flags()[i] & FLAG_REFINED != 0 {
+            CONTEXT_REFINEMENT + 2
+        } else if self.significance_context(i, causal) != 0 {
+            CONTEXT_REFINEMENT + 1
+        } else {
+            CONTEXT_REFINEMENT
+        }
+    }
+
+    // Run-length decoding is used when all four coefficients of the column
+    // are insignificant, not yet decoded and have insignificant neighbours.
+    fn is_run_length_column(&self, x: usize, y0: usize) -> bool {
+        (y0..y0 + STRIPE_HEIGHT).all(|y| {
+            let i = self.index(x, y);
+            self.flags()[i] & (FLAG_SIGNIFICANT | FLAG_VISITED) == 0
+                && self.significance_context(i, self.is_causal(y)) == 0
+        })
+    }
+}
+
+/// Code-block decoder for the coefficient bit modelling, see Annex D
+///
+/// Reconstructs the quantized coefficients of a single code-block from its
+/// codeword segments by running the significance propagation, magnitude
+/// refinement and cleanup coding passes from the most significant bit-plane
+/// downwards.
+#[derive(Debug)]
+pub struct CodeBlockDecoder {
+    width: usize,
+    height: usize,
+    orientation: SubbandOrientation,
+
+    selective_arithmetic_coding_bypass: bool,
+    reset_context_probabilities: bool,
+    termination_on_each_coding_pass: bool,
+    vertically_causal_context: bool,
+    predictable_termination: bool,
+    segmentation_symbols: bool,
+
+    // Coefficient state and magnitudes, with a border of one coefficient on
+    // every side so that neighbours can be read without bounds checks.
+    stride: usize,
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+
+    no_bit_planes_decoded: u8,
+    no_passes_decoded: u8,
+}
+
+// The state of a code-block after the coding passes which are known to be
+// intact, which a resilient decoder falls back to
+#[derive(Debug)]
+struct Checkpoint {
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+    no_bit_planes_decoded: u8,
+    no_passes_decoded: u8,
+}
+
+impl CodeBlockDecoder {
+    pub fn new(
+        width: u32,
+        height: u32,
+        orientation: SubbandOrientation,
+        code_block_style: u8,
+    ) -> CodeBlockDecoder {
+        let coding_block_styles = CodingBlockStyle::new(code_block_style);
+        let width = width as usize;
+        let height = height as usize;
+        let stride = width + 2;
+
+        CodeBlockDecoder {
+            width,
+            height,
+            orientation,
+            selective_arithmetic_coding_bypass: coding_block_styles
+                .contains(&CodingBlockStyle::SelectiveArithmeticCodingBypass),
+            reset_context_probabilities: coding_block_styles
+                .contains(&CodingBlockStyle::ResetContextProbabilities),
+            termination_on_each_coding_pass: coding_block_styles
+                .contains(&CodingBlockStyle::TerminationOnEachCodingPass),
+            vertically_causal_context: coding_block_styles
+                .contains(&CodingBlockStyle::VerticallyCausalContext),
+            predictable_termination: coding_block_styles
+                .contains(&CodingBlockStyle::PredictableTermination),
+            segmentation_symbols: coding_block_styles
+                .contains(&CodingBlockStyle::SegmentationSymbolsAreUsed),
+            stride,
+            flags: vec![0; stride * (height + 2)],
+            magnitudes: vec![0; stride * (height + 2)],
+            no_bit_planes_decoded: 0,
+            no_passes_decoded: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// Number of bit-planes which at least one coding pass was decoded for,
+    /// N_b in E.1.1.2.
+    pub fn no_bit_planes_decoded(&self) -> u8 {
+        self.no_bit_planes_decoded
+    }
+
+    /// Number of coding passes decoded, which is less than the number of
+    /// coding passes included when a resilient decoder discarded corrupt
+    /// coding passes.
+    pub fn no_passes_decoded(&self) -> u8 {
+        self.no_passes_decoded
+    }
+
+    /// Quantized coefficients in raster order, as signed values with the
+    /// decoded bit-planes aligned to the magnitude bits M_b.
+    pub fn coefficients(&self) -> Vec<i32> {
+        let mut coefficients = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = self.index(x, y);
+                let magnitude = self.magnitudes[i] as i32;
+                if self.flags[i] & FLAG_NEGATIVE != 0 {
+                    coefficients.push(-magnitude);
+                } else {
+                    coefficients.push(magnitude);
+                }
+            }
+        }
+        coefficients
+    }
+
+    /// Decode the coding passes of the code-block.
+    ///
+    /// The codeword segments are the bytes contributed to the code-block by
+    /// each packet, which continue a single codeword segment unless coding
+    /// passes are terminated. With selective arithmetic coding bypass or
+    /// termination on each coding pass every codeword segment ends with a
+    /// terminated coding pass instead, see [`is_terminated`].
+    ///
+    /// no_passes is the total number of coding passes included,
+    /// zero_bit_planes the number of missing most significant bit-planes (P)
+    /// and magnitude_bits the number of magnitude bits of the subband (M_b).
+    pub fn decode(
+        &mut self,
+        segments: &[&[u8]],
+        no_passes: u8,
+        zero_bit_planes: u8,
+        magnitude_bits: u8,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.decode_passes(segments, no_passes, zero_bit_planes, magnitude_bits, false)
+    }
+
+    /// Decode the coding passes of the code-block like
+    /// [`decode`](Self::decode), but discard the coding passes which are
+    /// found to be corrupt instead of failing.
+    ///
+    /// With segmentation symbols the coding passes of a bit-plane are
+    /// discarded when the segmentation symbol of its cleanup pass is not
+    /// 1010, and every coding pass after it. With predictable termination the
+    /// coding passes since the last correct segmentation symbol are discarded
+    /// when the codeword segment does not end as predicted, see D.4.2 and D.5.
+    pub fn decode_resilient(
+        &mut self,
+        segments: &[&[u8]],
+        no_passes: u8,
+        zero_bit_planes: u8,
+        magnitude_bits: u8,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.decode_passes(segments, no_passes, zero_bit_planes, magnitude_bits, true)
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            flags: self.flags.clone(),
+            magnitudes: self.magnitudes.clone(),
+            no_bit_planes_decoded: self.no_bit_planes_decoded,
+            no_passes_decoded: self.no_passes_decoded,
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.flags = checkpoint.flags;
+        self.magnitudes = checkpoint.magnitudes;
+        self.no_bit_planes_decoded = checkpoint.no_bit_planes_decoded;
+        self.no_passes_decoded = checkpoint.no_passes_decoded;
+    }
+
+    fn decode_passes(
+        &mut self,
+        segments: &[&[u8]],
+        no_passes: u8,
+        zero_bit_planes: u8,
+        magnitude_bits: u8,
+        resilient: bool,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.flags.iter_mut().for_each(|flag| *flag = 0);
+        self.magnitudes
+            .iter_mut()
+            .for_each(|magnitude| *magnitude = 0);
+        self.no_bit_planes_decoded = 0;
+        self.no_passes_decoded = 0;
+
+        if no_passes == 0 || self.width == 0 || self.height == 0 {
+            return Ok(());
+        }
+
+        if magnitude_bits > 31 {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!("{} magnitude bits exceeds 31", magnitude_bits),
+            }
+            .into());
+        }
+        if zero_bit_planes >= magnitude_bits {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!(
+                    "{} zero bit-planes exceeds {} magnitude bits",
+                    zero_bit_planes, magnitude_bits
+                ),
+            }
+            .into());
+        }
+
+        // Without termination the passes of every packet continue a single
+        // codeword segment.
+        let concatenated;
+        let data;
+        let segments =
+            if self.selective_arithmetic_coding_bypass || self.termination_on_each_coding_pass {
+                segments
+            } else {
+                concatenated = segments.concat();
+                data = [&concatenated[..]];
+                &data[..]
+            };
+        let mut segments = segments.iter().copied();
+        let mut decoder = MqDecoder::new(segments.next().unwrap_or_default());
+        let mut raw_decoder = None;
+
+        // The first coding pass is a cleanup pass on the most significant
+        // bit-plane that is not all zero.
+        let top_bit_plane = magnitude_bits - 1 - zero_bit_planes;
+        let mut bit_plane = top_bit_plane as i32;
+        let mut checkpoint = if resilient {
+            Some(self.checkpoint())
+        } else {
+            None
+        };
+
+        for index in 0..no_passes {
+            let pass = CodingPass::new(index);
+            if index > 0 && pass == CodingPass::SignificancePropagation {
+                bit_plane -= 1;
+            }
+            if bit_plane < 0 {
+                return Err(CodestreamError::CodeBlockError {
+                    error: format!(
+                        "{} coding passes exceeds {} bit-planes",
+                        no_passes,
+                        top_bit_plane + 1
+                    ),
+                }
+                .into());
+            }
+
+            // A new codeword segment starts after a terminated coding pass,
+            // with the arithmetic decoder keeping the state of its contexts
+            let raw = is_raw(index, self.selective_arithmetic_coding_bypass);
+            let terminated = is_terminated(
+                index,
+                self.selective_arithmetic_coding_bypass,
+                self.termination_on_each_coding_pass,
+            );
+            if index > 0
+                && is_terminated(
+                    index - 1,
+                    self.selective_arithmetic_coding_bypass,
+                    self.termination_on_each_coding_pass,
+                )
+            {
+                let segment = segments.next().unwrap_or_default();
+                if raw {
+                    raw_decoder = Some(RawDecoder::new(segment));
+                } else {
+                    decoder.restart(segment);
+                }
+            }
+
+            let bit = 1 << bit_plane;
+            match (pass, raw_decoder.as_mut().filter(|_| raw)) {
+                (CodingPass::SignificancePropagation, Some(raw_decoder)) => {
+                    self.significance_propagation_pass(raw_decoder, bit)
+                }
+                (CodingPass::SignificancePropagation, None) => {
+                    self.significance_propagation_pass(&mut decoder, bit)
+                }
+                (CodingPass::MagnitudeRefinement, Some(raw_decoder)) => {
+                    self.magnitude_refinement_pass(raw_decoder, bit)
+                }
+                (CodingPass::MagnitudeRefinement, None) => {
+                    self.magnitude_refinement_pass(&mut decoder, bit)
+                }
+                (CodingPass::Cleanup, _) => self.cleanup_pass(&mut decoder, bit),
+            }
+
+            self.no_bit_planes_decoded = top_bit_plane - bit_plane as u8 + 1;
+            self.no_passes_decoded += 1;
+
+            if pass == CodingPass::Cleanup && self.segmentation_symbols {
+                match (self.segmentation_symbol(&mut decoder), checkpoint.take()) {
+                    (Ok(()), Some(_)) => checkpoint = Some(self.checkpoint()),
+                    (Ok(()), None) => {}
+                    (Err(error), None) => return Err(error),
+                    (Err(_), Some(checkpoint)) => {
+                        self.restore(checkpoint);
+                        return Ok(());
+                    }
+                }
+            }
+
+            // The arithmetically coded segments which end before the last
+            // coding pass are known to be intact once they end as predicted
+            if !raw && terminated && index + 1 < no_passes && self.predictable_termination {
+                match checkpoint.take() {
+                    Some(_) if decoder.is_predictably_terminated() => {
+                        checkpoint = Some(self.checkpoint())
+                    }
+                    Some(checkpoint) => {
+                        self.restore(checkpoint);
+                        return Ok(());
+                    }
+                    None => {}
+                }
+            }
+
+            if self.reset_context_probabilities {
+                decoder.reset_contexts();
+            }
+        }
+
+        // The last coding pass is only known to end a codeword segment when it
+        // is terminated, or is the last of the code-block. The codeword
+        // segment may otherwise continue in the packets of later layers.
+        if let Some(checkpoint) = checkpoint {
+            let last = no_passes - 1;
+            let raw = is_raw(last, self.selective_arithmetic_coding_bypass);
+            let ends_segment = bit_plane == 0 && CodingPass::new(last) == CodingPass::Cleanup
+                || is_terminated(
+                    last,
+                    self.selective_arithmetic_coding_bypass,
+                    self.termination_on_each_coding_pass,
+                );
+            if self.predictable_termination
+                && !raw
+                && ends_segment
+                && !decoder.is_predictably_terminated()
+            {
+                self.restore(checkpoint);
+            }
+        }
+
+        Ok(())
+    }
+
+    // D.3.2 - Sign bit decoding
+    fn decode_sign<D: SymbolDecoder>(&mut self, decoder: &mut D, i: usize, causal: bool) {
+        let (context, xor) = self.sign_context(i, causal);
+        let sign = decoder.decode_symbol(CONTEXT_INDEX_SIGNIFICANCE + context) ^ xor;
+
+        self.flags[i] |= FLAG_SIGNIFICANT;
+        if sign == 1 {
+            self.flags[i] |= FLAG_NEGATIVE;
+        }
+    }
+
+    // D.3.1 - Significance propagation decoding pass
+    //
+    // Only coefficients that are not yet significant and have at least one
+    // significant neighbour are decoded in this pass.
+    fn significance_propagation_pass<D: SymbolDecoder>(&mut self, decoder: &mut D, bit: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & FLAG_SIGNIFICANT != 0 {
+                        continue;
+                    }
+
+                    let causal = self.is_causal(y);
+                    let context = self.significance_context(i, causal);
+                    if context == 0 {
+                        continue;
+                    }
+
+                    if decoder.decode_symbol(CONTEXT_INDEX_SIGNIFICANCE + context) == 1 {
+                        self.decode_sign(decoder, i, causal);
+                        self.magnitudes[i] |= bit;
+                    }
+                    self.flags[i] |= FLAG_VISITED;
+                }
+            }
+        }
+    }
+
+    // D.3.3 - Magnitude refinement pass
+    //
+    // Coefficients that became significant in a previous bit-plane are
+    // refined by one bit.
+    fn magnitude_refinement_pass<D: SymbolDecoder>(&mut self, decoder: &mut D, bit: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (FLAG_SIGNIFICANT | FLAG_VISITED) != FLAG_SIGNIFICANT {
+                        continue;
+                    }
+
+                    let context = self.refinement_context(i, self.is_causal(y));
+                    if decoder.decode_symbol(CONTEXT_INDEX_SIGNIFICANCE + context) == 1 {
+                        self.magnitudes[i] |= bit;
+                    }
+                    self.flags[i] |= FLAG_REFINED;
+                }
+            }
+        }
+    }
+
+    // D.3.4 - Cleanup pass
+    //
+    // Decodes every coefficient not decoded in the significance propagation
+    // pass, using run-length decoding for columns of a stripe without any
+    // significant neighbours.
+    fn cleanup_pass(&mut self, decoder: &mut MqDecoder, bit: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                let mut y = y0;
+
+                if y1 - y0 == STRIPE_HEIGHT && self.is_run_length_column(x, y0) {
+                    // A single symbol signals whether any of the four
+                    // coefficients becomes significant in this bit-plane.
+                    if decoder.decode(CONTEXT_INDEX_RUN_LENGTH) == 0 {
+                        continue;
+                    }
+
+                    // The position of the first significant coefficient is
+                    // decoded as two bits, most significant first.
+                    let position = (decoder.decode(CONTEXT_INDEX_UNIFORM) << 1)
+                        | decoder.decode(CONTEXT_INDEX_UNIFORM);
+                    y += position as usize;
+
+                    let i = self.index(x, y);
+                    self.decode_sign(decoder, i, self.is_causal(y));
+                    self.magnitudes[i] |= bit;
+                    y += 1;
+                }
+
+                for y in y..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (FLAG_SIGNIFICANT | FLAG_VISITED) == 0 {
+                        let causal = self.is_causal(y);
+                        let context = self.significance_context(i, causal);
+                        if decoder.decode(CONTEXT_INDEX_SIGNIFICANCE + context) == 1 {
+                            self.decode_sign(decoder, i, causal);
+                            self.magnitudes[i] |= bit;
+                        }
+                    }
+                }
+
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    self.flags[i] &= !FLAG_VISITED;
+                }
+            }
+        }
+    }
+
+    // D.5 - Error resilience segmentation symbol
+    fn segmentation_symbol(
+        &mut self,
+        decoder: &mut MqDecoder,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut symbol = 0;
+        for _ in 0..4 {
+            symbol = (symbol << 1) | decoder.decode(CONTEXT_INDEX_UNIFORM);
+        }
+
+        if symbol != SEGMENTATION_SYMBOL {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!("invalid segmentation symbol {:04b}", symbol),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl ContextModel for CodeBlockDecoder {
+    fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    fn orientation(&self) -> SubbandOrientation {
+        self.orientation
+    }
+
+    fn vertically_causal_context(&self) -> bool {
+        self.vertically_causal_context
+    }
+}
+
+// The significance propagation and magnitude refinement passes encode their
+// symbols with the MQ encoder in the context given, or as raw bits
+trait SymbolEncoder {
+    fn encode_symbol(&mut self, d: u8, context: usize);
+}
+
+impl SymbolEncoder for MqEncoder {
+    fn encode_symbol(&mut self, d: u8, context: usize) {
+        self.encode(d, context)
+    }
+}
+
+impl SymbolEncoder for RawEncoder {
+    fn encode_symbol(&mut self, d: u8, _context: usize) {
+        self.encode(d)
+    }
+}
+
+/// A coding pass of an encoded code-block
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedPass {
+    length: usize,
+    distortion: f64,
+    terminated: bool,
+}
+
+impl EncodedPass {
+    /// Number of bytes of the code-block data needed to decode the coding
+    /// passes up to and including this one, the truncation point of the
+    /// code-block after this coding pass.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Estimate of the reduction of the squared error of the quantized
+    /// coefficients by this coding pass, with the coefficients reconstructed
+    /// at the midpoint of their quantization interval.
+    ///
+    /// The reduction of the squared error of the image follows from
+    /// weighting it by the square of the step size of the subband and the
+    /// energy gain of its synthesis filters.
+    pub fn distortion(&self) -> f64 {
+        self.distortion
+    }
+
+    /// Whether the codeword segment ends with this coding pass.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// The coding passes of an encoded code-block, with the codeword segments of
+/// all its coding passes
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedCodeBlock {
+    data: Vec<u8>,
+    zero_bit_planes: u8,
+    passes: Vec<EncodedPass>,
+}
+
+impl EncodedCodeBlock {
+    /// The codeword segments, one after the other.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Number of missing most significant bit-planes (P), which is the number
+    /// of magnitude bits without any coding passes.
+    pub fn zero_bit_planes(&self) -> u8 {
+        self.zero_bit_planes
+    }
+
+    pub fn passes(&self) -> &[EncodedPass] {
+        &self.passes
+    }
+
+    pub fn no_passes(&self) -> u8 {
+        self.passes.len() as u8
+    }
+
+    /// The codeword segments of the first no_passes coding passes, as the
+    /// code-block decoder takes them. Asking for more coding passes than the
+    /// code-block has gives the segments of every coding pass.
+    pub fn segments(&self, no_passes: u8) -> Vec<&[u8]> {
+        let no_passes = no_passes.min(self.no_passes()) as usize;
+        let mut segments = vec![];
+        let mut start = 0;
+        for (index, pass) in self.passes[..no_passes].iter().enumerate() {
+            if pass.terminated || index + 1 == no_passes {
+                segments.push(&self.data[start..pass.length]);
+                start = pass.length;
+            }
+        }
+        segments
+    }
+}
+
+/// Code-block encoder for the coefficient bit modelling, see Annex D
+///
+/// Codes the quantized coefficients of a single code-block into the
+/// significance propagation, magnitude refinement and cleanup coding passes
+/// of each bit-plane, from the most significant bit-plane with a 1-bit
+/// downwards, which the code-block decoder decodes.
+#[derive(Debug)]
+pub struct CodeBlockEncoder {
+    width: usize,
+    height: usize,
+    orientation: SubbandOrientation,
+
+    selective_arithmetic_coding_bypass: bool,
+    reset_context_probabilities: bool,
+    termination_on_each_coding_pass: bool,
+    vertically_causal_context: bool,
+    predictable_termination: bool,
+    segmentation_symbols: bool,
+
+    // Coefficient state and magnitudes, with a border of one coefficient on
+    // every side like the decoder
+    stride: usize,
+    flags: Vec<u8>,
+    magnitudes: Vec<u32>,
+    negative: Vec<bool>,
+
+    // Distortion reduction of the coding pass being encoded
+    distortion: f64,
+}
+
+impl CodeBlockEncoder {
+    pub fn new(
+        width: u32,
+        height: u32,
+        orientation: SubbandOrientation,
+        code_block_style: u8,
+    ) -> CodeBlockEncoder {
+        let coding_block_styles = CodingBlockStyle::new(code_block_style);
+        let width = width as usize;
+        let height = height as usize;
+        let stride = width + 2;
+
+        CodeBlockEncoder {
+            width,
+            height,
+            orientation,
+            selective_arithmetic_coding_bypass: coding_block_styles
+                .contains(&CodingBlockStyle::SelectiveArithmeticCodingBypass),
+            reset_context_probabilities: coding_block_styles
+                .contains(&CodingBlockStyle::ResetContextProbabilities),
+            termination_on_each_coding_pass: coding_block_styles
+                .contains(&CodingBlockStyle::TerminationOnEachCodingPass),
+            vertically_causal_context: coding_block_styles
+                .contains(&CodingBlockStyle::VerticallyCausalContext),
+            predictable_termination: coding_block_styles
+                .contains(&CodingBlockStyle::PredictableTermination),
+            segmentation_symbols: coding_block_styles
+                .contains(&CodingBlockStyle::SegmentationSymbolsAreUsed),
+            stride,
+            flags: vec![0; stride * (height + 2)],
+            magnitudes: vec![0; stride * (height + 2)],
+            negative: vec![false; stride * (height + 2)],
+            distortion: 0.0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    /// Encode the coding passes of the code-block.
+    ///
+    /// The quantized coefficients are signed values in raster order, with
+    /// magnitudes of at most magnitude_bits bits (M_b), as the code-block
+    /// decoder reconstructs them. Every bit-plane below the most significant
+    /// one with a 1-bit is coded, and a code-block of zero coefficients has
+    /// no coding passes.
+    pub fn encode(
+        &mut self,
+        coefficients: &[i32],
+        magnitude_bits: u8,
+    ) -> Result<EncodedCodeBlock, Box<dyn error::Error>> {
+        if coefficients.len() != self.width * self.height {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!(
+                    "{} coefficients for a {}x{} code-block",
+                    coefficients.len(),
+                    self.width,
+                    self.height
+                ),
+            }
+            .into());
+        }
+        if magnitude_bits > 31 {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!("{} magnitude bits exceeds 31", magnitude_bits),
+            }
+            .into());
+        }
+
+        self.flags.iter_mut().for_each(|flag| *flag = 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coefficient = coefficients[y * self.width + x];
+                let i = self.index(x, y);
+                self.magnitudes[i] = coefficient.unsigned_abs();
+                self.negative[i] = coefficient < 0;
+            }
+        }
+
+        let maximum = coefficients
+            .iter()
+            .map(|coefficient| coefficient.unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        let no_bits = 32 - maximum.leading_zeros() as u8;
+        if no_bits > magnitude_bits {
+            return Err(CodestreamError::CodeBlockError {
+                error: format!(
+                    "magnitude {} exceeds {} magnitude bits",
+                    maximum, magnitude_bits
+                ),
+            }
+            .into());
+        }
+        if no_bits == 0 {
+            return Ok(EncodedCodeBlock {
+                data: vec![],
+                zero_bit_planes: magnitude_bits,
+                passes: vec![],
+            });
+        }
+
+        let termination = if self.predictable_termination {
+            Termination::Predictable
+        } else {
+            Termination::Easy
+        };
+
+        // A cleanup pass on the most significant bit-plane with a 1-bit is
+        // followed by the three coding passes of each bit-plane below it
+        let top_bit_plane = no_bits - 1;
+        let no_passes = 3 * top_bit_plane + 1;
+        let mut bit_plane = top_bit_plane as i32;
+
+        let mut data = vec![];
+        let mut passes: Vec<EncodedPass> = vec![];
+        let mut encoder = MqEncoder::new();
+        let mut raw_encoder = RawEncoder::new();
+        // Start of the codeword segment in data, and the first coding pass
+        // of it
+        let mut segment_start = 0;
+        let mut segment_pass = 0;
+
+        for index in 0..no_passes {
+            let pass = CodingPass::new(index);
+            if index > 0 && pass == CodingPass::SignificancePropagation {
+                bit_plane -= 1;
+            }
+
+            let raw = is_raw(index, self.selective_arithmetic_coding_bypass);
+            let terminated = index + 1 == no_passes
+                || is_terminated(
+                    index,
+                    self.selective_arithmetic_coding_bypass,
+                    self.termination_on_each_coding_pass,
+                );
+
+            self.distortion = 0.0;
+            let bit_plane = bit_plane as u32;
+            match (pass, raw) {
+                (CodingPass::SignificancePropagation, true) => {
+                    self.significance_propagation_pass(&mut raw_encoder, bit_plane)
+                }
+                (CodingPass::SignificancePropagation, false) => {
+                    self.significance_propagation_pass(&mut encoder, bit_plane)
+                }
+                (CodingPass::MagnitudeRefinement, true) => {
+                    self.magnitude_refinement_pass(&mut raw_encoder, bit_plane)
+                }
+                (CodingPass::MagnitudeRefinement, false) => {
+                    self.magnitude_refinement_pass(&mut encoder, bit_plane)
+                }
+                (CodingPass::Cleanup, _) => self.cleanup_pass(&mut encoder, bit_plane),
+            }
+
+            if pass == CodingPass::Cleanup && self.segmentation_symbols {
+                for i in (0..4).rev() {
+                    encoder.encode((SEGMENTATION_SYMBOL >> i) & 1, CONTEXT_INDEX_UNIFORM);
+                }
+            }
+
+            if terminated {
+                let segment = if raw {
+                    raw_encoder.flush(termination)
+                } else {
+                    encoder.flush(termination)
+                };
+                data.extend(segment);
+
+                // The coding passes before the end of the codeword segment
+                // are truncated within it, without ending on a 0xFF byte
+                for pass in passes[segment_pass..].iter_mut() {
+                    pass.length = cmp::min(pass.length, data.len());
+                    if pass.length > segment_start && data[pass.length - 1] == 0xFF {
+                        pass.length -= 1;
+                    }
+                }
+                segment_start = data.len();
+                segment_pass = index as usize + 1;
+            }
+
+            // Of the bytes of an MQ coded segment that is not terminated,
+            // the decisions of the coding passes so far need at most the
+            // bytes holding the C-register, which are the next four bytes
+            let length = if terminated {
+                data.len()
+            } else if raw {
+                segment_start + raw_encoder.no_bytes()
+            } else {
+                segment_start + encoder.no_bytes() + 4
+            };
+            passes.push(EncodedPass {
+                length,
+                distortion: self.distortion,
+                terminated,
+            });
+
+            if self.reset_context_probabilities {
+                encoder.reset_contexts();
+            }
+        }
+
+        Ok(EncodedCodeBlock {
+            data,
+            zero_bit_planes: magnitude_bits - no_bits,
+            passes,
+        })
+    }
+
+    // Squared error of a magnitude reconstructed from its bit-planes down to
+    // the given one, at the midpoint of the quantization interval once it is
+    // significant
+    fn squared_error(magnitude: u32, bit_plane: u32) -> f64 {
+        let truncated = (magnitude >> bit_plane) << bit_plane;
+        let reconstructed = if truncated == 0 {
+            0.0
+        } else if bit_plane == 0 {
+            truncated as f64
+        } else {
+            truncated as f64 + (1u32 << (bit_plane - 1)) as f64
+        };
+        let error = magnitude as f64 - reconstructed;
+        error * error
+    }
+
+    // The bit of the given bit-plane of a coefficient is coded, reducing its
+    // error
+    fn code_bit(&mut self, i: usize, bit_plane: u32) {
+        let magnitude = self.magnitudes[i];
+        self.distortion += Self::squared_error(magnitude, bit_plane + 1)
+            - Self::squared_error(magnitude, bit_plane);
+    }
+
+    // D.3.2 - Sign bit encoding
+    fn encode_sign<E: SymbolEncoder>(&mut self, encoder: &mut E, i: usize, causal: bool) {
+        let (context, xor) = self.sign_context(i, causal);
+        encoder.encode_symbol(
+            self.negative[i] as u8 ^ xor,
+            CONTEXT_INDEX_SIGNIFICANCE + context,
+        );
+
+        self.flags[i] |= FLAG_SIGNIFICANT;
+        if self.negative[i] {
+            self.flags[i] |= FLAG_NEGATIVE;
+        }
+    }
+
+    // D.3.1 - Significance propagation pass
+    fn significance_propagation_pass<E: SymbolEncoder>(&mut self, encoder: &mut E, bit_plane: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & FLAG_SIGNIFICANT != 0 {
+                        continue;
+                    }
+
+                    let causal = self.is_causal(y);
+                    let context = self.significance_context(i, causal);
+                    if context == 0 {
+                        continue;
+                    }
+
+                    let d = (self.magnitudes[i] >> bit_plane) as u8 & 1;
+                    encoder.encode_symbol(d, CONTEXT_INDEX_SIGNIFICANCE + context);
+                    if d == 1 {
+                        self.encode_sign(encoder, i, causal);
+                        self.code_bit(i, bit_plane);
+                    }
+                    self.flags[i] |= FLAG_VISITED;
+                }
+            }
+        }
+    }
+
+    // D.3.3 - Magnitude refinement pass
+    fn magnitude_refinement_pass<E: SymbolEncoder>(&mut self, encoder: &mut E, bit_plane: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (FLAG_SIGNIFICANT | FLAG_VISITED) != FLAG_SIGNIFICANT {
+                        continue;
+                    }
+
+                    let context = self.refinement_context(i, self.is_causal(y));
+                    let d = (self.magnitudes[i] >> bit_plane) as u8 & 1;
+                    encoder.encode_symbol(d, CONTEXT_INDEX_SIGNIFICANCE + context);
+                    self.code_bit(i, bit_plane);
+                    self.flags[i] |= FLAG_REFINED;
+                }
+            }
+        }
+    }
+
+    // D.3.4 - Cleanup pass
+    fn cleanup_pass(&mut self, encoder: &mut MqEncoder, bit_plane: u32) {
+        for y0 in (0..self.height).step_by(STRIPE_HEIGHT) {
+            let y1 = cmp::min(y0 + STRIPE_HEIGHT, self.height);
+            for x in 0..self.width {
+                let mut y = y0;
+
+                if y1 - y0 == STRIPE_HEIGHT && self.is_run_length_column(x, y0) {
+                    // The first coefficient of the column which becomes
+                    // significant, if any
+                    let position = (y0..y1)
+                        .position(|y| (self.magnitudes[self.index(x, y)] >> bit_plane) & 1 == 1);
+                    let position = match position {
+                        Some(position) => position,
+                        None => {
+                            encoder.encode(0, CONTEXT_INDEX_RUN_LENGTH);
+                            continue;
+                        }
+                    };
+
+                    encoder.encode(1, CONTEXT_INDEX_RUN_LENGTH);
+                    encoder.encode((position >> 1) as u8, CONTEXT_INDEX_UNIFORM);
+                    encoder.encode(position as u8 & 1, CONTEXT_INDEX_UNIFORM);
+                    y += position;
+
+                    let i = self.index(x, y);
+                    self.encode_sign(encoder, i, self.is_causal(y));
+                    self.code_bit(i, bit_plane);
+                    y += 1;
+                }
+
+                for y in y..y1 {
+                    let i = self.index(x, y);
+                    if self.flags[i] & (FLAG_SIGNIFICANT | FLAG_VISITED) == 0 {
+                        let causal = self.is_causal(y);
+                        let context = self.significance_context(i, causal);
+                        let d = (self.magnitudes[i] >> bit_plane) as u8 & 1;
+                        encoder.encode(d, CONTEXT_INDEX_SIGNIFICANCE + context);
+                        if d == 1 {
+                            self.encode_sign(encoder, i, causal);
+                            self.code_bit(i, bit_plane);
+                        }
+                    }
+                }
+
+                for y in y0..y1 {
+                    let i = self.index(x, y);
+                    self.flags[i] &= !FLAG_VISITED;
+                }
+            }
+        }
+    }
+}
+
+impl ContextModel for CodeBlockEncoder {
+    fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    fn orientation(&self) -> SubbandOrientation {
+        self.orientation
+    }
+
+    fn vertically_causal_context(&self) -> bool {
+        self.vertically_causal_context
+    }
+}
+
+// Table D.1 for the LL and LH subbands, the HL subband swaps the horizontal
+// and vertical contributions.
+fn significance_context_lh(h: u8, v: u8, d: u8) -> usize {
+    match (h, v, d) {
+        (2, _, _) => 8,
+        (1, 1.., _) => 7,
+        (1, 0, 1..) => 6,
+        (1, 0, 0) => 5,
+        (0, 2, _) => 4,
+        (0, 1, _) => 3,
+        (0, 0, 2..) => 2,
+        (0, 0, 1) => 1,
+        _ => 0,
+    }
+}
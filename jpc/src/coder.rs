@@ -1,10 +1,11 @@
-use log::info;
-use std::io::{self, Read};
+use std::cmp;
+use std::mem;
 
 type Register = u32;
-type Interval = u16;
+type Interval = u32;
 type Index = usize;
 
+// Table C.2 - Qe values and probability estimation
 const QE: [u16; 47] = [
     0x5601, 0x3401, 0x1801, 0x0ac1, 0x0521, 0x0221, 0x5601, 0x5401, 0x4801, 0x3801, 0x3001, 0x2401,
     0x1c01, 0x1601, 0x5601, 0x5401, 0x5101, 0x4801, 0x3801, 0x3401, 0x3001, 0x2801, 0x2401, 0x2201,
@@ -51,179 +52,641 @@ const CONTEXT_INITIAL: [u8; 19] = [
     0,
 ];
 
-fn print_c(n: u32) {
-    println!("high {:016b}, low {:016b}", c_high(n), c_low(n));
+/// Number of contexts used by the coefficient bit modelling, see Annex D.
+pub const NO_CONTEXTS: usize = CONTEXT_INITIAL.len();
+
+/// Index of the uniform context, which starts in state 46.
+pub const CONTEXT_INDEX_UNIFORM: usize = 0;
+
+/// Index of the run-length context, which starts in state 3.
+pub const CONTEXT_INDEX_RUN_LENGTH: usize = 1;
+
+/// Index of the first significance propagation context, the one used when
+/// all neighbours are insignificant, which starts in state 4. The remaining
+/// significance, sign and magnitude refinement contexts follow in order.
+pub const CONTEXT_INDEX_SIGNIFICANCE: usize = 2;
+
+// The state of each context CX, see C.2.5
+//
+// The index I(CX) points to the current estimate in the Qe table, and
+// MPS(CX) is the sense of the more probable symbol.
+#[derive(Debug, Clone, Copy, Default)]
+struct ContextState {
+    index: Index,
+    mps: u8,
 }
 
-fn c_high(n: u32) -> u16 {
-    (n >> 16) as u16
+fn c_high(n: Register) -> Interval {
+    n >> 16
 }
 
-fn c_low(n: u32) -> u16 {
-    (n << 16 >> 16) as u16
+/// MQ arithmetic decoder, see Annex C.3
+///
+/// Decodes a single codeword segment of compressed image data into decisions
+/// (D), with the probability of each decision estimated from the context (CX)
+/// it is decoded in. The state of every context is kept by the decoder and
+/// can be reset to the initial states from Table D.7.
+#[derive(Debug)]
+pub struct MqDecoder<'a> {
+    // BP is the buffer pointer, pointing into the compressed image data
+    data: &'a [u8],
+    bp: usize,
+
+    // C-register - the concatenation of the Chigh and Clow registers
+    //
+    // Chigh and Clow can be thought of as one 32 bit C-register in that
+    // renormalization of C shifts a bit of new data from the MSB of Clow
+    // to the LSB of Chigh.
+    c: Register,
+
+    // A - interval
+    //
+    // The interval A is kept in the range 0,75 ≤ A < 1,5 by doubling it
+    // whenever the integer value falls below 0x8000. 0x8000 is equivalent
+    // to decimal 0,75
+    a: Interval,
+
+    // CT - bit counter
+    ct: u32,
+
+    // Number of times 1-bits were fed to the decoder at a marker or past the
+    // end of the codeword segment
+    no_markers: u32,
+
+    contexts: [ContextState; NO_CONTEXTS],
 }
 
-// Inserting a new byte into the C register in the software-conventions decoder
-// Figure J-3
-fn bytein(
-    bp: &mut dyn Iterator<Item = Result<u8, io::Error>>,
-    c: &mut u32,
-    ct: &mut u32,
-) -> Result<u8, io::Error> {
-    let mut b = bp.next().unwrap()?;
-
-    // If B is a 0xFF byte, then B1 (the byte pointed to by BP+1) is tested
-    if b == 0xFF {
-        let b1 = bp.next().unwrap()?;
-
-        // If B1 exceeds 0x8F, then B1 must be one of the marker codes.
-        if b1 > 0x8F {
-            // The marker code is interpreted as required, and the buffer
-            // pointer remains pointed to the 0xFF prefix of the marker code
-            // which terminates the arithmetically compressed image data.
-
-            // 1-bits are then fed to the decoder until the decoding is
-            // complete. This is shown by adding 0xFF00 to the C-register
-            // and setting the bit counter CT to 8
-            *ct = 8;
-
-            todo!();
-        }
-        // If B1 is not a marker code, then BP is incremented to point
-        // to the next byte which contains a stuffed bit.
+impl<'a> MqDecoder<'a> {
+    /// Initialise the decoder (INITDEC) on a codeword segment, with all
+    /// contexts in their initial state.
+    pub fn new(data: &'a [u8]) -> MqDecoder<'a> {
+        let mut decoder = MqDecoder {
+            data,
+            bp: 0,
+            c: 0,
+            a: 0,
+            ct: 0,
+            no_markers: 0,
+            contexts: [ContextState::default(); NO_CONTEXTS],
+        };
+        decoder.reset_contexts();
+        decoder.initdec();
+        decoder
+    }
+
+    /// Reset every context to the initial state in Table D.7.
+    pub fn reset_contexts(&mut self) {
+        for (context, initial) in self.contexts.iter_mut().zip(CONTEXT_INITIAL) {
+            context.index = initial as Index;
+            context.mps = 0;
+        }
+    }
+
+    /// Restart decoding on a new codeword segment, keeping the state of the
+    /// contexts.
+    pub fn restart(&mut self, data: &'a [u8]) {
+        self.data = data;
+        self.bp = 0;
+        self.no_markers = 0;
+        self.initdec();
+    }
+
+    /// Number of bytes of the codeword segment consumed so far.
+    pub fn position(&self) -> usize {
+        self.bp
+    }
+
+    /// Whether the codeword segment ends where a predictable termination
+    /// would, after the last decision has been decoded, see D.4.2
+    ///
+    /// Predictable termination writes the bits of the C-register down to the
+    /// last byte the decoder needs, and leaves out a last 0xFF byte, so the
+    /// decoder has to have read every byte of the segment. The 1-bits fed
+    /// past its end stand for the bits which were not written: 8 to 15 of
+    /// them are shifted into Chigh, or 8 more for a 0xFF byte left out. C
+    /// is then below 2 to the power of the number of bits not written.
+    pub fn is_predictably_terminated(&self) -> bool {
+        if self.bp != self.data.len() {
+            return false;
+        }
+
+        // The byte at the end is read like any other, and each 0xFF byte
+        // after it like a marker
+        let no_bits = 8 + 8 * self.no_markers - self.ct;
+        (8..24).contains(&no_bits) && c_high(self.c) >> (8 + no_bits % 8) == 0
+    }
+
+    // B is the byte pointed to by the compressed image data buffer pointer,
+    // bytes past the end of the segment are read as 0xFF so that they are
+    // treated like a terminating marker.
+    fn byte(&self, offset: usize) -> u8 {
+        self.data.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    // Initialisation of the decoder, Figure C.19
+    fn initdec(&mut self) {
+        // The first byte of the compressed image data is shifted into the low
+        // order byte of Chigh, and a new byte is then read in.
+        self.c = (self.byte(self.bp) as Register) << 16;
+        self.bytein();
+        self.c <<= 7;
+        self.ct -= 7;
+        self.a = 0x8000;
+    }
+
+    // Inserting a new byte into the C register, Figure C.20
+    fn bytein(&mut self) {
+        // If B is a 0xFF byte, then B1 (the byte pointed to by BP+1) is tested
+        if self.byte(self.bp) == 0xFF {
+            // If B1 exceeds 0x8F, then B1 must be one of the marker codes.
+            if self.byte(self.bp + 1) > 0x8F {
+                // The marker code is interpreted as required, and the buffer
+                // pointer remains pointed to the 0xFF prefix of the marker
+                // code which terminates the arithmetically compressed image
+                // data.
+                //
+                // 1-bits are then fed to the decoder until the decoding is
+                // complete. This is shown by adding 0xFF00 to the C-register
+                // and setting the bit counter CT to 8
+                self.c += 0xFF00;
+                self.ct = 8;
+                self.no_markers += 1;
+            }
+            // If B1 is not a marker code, then BP is incremented to point
+            // to the next byte which contains a stuffed bit.
+            else {
+                self.bp += 1;
+                // The B is added to the C-register with an alignment such that
+                // the stuff bit (which contains any carry) is added to the low
+                // order bit of Chigh.
+                self.c += (self.byte(self.bp) as Register) << 9;
+                self.ct = 7;
+            }
+        }
+        // If B is not a 0xFF byte, BP is incremented and the new value of B
+        // is inserted into the high order 8 bits of Clow.
         else {
-            b = bp.next().unwrap()?;
-            // The B is added to the C-register with an alignment such that
-            // the stuff bit (which contains any carry) is added to the low
-            // order bit of Chigh.
-            // ORIGINAL: *c |= (b as u32) << 16;
-            print_c(*c);
-            *c = *c + 0xFF00 - ((b as u32) << 9);
-            print_c(*c);
-            *ct = 7;
-        }
-    }
-    // If B is not a 0xFF byte, BP is incremented and the new value of B
-    // is inserted into the << 7 high order 8 bits of Clow.
-    else {
-        //ORIGINAL: *c |= (b as u32) << 7;
-        *c = *c + 0xFF00 - ((b as u32) << 8);
-        *ct = 8;
-    }
-
-    Ok(b)
-}
+            self.bp += 1;
+            self.c += (self.byte(self.bp) as Register) << 8;
+            self.ct = 8;
+        }
+    }
+
+    // Renormalization in the decoder, Figure C.18
+    fn renormd(&mut self) {
+        loop {
+            if self.ct == 0 {
+                self.bytein();
+            }
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+
+    // Decoder MPS (Most Probable Symbol) path conditional exchange procedure,
+    // Figure C.17
+    fn mps_exchange(&mut self, cx: usize) -> u8 {
+        let context = &mut self.contexts[cx];
+        let qe = QE[context.index] as Interval;
+
+        if self.a < qe {
+            let d = 1 - context.mps;
+            if SWITCH_LM[context.index] == 1 {
+                context.mps = 1 - context.mps;
+            }
+            context.index = NEXT_LPS[context.index];
+            d
+        } else {
+            context.index = NEXT_MPS[context.index];
+            context.mps
+        }
+    }
 
-// Decoder LPS (Least Probable Symbol) path conditional exchange procedure
-fn lps_exchange(a: &mut Interval, i: &mut Index, _d: &mut u8) -> Result<(), io::Error> {
-    info!("LPS exchange");
-    if *a < QE[*i] {
-        *a = QE[*i];
-        //*d = MPS(CX);
-        *i = NEXT_MPS[*i];
+    // Decoder LPS (Least Probable Symbol) path conditional exchange procedure,
+    // Figure C.16
+    fn lps_exchange(&mut self, cx: usize) -> u8 {
+        let context = &mut self.contexts[cx];
+        let qe = QE[context.index] as Interval;
+
+        if self.a < qe {
+            self.a = qe;
+            context.index = NEXT_MPS[context.index];
+            context.mps
+        } else {
+            self.a = qe;
+            let d = 1 - context.mps;
+            if SWITCH_LM[context.index] == 1 {
+                context.mps = 1 - context.mps;
+            }
+            context.index = NEXT_LPS[context.index];
+            d
+        }
+    }
+
+    /// Decode a single decision (D) in the context CX, see Figure C.15
+    pub fn decode(&mut self, cx: usize) -> u8 {
+        // sub-interval for the MPS = A - (Qe * A)
+        // sub-interval for the LPS = Qe * A
+        let qe = QE[self.contexts[cx].index] as Interval;
+        self.a -= qe;
+
+        if c_high(self.c) < qe {
+            let d = self.lps_exchange(cx);
+            self.renormd();
+            d
+        } else {
+            self.c -= qe << 16;
+            if self.a & 0x8000 == 0 {
+                let d = self.mps_exchange(cx);
+                self.renormd();
+                d
+            } else {
+                self.contexts[cx].mps
+            }
+        }
     }
-    todo!();
 }
 
-// Decoder MPS (Most Probable Symbol) path conditional exchange procedure
-fn mps_exchange(_a: &mut Interval, _i: &mut Index, _d: &mut u8) -> Result<(), io::Error> {
-    info!("MPS exchange");
-    todo!();
+/// How the MQ encoder terminates a codeword segment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Termination {
+    // The FLUSH procedure of Figure C.11, which sets as many of the last bits
+    // of the C-register to 1 as the interval allows
+    Easy,
+
+    // Predictable termination, see D.4.2, which lets the decoder check the
+    // segment ends where it should to detect errors
+    Predictable,
 }
 
-fn renormd() -> Result<(), io::Error> {
-    todo!();
+/// MQ arithmetic encoder, see Annex C.2
+///
+/// Encodes decisions (D) in contexts (CX) into codeword segments, with the
+/// probability of each decision estimated from the state of its context the
+/// same way the decoder does. The contexts start in the initial states from
+/// Table D.7 and are kept from one codeword segment to the next.
+#[derive(Debug)]
+pub struct MqEncoder {
+    // The codeword segment so far, where the first byte is the one before the
+    // first byte of the segment, at BPST - 1, and the last byte is B, the byte
+    // pointed to by BP, which is still being written to
+    data: Vec<u8>,
+
+    // C-register - the code register, with the 8 bit "spacer" and carry bit
+    // above the 19 bits of the fractional part
+    c: Register,
+
+    // A - interval, kept in the range 0,75 ≤ A < 1,5 like the decoder
+    a: Interval,
+
+    // CT - the number of shifts until a byte is removed from the C-register
+    ct: u32,
+
+    contexts: [ContextState; NO_CONTEXTS],
 }
 
-pub fn decode<R: io::Read>(reader: &mut R) -> Result<(), io::Error> {
-    // LPS - less probable symbol
-    // MPS - more probable symbol
+impl Default for MqEncoder {
+    fn default() -> Self {
+        MqEncoder::new()
+    }
+}
 
-    // The coding operations are done using fixed precision integer arithmetic and using an integer representation of fractional values in which 0x8000 is equivalent to decimal 0,75.
-    //
+impl MqEncoder {
+    /// Initialise the encoder (INITENC), with all contexts in their initial
+    /// state.
+    pub fn new() -> MqEncoder {
+        let mut encoder = MqEncoder {
+            data: vec![],
+            c: 0,
+            a: 0,
+            ct: 0,
+            contexts: [ContextState::default(); NO_CONTEXTS],
+        };
+        encoder.reset_contexts();
+        encoder.initenc();
+        encoder
+    }
 
-    // Carry-over into the external buffer is prevented by a bit stuffing procedure.
+    /// Reset every context to the initial state in Table D.7.
+    pub fn reset_contexts(&mut self) {
+        for (context, initial) in self.contexts.iter_mut().zip(CONTEXT_INITIAL) {
+            context.index = initial as Index;
+            context.mps = 0;
+        }
+    }
 
-    //
-    // Qe - current estimate of the LPS probability
-    // CD - compressed image data
-    // D - decision
-    // CX - context
-    //
-    // sub-interval for the MPS = A - (Qe * A)
-    // sub-interval for the LPS = Qe * A
+    /// Number of bytes of the codeword segment written so far, not counting
+    /// the bytes still held in the C-register.
+    pub fn no_bytes(&self) -> usize {
+        self.data.len() - 1
+    }
 
-    // TODO: Consider using struct for C-register
+    // Initialisation of the encoder, Figure C.10
+    fn initenc(&mut self) {
+        self.data.clear();
+        self.data.push(0);
+        self.a = 0x8000;
+        self.c = 0;
+        self.ct = 12;
+    }
 
-    // The code register is also doubled each time A is doubled.
-    // Periodically – to keep C from overflowing – a byte of compressed
-    // image data is removed from the high order bits of the C-register and
-    // placed in an external compressed imagedata buffer.
+    /// Encode a single decision (D) in the context CX, see Figure C.3
+    pub fn encode(&mut self, d: u8, cx: usize) {
+        if d == self.contexts[cx].mps {
+            self.codemps(cx);
+        } else {
+            self.codelps(cx);
+        }
+    }
 
-    // decoding = CD + CX = D
-    // encoding = CX + D = CD
+    // Encoding an LPS (Least Probable Symbol), Figure C.5
+    fn codelps(&mut self, cx: usize) {
+        let context = &mut self.contexts[cx];
+        let qe = QE[context.index] as Interval;
 
-    // I = index
-    // The index to the current estimate is part of the information stored
-    // for context CX. This index is used as the index to the table of
-    // values in NMPS, which gives the next index for an MPS renormalizatio
-    let mut i: Index = 0;
+        self.a -= qe;
+        if self.a < qe {
+            self.c += qe;
+        } else {
+            self.a = qe;
+        }
+        if SWITCH_LM[context.index] == 1 {
+            context.mps = 1 - context.mps;
+        }
+        context.index = NEXT_LPS[context.index];
+        self.renorme();
+    }
 
-    // C-register - the concatenation of the Chigh and Clow registers
-    //
-    // Chigh and Clow can be thought of as one 32 bit C-register in that
-    // renormalization of C shifts a bit of new data from the MSB of Clow
-    // to the LSB of Chigh.
+    // Encoding an MPS (Most Probable Symbol), Figure C.6
+    fn codemps(&mut self, cx: usize) {
+        let context = &mut self.contexts[cx];
+        let qe = QE[context.index] as Interval;
+
+        self.a -= qe;
+        if self.a & 0x8000 == 0 {
+            if self.a < qe {
+                self.a = qe;
+            } else {
+                self.c += qe;
+            }
+            context.index = NEXT_MPS[context.index];
+            self.renorme();
+        } else {
+            self.c += qe;
+        }
+    }
+
+    // Renormalization in the encoder, Figure C.7
+    fn renorme(&mut self) {
+        loop {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byteout();
+            }
+
+            if self.a & 0x8000 != 0 {
+                break;
+            }
+        }
+    }
+
+    // B is the last byte written, which a carry from the C-register is added
+    // to
+    fn b(&mut self) -> &mut u8 {
+        self.data.last_mut().unwrap()
+    }
+
+    // Removing a byte of compressed data from the C-register, Figure C.8
     //
-    // Bits are packed into bytes from the MSB to the LSB.
-    let mut c: Register = 0;
-    let mut _c_high: [u8; 2] = [0; 2];
-    let mut _c_low: [u8; 2] = [0; 2];
+    // A 0xFF byte is followed by a byte with a stuffed zero bit in its MSB,
+    // which takes any carry, so that no carry propagates past a 0xFF byte
+    // and no marker code is formed.
+    fn byteout(&mut self) {
+        if *self.b() == 0xFF {
+            self.stuffed_byteout();
+        } else if self.c < 0x800_0000 {
+            self.data.push((self.c >> 19) as u8);
+            self.c &= 0x7_FFFF;
+            self.ct = 8;
+        } else {
+            // Propagate the carry to B
+            *self.b() += 1;
+            if *self.b() == 0xFF {
+                self.c &= 0x7FF_FFFF;
+                self.stuffed_byteout();
+            } else {
+                self.data.push((self.c >> 19) as u8);
+                self.c &= 0x7_FFFF;
+                self.ct = 8;
+            }
+        }
+    }
 
-    // CT - bit counter
-    let mut _ct: u32 = 0;
+    fn stuffed_byteout(&mut self) {
+        self.data.push((self.c >> 20) as u8);
+        self.c &= 0xF_FFFF;
+        self.ct = 7;
+    }
 
-    // A - interval
-    // The interval A is kept in the range 0,75 ≤ A < 1,5 by doubling it
-    // whenever the integer value falls below 0x8000. 0x8000 is equivalent
-    // to decimal 0,75
-    let mut _a: Interval = 0;
+    // Setting the final bits in the C-register, Figure C.12
+    fn setbits(&mut self) {
+        let temp = self.c + self.a;
+        self.c |= 0xFFFF;
+        if self.c >= temp {
+            self.c -= 0x8000;
+        }
+    }
+
+    /// Terminate the codeword segment and return it, restarting the encoder
+    /// on a new codeword segment which keeps the state of the contexts.
+    pub fn flush(&mut self, termination: Termination) -> Vec<u8> {
+        match termination {
+            // FLUSH, Figure C.11
+            Termination::Easy => {
+                self.setbits();
+                self.c <<= self.ct;
+                self.byteout();
+                self.c <<= self.ct;
+                self.byteout();
+
+                // A last 0xFF byte is left out
+                if *self.b() == 0xFF {
+                    self.data.pop();
+                }
+            }
+            // D.4.2 - Only the bits needed to decode the decisions so far are
+            // written, without the 1-bits the decoder is fed past the end of
+            // the segment
+            Termination::Predictable => {
+                let mut k = 11 - self.ct as i32 + 1;
+                while k > 0 {
+                    self.c <<= self.ct;
+                    self.ct = 0;
+                    self.byteout();
+                    k -= self.ct as i32;
+                }
+
+                // B is kept unless it is 0xFF, after adding any carry to it
+                if *self.b() != 0xFF {
+                    self.byteout();
+                }
+                self.data.pop();
+            }
+        }
+
+        let mut data = mem::take(&mut self.data);
+        data.remove(0);
+        self.initenc();
+        data
+    }
+}
+
+/// Raw decoder for the coding passes coded without the arithmetic coder when
+/// selective arithmetic coding bypass is used, see D.6
+///
+/// Bits are read from the MSB to the LSB of each byte. A byte following a
+/// 0xFF byte holds only 7 bits, after a stuffed zero bit in its MSB. Past the
+/// end of the codeword segment, or at a marker, 1-bits are fed instead.
+#[derive(Debug)]
+pub struct RawDecoder<'a> {
+    data: &'a [u8],
+    bp: usize,
+
+    // The byte currently being read from
+    c: u8,
+
+    // CT - number of bits of C left to read
+    ct: u32,
+}
+
+impl<'a> RawDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> RawDecoder<'a> {
+        RawDecoder {
+            data,
+            bp: 0,
+            c: 0,
+            ct: 0,
+        }
+    }
+
+    /// Number of bytes of the codeword segment consumed so far.
+    pub fn position(&self) -> usize {
+        self.bp
+    }
+
+    /// Decode a single raw bit.
+    pub fn decode(&mut self) -> u8 {
+        if self.ct == 0 {
+            let byte = self.data.get(self.bp).copied().unwrap_or(0xFF);
+            if self.c == 0xFF {
+                if byte > 0x8F {
+                    self.ct = 8;
+                } else {
+                    self.c = byte;
+                    self.bp += 1;
+                    self.ct = 7;
+                }
+            } else {
+                self.c = byte;
+                self.bp = cmp::min(self.bp + 1, self.data.len());
+                self.ct = 8;
+            }
+        }
 
-    // INITDEC
+        self.ct -= 1;
+        (self.c >> self.ct) & 1
+    }
+}
 
-    // BP is the buffer pointer
-    // BPST is pointing to the first compressed byte
-    let mut bp = reader.bytes();
+/// Raw encoder for the coding passes coded without the arithmetic coder when
+/// selective arithmetic coding bypass is used, see D.6
+///
+/// Bits are written from the MSB to the LSB of each byte, with a zero bit
+/// stuffed in the MSB of the byte following a 0xFF byte, like the raw decoder
+/// reads them.
+#[derive(Debug)]
+pub struct RawEncoder {
+    data: Vec<u8>,
 
-    // The first byte of the compressed image data is shifted into the low
-    // order byte of Chigh, and a new byte is then read in.
-    let mut _b = bp.next().unwrap()?;
-    c |= (_b as u32) << 16;
+    // The byte currently being written to
+    c: u8,
 
-    // B is the byte pointed to by the compressed image data buffer pointer
-    bytein(&mut bp, &mut c, &mut _ct)?;
+    // CT - number of bits left to write to C
+    ct: u32,
+
+    // Number of bits C holds, 7 after a 0xFF byte
+    capacity: u32,
+}
 
-    c <<= 7;
-    _ct -= 7;
-    _a = 0x8000;
+impl Default for RawEncoder {
+    fn default() -> Self {
+        RawEncoder::new()
+    }
+}
 
-    let mut d: u8 = 0;
+impl RawEncoder {
+    pub fn new() -> RawEncoder {
+        RawEncoder {
+            data: vec![],
+            c: 0,
+            ct: 8,
+            capacity: 8,
+        }
+    }
 
-    // Decode
-    print_c(c);
-    _a -= QE[i];
-    if c_high(c) < _a {
-        if _a & 0x8000 > 0 {
-            mps_exchange(&mut _a, &mut i, &mut d)?;
-            renormd()?;
+    /// Number of bytes of the codeword segment written so far, including the
+    /// byte currently being written to.
+    pub fn no_bytes(&self) -> usize {
+        if self.ct < self.capacity {
+            self.data.len() + 1
         } else {
-            //D = MPS(cx);
-            todo!();
+            self.data.len()
         }
-    } else {
-        lps_exchange(&mut _a, &mut i, &mut d)?;
-        renormd()?;
     }
 
-    Ok(())
+    /// Encode a single raw bit.
+    pub fn encode(&mut self, d: u8) {
+        self.c = (self.c << 1) | (d & 1);
+        self.ct -= 1;
+        if self.ct == 0 {
+            self.data.push(self.c);
+            self.capacity = if self.c == 0xFF { 7 } else { 8 };
+            self.ct = self.capacity;
+            self.c = 0;
+        }
+    }
+
+    /// Terminate the codeword segment and return it, restarting the encoder
+    /// on a new codeword segment.
+    ///
+    /// The last byte is padded with 1-bits, or with alternating 0 and 1 bits
+    /// with predictable termination. A last 0xFF byte is left out, as the
+    /// decoder is fed 1-bits past the end of the codeword segment.
+    pub fn flush(&mut self, termination: Termination) -> Vec<u8> {
+        let mut padding = 0;
+        while self.ct < self.capacity {
+            match termination {
+                Termination::Easy => self.encode(1),
+                Termination::Predictable => {
+                    self.encode(padding);
+                    padding ^= 1;
+                }
+            }
+        }
+
+        if self.data.last() == Some(&0xFF) {
+            self.data.pop();
+        }
+
+        let data = mem::take(&mut self.data);
+        *self = RawEncoder::new();
+        data
+    }
 }
@@ -0,0 +1,1211 @@
+use std::error;
+use std::ops;
+
+use super::code_block::CodeBlockDecoder;
+use super::geometry::{self, Rectangle};
+use super::packet::Precinct;
+use super::progression::{self, Progression, ResolutionPrecincts, TileComponentPrecincts};
+use super::quantization::{self, Quantization};
+use super::region_of_interest;
+use super::registration;
+use super::transformation;
+use super::wavelet;
+use super::{
+    CodestreamError, CodingStyleDefault, CodingStyleMarkerSegment, CodingStyleParameters,
+    Component, ConcealedCodeBlock, ContiguousCodestream, DecodeOptions, Header, Image, ImageArea,
+    MultipleComponentTransformation, RegionOfInterestStyle, SubbandOrientation, Tile,
+    TransformationFilter, MARKER_SYMBOL_SOP,
+};
+
+// Size of the SOP marker segment, including the marker
+const SOP_LENGTH: usize = 6;
+
+// The compressed data of a code-block gathered from the packets of a tile
+#[derive(Debug)]
+struct CodeBlock {
+    // Position of the code-block in its subband
+    region: Rectangle,
+
+    // Codeword segments, joining the contributions of the packets that
+    // continue the same segment
+    segments: Vec<Vec<u8>>,
+    no_passes: u8,
+    zero_bit_planes: u8,
+}
+
+#[derive(Debug)]
+struct PrecinctState {
+    precinct: Precinct,
+
+    // Code-blocks of each subband which are in the precinct, in raster order
+    code_blocks: Vec<Vec<CodeBlock>>,
+
+    // A packet of the precinct was lost, the headers of its later packets
+    // cannot be decoded without it
+    corrupt: bool,
+}
+
+#[derive(Debug)]
+struct Subband {
+    orientation: SubbandOrientation,
+    region: Rectangle,
+
+    // Position of the subband in the tile-component coefficients, with the
+    // subbands of each decomposition level arranged as expected by the
+    // inverse wavelet transformation
+    x_offset: u32,
+    y_offset: u32,
+}
+
+#[derive(Debug)]
+struct Resolution {
+    region: Rectangle,
+
+    // Part of the subbands needed to reconstruct the window being decoded
+    window: Rectangle,
+
+    // Precinct width and height exponents (PPx, PPy)
+    ppx: u8,
+    ppy: u8,
+
+    subbands: Vec<Subband>,
+    precincts: Vec<PrecinctState>,
+}
+
+#[derive(Debug)]
+struct TileComponent<'a> {
+    // Indexes of the tile and the component
+    tile: u16,
+    component: u16,
+
+    region: Rectangle,
+    precision: u8,
+    coding_style_parameters: &'a CodingStyleParameters,
+    quantization: Quantization,
+    resolutions: Vec<Resolution>,
+
+    // ROI shift (s) of the Maxshift method, 0 without a region of interest
+    region_of_interest_shift: u8,
+
+    // Number of highest resolution levels which are discarded
+    reduce: u8,
+}
+
+impl TileComponent<'_> {
+    // Number of resolution levels which are decoded
+    fn no_resolutions(&self) -> usize {
+        self.resolutions.len() - self.reduce as usize
+    }
+
+    // The tile-component at the highest resolution level which is decoded
+    fn decoded_region(&self) -> Rectangle {
+        self.resolutions[self.no_resolutions() - 1].region
+    }
+}
+
+// The samples of each tile-component of a tile, with the region of the
+// component they belong to
+type TileSamples = Vec<(Rectangle, Vec<i32>)>;
+
+// Reconstructed tile-component samples, integers after the reversible
+// transformation and real numbers after the irreversible transformation.
+enum Samples {
+    Reversible(Vec<i32>),
+    Irreversible(Vec<f32>),
+}
+
+/// Decode the image from the headers and tile data of a codestream, see
+/// Figure 1
+pub(crate) fn decode(
+    codestream: &ContiguousCodestream,
+    options: &DecodeOptions,
+) -> Result<Image, Box<dyn error::Error>> {
+    let header = &codestream.header;
+    let siz = header.image_and_tile_size_marker_segment();
+
+    let image = Rectangle::new(
+        siz.image_horizontal_offset(),
+        siz.image_vertical_offset(),
+        siz.reference_grid_width(),
+        siz.reference_grid_height(),
+    );
+
+    // The window of the image to decode
+    let window = match &options.area {
+        Some(area) => {
+            image.intersection(&Rectangle::new(area.x0(), area.y0(), area.x1(), area.y1()))
+        }
+        None => image,
+    };
+    if window.is_empty() {
+        return Err(CodestreamError::DecodeError {
+            error: format!("area {:?} does not intersect the image area", options.area),
+        }
+        .into());
+    }
+
+    // The window and components at the lower resolution, when discarding the
+    // highest resolution levels
+    let reduced = geometry::reduce(&window, options.reduce);
+    let area = ImageArea::new(reduced.x0(), reduced.y0(), reduced.x1(), reduced.y1());
+
+    let mut components = Vec::with_capacity(siz.no_components() as usize);
+    for c in 0..siz.no_components() as usize {
+        let horizontal_separation = siz.horizontal_separation(c)?;
+        let vertical_separation = siz.vertical_separation(c)?;
+        let region = geometry::reduce(
+            &geometry::tile_component(&window, horizontal_separation, vertical_separation),
+            options.reduce,
+        );
+
+        components.push(Component {
+            x0: region.x0(),
+            y0: region.y0(),
+            width: region.width(),
+            height: region.height(),
+            precision: siz.precision(c)? as u8,
+            signed: siz.values_are_signed(c)?,
+            horizontal_separation,
+            vertical_separation,
+            samples: vec![0; region.width() as usize * region.height() as usize],
+        });
+    }
+
+    // Tiles outside of the window are not decoded at all
+    let tiles: Vec<&Tile> = codestream
+        .tiles
+        .iter()
+        .filter(|tile| {
+            let region = geometry::tile(siz, tile.index as u32);
+            !region.intersection(&window).is_empty()
+        })
+        .collect();
+
+    let mut concealed_code_blocks = vec![];
+    for_each_in_order(
+        &tiles,
+        |tile| {
+            let mut concealed_code_blocks = vec![];
+            let samples = decode_tile(header, tile, &window, options, &mut concealed_code_blocks)
+                .map_err(send_error)?;
+            Ok((samples, concealed_code_blocks))
+        },
+        |_, (samples, concealed)| {
+            for (component, (region, samples)) in components.iter_mut().zip(samples) {
+                copy_samples(component, &region, &samples);
+            }
+            concealed_code_blocks.extend(concealed);
+        },
+    )?;
+
+    if options.upsample {
+        let crg = header.component_registration_segment();
+        for (c, component) in components.iter_mut().enumerate() {
+            let offset = match crg {
+                Some(crg) => (crg.horizontal_offset(c), crg.vertical_offset(c)),
+                None => (0, 0),
+            };
+            upsample(component, &reduced, offset);
+        }
+    }
+
+    Ok(Image {
+        area,
+        components,
+        concealed_code_blocks,
+    })
+}
+
+// B.2 - Upsample a component to every point of the image area on the
+// reference grid, with its component registration offsets
+fn upsample(component: &mut Component, area: &Rectangle, offset: (u16, u16)) {
+    let separation = (
+        component.horizontal_separation,
+        component.vertical_separation,
+    );
+    if separation == (1, 1) && offset == (0, 0) {
+        return;
+    }
+
+    let region = Rectangle::new(
+        component.x0,
+        component.y0,
+        component.x0 + component.width,
+        component.y0 + component.height,
+    );
+    component.samples =
+        registration::upsample(&component.samples, &region, separation, offset, area);
+    component.x0 = area.x0();
+    component.y0 = area.y0();
+    component.width = area.width();
+    component.height = area.height();
+    component.horizontal_separation = 1;
+    component.vertical_separation = 1;
+}
+
+// Copy the samples of a tile-component which are in the window of a component
+fn copy_samples(component: &mut Component, region: &Rectangle, samples: &[i32]) {
+    let target = Rectangle::new(
+        component.x0,
+        component.y0,
+        component.x0 + component.width,
+        component.y0 + component.height,
+    );
+    let visible = region.intersection(&target);
+    let width = visible.width() as usize;
+
+    for y in visible.y0()..visible.y1() {
+        let from = ((y - region.y0()) * region.width() + visible.x0() - region.x0()) as usize;
+        let to = ((y - target.y0()) * target.width() + visible.x0() - target.x0()) as usize;
+        component.samples[to..to + width].copy_from_slice(&samples[from..from + width]);
+    }
+}
+
+// Decode the tile-components of a tile into component samples.
+fn decode_tile(
+    header: &Header,
+    tile: &Tile,
+    window: &Rectangle,
+    options: &DecodeOptions,
+    concealed_code_blocks: &mut Vec<ConcealedCodeBlock>,
+) -> Result<TileSamples, Box<dyn error::Error>> {
+    let siz = header.image_and_tile_size_marker_segment();
+    let cod = coding_style(header, tile);
+    let region = geometry::tile(siz, tile.index as u32);
+
+    let mut tile_components = Vec::with_capacity(siz.no_components() as usize);
+    for c in 0..siz.no_components() {
+        let horizontal_separation = siz.horizontal_separation(c as usize)?;
+        let vertical_separation = siz.vertical_separation(c as usize)?;
+        let tile_component =
+            geometry::tile_component(&region, horizontal_separation, vertical_separation);
+        let mut tile_component =
+            new_tile_component(header, tile, c, tile_component, options.reduce)?;
+
+        let window = geometry::tile_component(window, horizontal_separation, vertical_separation);
+        set_windows(&mut tile_component, &window);
+        tile_components.push(tile_component);
+    }
+
+    let max_layers = options.max_layers.unwrap_or(u16::MAX);
+    decode_packets(header, tile, &region, &mut tile_components, max_layers)?;
+
+    let mut samples = Vec::with_capacity(tile_components.len());
+    for tile_component in &tile_components {
+        samples.push(decode_tile_component(
+            tile_component,
+            options,
+            concealed_code_blocks,
+        )?);
+    }
+
+    // G.2 and G.3 - Inverse multiple component transformation of the first
+    // three components
+    if cod.multiple_component_transformation() == MultipleComponentTransformation::Multiple {
+        if tile_components.len() < 3
+            || tile_components[1].decoded_region() != tile_components[0].decoded_region()
+            || tile_components[2].decoded_region() != tile_components[0].decoded_region()
+        {
+            return Err(CodestreamError::DecodeError {
+                error: "multiple component transformation needs three components of the same size"
+                    .to_string(),
+            }
+            .into());
+        }
+
+        match &mut samples[..] {
+            [Samples::Reversible(c0), Samples::Reversible(c1), Samples::Reversible(c2), ..] => {
+                transformation::inverse_reversible(c0, c1, c2)
+            }
+            [Samples::Irreversible(c0), Samples::Irreversible(c1), Samples::Irreversible(c2), ..] => {
+                transformation::inverse_irreversible(c0, c1, c2)
+            }
+            _ => {
+                return Err(CodestreamError::DecodeError {
+                    error:
+                        "multiple component transformation needs the same filter for each component"
+                            .to_string(),
+                }
+                .into());
+            }
+        }
+    }
+
+    // G.1.2 - Inverse DC level shifting
+    let mut result = Vec::with_capacity(samples.len());
+    for (c, (tile_component, samples)) in tile_components.iter().zip(samples).enumerate() {
+        let precision = tile_component.precision;
+        let signed = siz.values_are_signed(c)?;
+
+        let samples = match samples {
+            Samples::Reversible(mut samples) => {
+                transformation::inverse_dc_level_shift(&mut samples, precision, signed);
+                samples
+            }
+            Samples::Irreversible(samples) => {
+                transformation::inverse_dc_level_shift_irreversible(&samples, precision, signed)
+            }
+        };
+        result.push((tile_component.decoded_region(), samples));
+    }
+
+    Ok(result)
+}
+
+// A.6.1 - The COD marker segment of the first tile-part header of a tile
+// overrides the one of the main header.
+fn coding_style<'a>(header: &'a Header, tile: &'a Tile) -> &'a CodingStyleMarkerSegment {
+    tile.header
+        .coding_style_marker_segment
+        .as_ref()
+        .unwrap_or_else(|| header.coding_style_marker_segment())
+}
+
+// A.6.2 - The coding style of a component, in the order of precedence tile
+// COC, tile COD, main COC and main COD.
+fn coding_style_parameters<'a>(
+    header: &'a Header,
+    tile: &'a Tile,
+    component: u16,
+) -> &'a CodingStyleParameters {
+    if let Some(coc) = tile
+        .header
+        .coding_style_component_segments
+        .iter()
+        .find(|coc| coc.component_index() == component)
+    {
+        return coc.coding_style_parameters();
+    }
+    if let Some(cod) = &tile.header.coding_style_marker_segment {
+        return cod.coding_style_parameters();
+    }
+    if let Some(coc) = header
+        .coding_style_component_segment()
+        .iter()
+        .find(|coc| coc.component_index() == component)
+    {
+        return coc.coding_style_parameters();
+    }
+    header
+        .coding_style_marker_segment()
+        .coding_style_parameters()
+}
+
+// A.6.5 - The quantization of a component, in the order of precedence tile
+// QCC, tile QCD, main QCC and main QCD.
+fn quantization(
+    header: &Header,
+    tile: &Tile,
+    component: u16,
+    no_decomposition_levels: u8,
+) -> Result<Quantization, Box<dyn error::Error>> {
+    let tile_qcc = tile
+        .header
+        .quantization_component_segments
+        .iter()
+        .find(|qcc| qcc.component_index() == component);
+    let main_qcc = header
+        .quantization_component_segments()
+        .iter()
+        .find(|qcc| qcc.component_index() == component);
+
+    let (style, exponents, mantissas) = if let Some(qcc) = tile_qcc {
+        (
+            qcc.quantization_style(),
+            qcc.quantization_exponents(),
+            qcc.quantization_mantissas(),
+        )
+    } else if let Some(qcd) = &tile.header.quantization_default_marker_segment {
+        (
+            qcd.quantization_style(),
+            qcd.quantization_exponents(),
+            qcd.quantization_mantissas(),
+        )
+    } else if let Some(qcc) = main_qcc {
+        (
+            qcc.quantization_style(),
+            qcc.quantization_exponents(),
+            qcc.quantization_mantissas(),
+        )
+    } else {
+        let qcd = header.quantization_default_marker_segment();
+        (
+            qcd.quantization_style(),
+            qcd.quantization_exponents(),
+            qcd.quantization_mantissas(),
+        )
+    };
+
+    Quantization::new(style, &exponents, &mantissas, no_decomposition_levels)
+}
+
+// A.6.3 - The ROI shift of a component, where the RGN marker segment of the
+// first tile-part header overrides the one of the main header.
+fn region_of_interest_shift(
+    header: &Header,
+    tile: &Tile,
+    component: u16,
+) -> Result<u8, Box<dyn error::Error>> {
+    let rgn = tile
+        .header
+        .regions
+        .iter()
+        .chain(header.region_of_interest_segments())
+        .find(|rgn| rgn.component_index() == component);
+
+    match rgn {
+        None => Ok(0),
+        Some(rgn) => match rgn.region_of_interest_style() {
+            RegionOfInterestStyle::ImplicitRegionOfInterest => Ok(rgn.region_of_interest_shift()),
+            RegionOfInterestStyle::Reserved { value } => Err(CodestreamError::DecodeError {
+                error: format!("reserved region of interest style {}", value),
+            }
+            .into()),
+        },
+    }
+}
+
+// The geometry, coding style and quantization of a tile-component, with the
+// code-blocks of every precinct.
+fn new_tile_component<'a>(
+    header: &'a Header,
+    tile: &'a Tile,
+    component: u16,
+    region: Rectangle,
+    reduce: u8,
+) -> Result<TileComponent<'a>, Box<dyn error::Error>> {
+    let coding_style_parameters = coding_style_parameters(header, tile, component);
+    let no_decomposition_levels = coding_style_parameters.no_decomposition_levels();
+    if reduce > no_decomposition_levels {
+        return Err(CodestreamError::DecodeError {
+            error: format!(
+                "cannot discard {} resolution levels of component {} with {} decomposition levels",
+                reduce, component, no_decomposition_levels
+            ),
+        }
+        .into());
+    }
+    let quantization = quantization(header, tile, component, no_decomposition_levels)?;
+
+    let siz = header.image_and_tile_size_marker_segment();
+    let mut resolutions = Vec::with_capacity(no_decomposition_levels as usize + 1);
+    for r in 0..=no_decomposition_levels {
+        let layout = geometry::layout(
+            siz,
+            coding_style_parameters,
+            tile.index as u32,
+            component,
+            r,
+        )?;
+        let resolution = *layout.region();
+        let ppx = layout.precinct_width_exponent();
+        let ppy = layout.precinct_height_exponent();
+
+        // The subbands of decomposition level n_b follow the resolution level
+        // they are reconstructed from
+        let lower = if r == 0 {
+            Rectangle::default()
+        } else {
+            geometry::resolution(&region, no_decomposition_levels, r - 1)
+        };
+        let subbands: Vec<Subband> = layout
+            .subbands()
+            .iter()
+            .map(|subband| {
+                let (x_offset, y_offset) = match subband.orientation() {
+                    SubbandOrientation::LL => (0, 0),
+                    SubbandOrientation::HL => (lower.width(), 0),
+                    SubbandOrientation::LH => (0, lower.height()),
+                    SubbandOrientation::HH => (lower.width(), lower.height()),
+                };
+                Subband {
+                    orientation: subband.orientation(),
+                    region: *subband.region(),
+                    x_offset,
+                    y_offset,
+                }
+            })
+            .collect();
+
+        let precincts = layout
+            .precincts()
+            .iter()
+            .map(|precinct| {
+                let no_code_blocks: Vec<(u32, u32)> = precinct
+                    .subbands()
+                    .iter()
+                    .map(|subband| subband.no_code_blocks())
+                    .collect();
+                let code_blocks = precinct
+                    .subbands()
+                    .iter()
+                    .map(|subband| {
+                        subband
+                            .code_blocks()
+                            .iter()
+                            .map(|code_block| CodeBlock {
+                                region: *code_block.region(),
+                                segments: vec![],
+                                no_passes: 0,
+                                zero_bit_planes: 0,
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                PrecinctState {
+                    precinct: Precinct::with_code_block_style(
+                        &no_code_blocks,
+                        coding_style_parameters.code_block_style(),
+                    ),
+                    code_blocks,
+                    corrupt: false,
+                }
+            })
+            .collect();
+
+        resolutions.push(Resolution {
+            region: resolution,
+            window: resolution,
+            ppx,
+            ppy,
+            subbands,
+            precincts,
+        });
+    }
+
+    Ok(TileComponent {
+        tile: tile.index,
+        component,
+        region,
+        precision: header
+            .image_and_tile_size_marker_segment()
+            .precision(component as usize)? as u8,
+        coding_style_parameters,
+        quantization,
+        resolutions,
+        region_of_interest_shift: region_of_interest_shift(header, tile, component)?,
+        reduce,
+    })
+}
+
+// F.3.2 - The parts of the subbands of each resolution level which are needed
+// to reconstruct a window of the tile-component, that is the window on the
+// grid of each lower resolution level extended by the support of the
+// synthesis filters. Code-blocks outside these parts are not decoded.
+fn set_windows(tile_component: &mut TileComponent, window: &Rectangle) {
+    // Coefficients on either side of a sample which contribute to it, for the
+    // 3 and 5 tap or 7 and 9 tap synthesis filters
+    let support = match tile_component.coding_style_parameters.transformation() {
+        TransformationFilter::Reversible => 2,
+        _ => 3,
+    };
+
+    let no_resolutions = tile_component.no_resolutions();
+    let mut needed = geometry::reduce(window, tile_component.reduce)
+        .intersection(&tile_component.decoded_region());
+
+    for r in (0..tile_component.resolutions.len()).rev() {
+        if r >= no_resolutions || needed.is_empty() {
+            tile_component.resolutions[r].window = Rectangle::default();
+        } else if r == 0 {
+            tile_component.resolutions[r].window = needed;
+        } else {
+            let window = Rectangle::new(
+                (needed.x0() / 2).saturating_sub(support),
+                (needed.y0() / 2).saturating_sub(support),
+                needed.x1().div_ceil(2) + support,
+                needed.y1().div_ceil(2) + support,
+            );
+            tile_component.resolutions[r].window = window;
+
+            // The lower resolution level is needed where its LL subband is
+            needed = window.intersection(&tile_component.resolutions[r - 1].region);
+        }
+    }
+}
+
+// A.6.6 - The progressions of the POC marker segments of the tile-part
+// headers of a tile, otherwise those of the main header, otherwise every
+// packet in the progression order of COD.
+fn progressions(header: &Header, tile: &Tile, no_resolutions: u8) -> Vec<Progression> {
+    let segments = if !tile.header.progression_order_changes.is_empty() {
+        tile.header.progression_order_changes.iter().collect()
+    } else {
+        header
+            .progression_order_change_segment()
+            .iter()
+            .collect::<Vec<_>>()
+    };
+
+    if segments.is_empty() {
+        let cod = coding_style(header, tile);
+        return vec![Progression::new(
+            cod.progression_order(),
+            cod.no_layers(),
+            no_resolutions,
+            header.image_and_tile_size_marker_segment().no_components(),
+        )];
+    }
+
+    segments
+        .iter()
+        .flat_map(|segment| segment.progressions())
+        .map(Progression::from)
+        .collect()
+}
+
+// B.9 and B.10 - Read the packets of a tile in progression order, gathering
+// the codeword segments of every code-block from the first layers.
+fn decode_packets(
+    header: &Header,
+    tile: &Tile,
+    region: &Rectangle,
+    tile_components: &mut [TileComponent],
+    max_layers: u16,
+) -> Result<(), Box<dyn error::Error>> {
+    let siz = header.image_and_tile_size_marker_segment();
+    let cod = coding_style(header, tile);
+    let sop = cod.coding_styles().contains(&CodingStyleDefault::SOP);
+
+    let mut precincts = Vec::with_capacity(tile_components.len());
+    for (c, tile_component) in tile_components.iter().enumerate() {
+        precincts.push(TileComponentPrecincts {
+            horizontal_separation: siz.horizontal_separation(c)?,
+            vertical_separation: siz.vertical_separation(c)?,
+            resolutions: tile_component
+                .resolutions
+                .iter()
+                .map(|resolution| ResolutionPrecincts {
+                    region: resolution.region,
+                    ppx: resolution.ppx,
+                    ppy: resolution.ppy,
+                })
+                .collect(),
+        });
+    }
+    let no_resolutions = precincts
+        .iter()
+        .map(|component| component.resolutions.len() as u8)
+        .max()
+        .unwrap_or(0);
+    let progressions = progressions(header, tile, no_resolutions);
+    let mut packets = progression::packets(region, &precincts, &progressions)?;
+
+    // The packets after the last packet of a layer and resolution level which
+    // is decoded are not read at all
+    let no_resolutions: Vec<usize> = tile_components
+        .iter()
+        .map(|tile_component| tile_component.no_resolutions())
+        .collect();
+    let decoded = |packet: &progression::Packet| {
+        packet.layer < max_layers
+            && (packet.resolution as usize) < no_resolutions[packet.component as usize]
+    };
+    let no_packets = packets.iter().rposition(decoded).map_or(0, |i| i + 1);
+    packets.truncate(no_packets);
+
+    // A.7.4 and A.7.5 - The packet headers are either packed in PPM or PPT
+    // marker segments, or they precede the packet bodies in the tile data
+    let data = tile.data();
+    let packet_headers = tile.packet_headers()?;
+    let mut position = 0;
+    let mut header_position = 0;
+
+    // Whether the packets start with SOP marker segments, which are used to
+    // resynchronise after a corrupt packet
+    let mut with_sop = false;
+
+    let mut index = 0;
+    while index < packets.len() {
+        let packet = packets[index];
+
+        // The remaining packets are missing from a truncated codestream
+        let remaining = match &packet_headers {
+            Some(packet_headers) => header_position < packet_headers.len(),
+            None => position < data.len(),
+        };
+        if !remaining {
+            break;
+        }
+        let start = position;
+
+        // A.8.1 - Start of packet marker segment, where Nsop is the index of
+        // the packet in the tile modulo 65536. It may be left out of any
+        // packet, but one which is there has to be well formed.
+        let mut corrupt_sop = false;
+        if sop {
+            let rest = &data[position.min(data.len())..];
+            match start_of_packet(rest) {
+                Some(sequence) if sequence == index as u16 => {
+                    position += SOP_LENGTH;
+                    with_sop = true;
+                }
+                None if !rest.starts_with(&MARKER_SYMBOL_SOP) => {}
+                _ => corrupt_sop = true,
+            }
+        }
+        let packet_start = position;
+
+        let state = &mut tile_components[packet.component as usize].resolutions
+            [packet.resolution as usize]
+            .precincts[packet.precinct as usize];
+
+        // The header of a packet of a precinct with a lost packet cannot be
+        // decoded
+        let mut packet_header = None;
+        if !corrupt_sop && !state.corrupt {
+            match &packet_headers {
+                Some(packet_headers) => {
+                    let header = state
+                        .precinct
+                        .decode_packet_header(&packet_headers[header_position..], packet.layer)?;
+                    header_position += header.length();
+                    packet_header = Some(header);
+                }
+                None => match state
+                    .precinct
+                    .decode_packet_header(&data[position..], packet.layer)
+                {
+                    Ok(header) => {
+                        position += header.length();
+                        packet_header = Some(header);
+                    }
+                    Err(error) if !with_sop => return Err(error),
+                    Err(_) => {}
+                },
+            }
+        }
+
+        // A packet which runs into the SOP marker segment of a later packet is
+        // corrupt, as bit stuffing keeps the marker out of packet headers and
+        // bodies, see B.10.1 and D.4.1
+        let packet_header = match packet_header {
+            Some(packet_header) => {
+                let end = (position + packet_header.body_length() as usize).min(data.len());
+                let packet = &data[packet_start.min(end)..end];
+                if with_sop && packet.windows(2).any(|bytes| bytes == MARKER_SYMBOL_SOP) {
+                    None
+                } else {
+                    Some(packet_header)
+                }
+            }
+            None => None,
+        };
+
+        let packet_header = match packet_header {
+            Some(packet_header) => packet_header,
+            None => {
+                if packet_headers.is_some() {
+                    return Err(CodestreamError::PacketError {
+                        error: format!("packet {} of tile {} is corrupt", index, tile.index),
+                    }
+                    .into());
+                }
+
+                // Continue at the next SOP marker segment, the packets up to
+                // it are lost. A packet with a corrupt SOP marker segment may
+                // follow later on.
+                let (from, next) = if corrupt_sop {
+                    (start, index)
+                } else {
+                    (start + 1, index + 1)
+                };
+                let (resume, resume_index) = match resynchronise(&data, from, next, packets.len()) {
+                    Some(resume) => resume,
+                    None => (data.len(), packets.len()),
+                };
+                for lost in &packets[index..resume_index.min(packets.len())] {
+                    tile_components[lost.component as usize].resolutions
+                        [lost.resolution as usize]
+                        .precincts[lost.precinct as usize]
+                        .corrupt = true;
+                }
+
+                position = resume;
+                index = resume_index;
+                continue;
+            }
+        };
+        index += 1;
+
+        // The packet headers of discarded layers and resolution levels are
+        // decoded only to skip their packet bodies
+        if !decoded(&packet) {
+            position += packet_header.body_length() as usize;
+            continue;
+        }
+
+        for contribution in packet_header.contributions() {
+            let code_block =
+                &mut state.code_blocks[contribution.subband()][contribution.code_block()];
+
+            for (i, length) in contribution.segment_lengths().iter().enumerate() {
+                let start = position.min(data.len());
+                let end = (position + *length as usize).min(data.len());
+                match code_block.segments.last_mut() {
+                    Some(segment) if i == 0 && contribution.is_continued() => {
+                        segment.extend_from_slice(&data[start..end])
+                    }
+                    _ => code_block.segments.push(data[start..end].to_vec()),
+                }
+                position += *length as usize;
+            }
+            code_block.no_passes = code_block
+                .no_passes
+                .saturating_add(contribution.no_passes());
+            if let Some(zero_bit_planes) = contribution.zero_bit_planes() {
+                code_block.zero_bit_planes = zero_bit_planes;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A.8.1 - The packet sequence number (Nsop) of the SOP marker segment at the
+// start of the data, if there is one with a length (Lsop) of 4
+fn start_of_packet(data: &[u8]) -> Option<u16> {
+    if data.len() < SOP_LENGTH || !data.starts_with(&MARKER_SYMBOL_SOP) || data[2..4] != [0, 4] {
+        return None;
+    }
+    Some(u16::from_be_bytes([data[4], data[5]]))
+}
+
+// The position of the next SOP marker segment in the data from a position,
+// with the index of the packet it starts. That is the first index from next
+// with the packet sequence number of the SOP marker segment, which has to be
+// one of the packets of the tile.
+fn resynchronise(
+    data: &[u8],
+    from: usize,
+    next: usize,
+    no_packets: usize,
+) -> Option<(usize, usize)> {
+    (from..data.len()).find_map(|position| {
+        let sequence = start_of_packet(&data[position..])?;
+        let index = next + sequence.wrapping_sub(next as u16) as usize;
+        if index < no_packets {
+            Some((position, index))
+        } else {
+            None
+        }
+    })
+}
+
+// Decode the code-blocks of a tile-component, dequantize the coefficients and
+// apply the inverse wavelet transformation.
+fn decode_tile_component(
+    tile_component: &TileComponent,
+    options: &DecodeOptions,
+    concealed_code_blocks: &mut Vec<ConcealedCodeBlock>,
+) -> Result<Samples, Box<dyn error::Error>> {
+    let parameters = tile_component.coding_style_parameters;
+    let quantization = &tile_component.quantization;
+    let region = &tile_component.decoded_region();
+    let no_decomposition_levels = parameters.no_decomposition_levels() - tile_component.reduce;
+    let stride = region.width() as usize;
+    let size = stride * region.height() as usize;
+
+    match parameters.transformation() {
+        TransformationFilter::Reversible => {
+            let mut coefficients = vec![0; size];
+            decode_code_blocks(
+                tile_component,
+                options,
+                concealed_code_blocks,
+                |subband, code_block, decoder, r| {
+                    let values = dequantize(
+                        tile_component,
+                        &decoder.coefficients(),
+                        quantization.magnitude_bits(r, subband.orientation),
+                        code_block.zero_bit_planes + decoder.no_bit_planes_decoded(),
+                        |values, magnitude_bits, no_bit_planes| {
+                            quantization::dequantize_reversible(
+                                values,
+                                magnitude_bits,
+                                no_bit_planes,
+                                options.reconstruction_bias,
+                            )
+                        },
+                    );
+                    place(&mut coefficients, stride, subband, code_block, &values);
+                },
+            )?;
+
+            wavelet::inverse_reversible(
+                &mut coefficients,
+                region.x0(),
+                region.y0(),
+                region.x1(),
+                region.y1(),
+                no_decomposition_levels,
+            );
+            Ok(Samples::Reversible(coefficients))
+        }
+        TransformationFilter::Irreversible => {
+            let mut coefficients = vec![0.0; size];
+            decode_code_blocks(
+                tile_component,
+                options,
+                concealed_code_blocks,
+                |subband, code_block, decoder, r| {
+                    let step_size =
+                        quantization.step_size(r, subband.orientation, tile_component.precision);
+                    let values = dequantize(
+                        tile_component,
+                        &decoder.coefficients(),
+                        quantization.magnitude_bits(r, subband.orientation),
+                        code_block.zero_bit_planes + decoder.no_bit_planes_decoded(),
+                        |values, magnitude_bits, no_bit_planes| {
+                            quantization::dequantize(
+                                values,
+                                step_size,
+                                magnitude_bits,
+                                no_bit_planes,
+                                options.reconstruction_bias,
+                            )
+                        },
+                    );
+                    place(&mut coefficients, stride, subband, code_block, &values);
+                },
+            )?;
+
+            wavelet::inverse_irreversible(
+                &mut coefficients,
+                region.x0(),
+                region.y0(),
+                region.x1(),
+                region.y1(),
+                no_decomposition_levels,
+            );
+            Ok(Samples::Irreversible(coefficients))
+        }
+        TransformationFilter::Reserved { value } => Err(CodestreamError::DecodeError {
+            error: format!("reserved wavelet transformation {:?}", value),
+        }
+        .into()),
+    }
+}
+
+// E.1.1.2 and H.2 - Dequantize the coefficients of a code-block with the
+// number of bit-planes decoded of the M_b + s magnitude bits it is coded
+// with. With a region of interest, the background and the descaled region of
+// interest are missing a different number of their magnitude bits.
+fn dequantize<T, F>(
+    tile_component: &TileComponent,
+    values: &[i32],
+    magnitude_bits: u8,
+    no_bit_planes: u8,
+    f: F,
+) -> Vec<T>
+where
+    T: ops::Add<Output = T>,
+    F: Fn(&[i32], u8, u8) -> Vec<T>,
+{
+    let shift = tile_component.region_of_interest_shift;
+    if shift == 0 {
+        return f(values, magnitude_bits, no_bit_planes);
+    }
+
+    let (background, region) = region_of_interest::split(values, shift);
+    let background = f(
+        &background,
+        magnitude_bits.saturating_add(shift),
+        no_bit_planes,
+    );
+    let region = f(&region, magnitude_bits, no_bit_planes);
+    background
+        .into_iter()
+        .zip(region)
+        .map(|(background, region)| background + region)
+        .collect()
+}
+
+// Run the coefficient bit modelling decoder over every code-block with coding
+// passes, handing the decoded code-block to place its coefficients. Resilient
+// decoding reports the code-blocks with corrupt coding passes, where those
+// which cannot be decoded at all are left as zero.
+fn decode_code_blocks<F>(
+    tile_component: &TileComponent,
+    options: &DecodeOptions,
+    concealed_code_blocks: &mut Vec<ConcealedCodeBlock>,
+    mut f: F,
+) -> Result<(), Box<dyn error::Error>>
+where
+    F: FnMut(&Subband, &CodeBlock, &CodeBlockDecoder, u8),
+{
+    let parameters = tile_component.coding_style_parameters;
+
+    let mut jobs = vec![];
+    let resolutions = tile_component.resolutions.iter();
+    for (r, resolution) in resolutions
+        .take(tile_component.no_resolutions())
+        .enumerate()
+    {
+        let r = r as u8;
+        for precinct in &resolution.precincts {
+            for (subband, code_blocks) in resolution.subbands.iter().zip(&precinct.code_blocks) {
+                // H.2 - The coefficients of the region of interest are coded
+                // in s additional bit-planes
+                let magnitude_bits = tile_component
+                    .quantization
+                    .magnitude_bits(r, subband.orientation)
+                    .saturating_add(tile_component.region_of_interest_shift);
+
+                for code_block in code_blocks {
+                    if code_block.no_passes == 0
+                        || code_block
+                            .region
+                            .intersection(&resolution.window)
+                            .is_empty()
+                    {
+                        continue;
+                    }
+                    jobs.push((r, subband, code_block, magnitude_bits));
+                }
+            }
+        }
+    }
+
+    // Code-blocks are decoded independently, but placed in order
+    for_each_in_order(
+        &jobs,
+        |&(_, subband, code_block, magnitude_bits)| {
+            let mut decoder = CodeBlockDecoder::new(
+                code_block.region.width(),
+                code_block.region.height(),
+                subband.orientation,
+                parameters.code_block_style(),
+            );
+            let segments: Vec<&[u8]> = code_block.segments.iter().map(|s| s.as_slice()).collect();
+            let result = if options.resilient {
+                decoder.decode_resilient(
+                    &segments,
+                    code_block.no_passes,
+                    code_block.zero_bit_planes,
+                    magnitude_bits,
+                )
+            } else {
+                decoder.decode(
+                    &segments,
+                    code_block.no_passes,
+                    code_block.zero_bit_planes,
+                    magnitude_bits,
+                )
+            };
+            match result {
+                Ok(()) => {
+                    let no_passes_decoded = decoder.no_passes_decoded();
+                    Ok((decoder, no_passes_decoded))
+                }
+                Err(error) if !options.resilient => Err(send_error(error)),
+                Err(_) => Ok((decoder, 0)),
+            }
+        },
+        |&(r, subband, code_block, _), (decoder, no_passes_decoded)| {
+            if no_passes_decoded < code_block.no_passes {
+                concealed_code_blocks.push(ConcealedCodeBlock {
+                    tile: tile_component.tile,
+                    component: tile_component.component,
+                    resolution: r,
+                    orientation: subband.orientation,
+                    x0: code_block.region.x0(),
+                    y0: code_block.region.y0(),
+                    width: code_block.region.width(),
+                    height: code_block.region.height(),
+                    no_passes: code_block.no_passes,
+                    no_passes_decoded,
+                });
+            }
+            if no_passes_decoded > 0 {
+                f(subband, code_block, &decoder, r);
+            }
+        },
+    )
+}
+
+// Decoding fails with codestream errors, which are sent between threads as
+// they are
+fn send_error(error: Box<dyn error::Error>) -> Box<dyn error::Error + Send + Sync> {
+    match error.downcast::<CodestreamError>() {
+        Ok(error) => error,
+        Err(error) => error.to_string().into(),
+    }
+}
+
+// Apply f to each item and hand each result to g in the order of the items.
+// The error of the first item that fails is returned, after the results of
+// the items before it have been handed over. Without the rayon feature every
+// result is handed over as soon as it is produced, so only one is held at a
+// time.
+#[cfg(not(feature = "rayon"))]
+fn for_each_in_order<T, U, F, G>(items: &[T], f: F, mut g: G) -> Result<(), Box<dyn error::Error>>
+where
+    F: Fn(&T) -> Result<U, Box<dyn error::Error + Send + Sync>>,
+    G: FnMut(&T, U),
+{
+    for item in items {
+        let result = f(item).map_err(|error| error as Box<dyn error::Error>)?;
+        g(item, result);
+    }
+    Ok(())
+}
+
+// With the rayon feature the items are mapped in parallel. Items after one
+// that failed are skipped, but every item before the first that fails is
+// mapped, so the same error is returned however the items are scheduled.
+#[cfg(feature = "rayon")]
+fn for_each_in_order<T, U, F, G>(items: &[T], f: F, mut g: G) -> Result<(), Box<dyn error::Error>>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> Result<U, Box<dyn error::Error + Send + Sync>> + Sync,
+    G: FnMut(&T, U),
+{
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let first_failed = AtomicUsize::new(usize::MAX);
+    let results: Vec<Option<Result<U, _>>> = items
+        .par_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            if i > first_failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            let result = f(item);
+            if result.is_err() {
+                first_failed.fetch_min(i, Ordering::Relaxed);
+            }
+            Some(result)
+        })
+        .collect();
+
+    for (item, result) in items.iter().zip(results) {
+        match result {
+            Some(Ok(result)) => g(item, result),
+            Some(Err(error)) => return Err(error),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+// Copy the coefficients of a code-block into the tile-component coefficients
+fn place<T: Copy>(
+    coefficients: &mut [T],
+    stride: usize,
+    subband: &Subband,
+    code_block: &CodeBlock,
+    values: &[T],
+) {
+    let width = code_block.region.width() as usize;
+    let x = (subband.x_offset + code_block.region.x0() - subband.region.x0()) as usize;
+    let y = (subband.y_offset + code_block.region.y0() - subband.region.y0()) as usize;
+
+    for (row, values) in values.chunks_exact(width).enumerate() {
+        let offset = (y + row) * stride + x;
+        coefficients[offset..offset + width].copy_from_slice(values);
+    }
+}
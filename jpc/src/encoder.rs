@@ -0,0 +1,640 @@
+use std::error;
+use std::io;
+
+use super::code_block::{CodeBlockEncoder, EncodedCodeBlock};
+use super::geometry::{self, Rectangle, ResolutionLayout};
+use super::packet::{CodeBlockContribution, Precinct};
+use super::progression::{self, Progression, ResolutionPrecincts, TileComponentPrecincts};
+use super::transformation;
+use super::wavelet;
+use super::writer::CodestreamWriter;
+use super::{
+    CodestreamError, CodingStyleMarkerSegment, CodingStyleParameters, Component, EncodeOptions,
+    Header, ImageAndTileSizeMarkerSegment, ProgressionOrder, QuantizationDefaultMarkerSegment,
+    QuantizationValue, StartOfTileSegment, SubbandOrientation, TRANSFORMATION_FILTER_REVERSIBLE,
+};
+
+// A.6.4 - Guard bits (G) signalled when the coefficients need no more, as
+// the reversible transformations of most encoders use
+const DEFAULT_GUARD_BITS: u8 = 2;
+
+// Largest number of guard bits Sqcd can signal
+const MAX_GUARD_BITS: u8 = 7;
+
+// A.6.1 - Code-block width and height exponents (xcb, ycb) range from 2 to 10
+// and their sum is at most 12
+const MIN_CODE_BLOCK_EXPONENT: u8 = 2;
+const MAX_CODE_BLOCK_EXPONENT: u8 = 10;
+const MAX_CODE_BLOCK_EXPONENTS: u8 = 12;
+
+// Table A.10 - At most 32 decomposition levels
+const MAX_DECOMPOSITION_LEVELS: u8 = 32;
+
+// The wavelet coefficients of a tile-component, with the subbands of each
+// decomposition level arranged as the forward wavelet transformation leaves
+// them, and the partition of each resolution level
+#[derive(Debug)]
+struct TileComponent {
+    region: Rectangle,
+    coefficients: Vec<i32>,
+    layouts: Vec<ResolutionLayout>,
+}
+
+impl TileComponent {
+    fn no_decomposition_levels(&self) -> u8 {
+        self.layouts.len() as u8 - 1
+    }
+
+    // Position of a subband of resolution level r in the coefficients, the
+    // same the decoder places the subbands at for the inverse transformation
+    fn subband_offset(&self, r: u8, orientation: SubbandOrientation) -> (u32, u32) {
+        if r == 0 {
+            return (0, 0);
+        }
+        let lower = geometry::resolution(&self.region, self.no_decomposition_levels(), r - 1);
+        match orientation {
+            SubbandOrientation::LL => (0, 0),
+            SubbandOrientation::HL => (lower.width(), 0),
+            SubbandOrientation::LH => (0, lower.height()),
+            SubbandOrientation::HH => (lower.width(), lower.height()),
+        }
+    }
+
+    // Coefficients of a code-block of a subband of resolution level r in
+    // raster order
+    fn code_block(
+        &self,
+        r: u8,
+        orientation: SubbandOrientation,
+        subband: &Rectangle,
+        code_block: &Rectangle,
+    ) -> Vec<i32> {
+        let stride = self.region.width() as usize;
+        let (x_offset, y_offset) = self.subband_offset(r, orientation);
+        let width = code_block.width() as usize;
+        let x = (x_offset + code_block.x0() - subband.x0()) as usize;
+        let y = (y_offset + code_block.y0() - subband.y0()) as usize;
+
+        let mut values = Vec::with_capacity(width * code_block.height() as usize);
+        for row in 0..code_block.height() as usize {
+            let offset = (y + row) * stride + x;
+            values.extend_from_slice(&self.coefficients[offset..offset + width]);
+        }
+        values
+    }
+
+    // Largest magnitude of the coefficients of a subband of resolution level r
+    fn max_magnitude(&self, r: u8, orientation: SubbandOrientation, subband: &Rectangle) -> u32 {
+        let stride = self.region.width() as usize;
+        let (x, y) = self.subband_offset(r, orientation);
+        let (x, y) = (x as usize, y as usize);
+        let width = subband.width() as usize;
+
+        (0..subband.height() as usize)
+            .flat_map(|row| {
+                let offset = (y + row) * stride + x;
+                self.coefficients[offset..offset + width].iter()
+            })
+            .map(|coefficient| coefficient.unsigned_abs())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// The code-blocks of a precinct coded for its single layer
+#[derive(Debug)]
+struct PrecinctState {
+    precinct: Precinct,
+
+    // Code-blocks of each subband which are in the precinct, in raster order
+    code_blocks: Vec<Vec<EncodedCodeBlock>>,
+}
+
+/// Encode the components of an image into a codestream with the reversible
+/// transformations, see Figure 1
+pub(crate) fn encode<W: io::Write>(
+    writer: W,
+    components: &[Component],
+    options: &EncodeOptions,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut header = new_header(components, options)?;
+
+    // The guard bits of QCD are those the largest coefficient of any subband
+    // needs, so every tile is transformed before any code-block is coded
+    let siz = header.image_and_tile_size_marker_segment();
+    let no_tiles = siz.num_x_tiles() * siz.num_y_tiles();
+    let mut tiles = Vec::with_capacity(no_tiles as usize);
+    for t in 0..no_tiles {
+        tiles.push(transform_tile(&header, components, t)?);
+    }
+
+    // The exponents of QCD apply to every component, with the precision of
+    // the component with the most bits
+    let precision = components
+        .iter()
+        .map(|component| component.precision)
+        .max()
+        .unwrap_or(1);
+    let guard_bits = guard_bits(&tiles, precision)?;
+    header.quantization_default_marker_segment = Some(quantization_default(
+        guard_bits,
+        precision,
+        options.no_decomposition_levels,
+    ));
+
+    let mut writer = CodestreamWriter::new(writer);
+    writer.write_header(&header)?;
+    for (t, tile) in tiles.iter().enumerate() {
+        let region = geometry::tile(header.image_and_tile_size_marker_segment(), t as u32);
+        let magnitude_bits = |orientation| guard_bits + exponent(precision, orientation) - 1;
+        let data = encode_tile(&region, tile, magnitude_bits, options.progression_order)?;
+
+        let segment = StartOfTileSegment {
+            tile_index: (t as u16).to_be_bytes(),
+            no_tile_parts: [1],
+            ..StartOfTileSegment::default()
+        };
+        writer.write_tile_part(&segment, &[], &data, false)?;
+    }
+    writer.write_eoc()
+}
+
+// A.5.1 and A.6.1 - The SIZ and COD marker segments of the image, with a
+// single layer of the 5-3 reversible filter and the reversible component
+// transformation of the first three components
+fn new_header(
+    components: &[Component],
+    options: &EncodeOptions,
+) -> Result<Header, Box<dyn error::Error>> {
+    let first = match components.first() {
+        Some(first) => first,
+        None => {
+            return Err(CodestreamError::EncodeError {
+                error: "an image needs at least one component".to_string(),
+            }
+            .into())
+        }
+    };
+    if components.len() > u16::MAX as usize {
+        return Err(CodestreamError::EncodeError {
+            error: format!(
+                "{} components exceed the 65535 of an image",
+                components.len()
+            ),
+        }
+        .into());
+    }
+    for (c, component) in components.iter().enumerate() {
+        if (
+            component.x0,
+            component.y0,
+            component.width,
+            component.height,
+        ) != (first.x0, first.y0, first.width, first.height)
+        {
+            return Err(CodestreamError::EncodeError {
+                error: format!(
+                    "component {} does not have the size and position of component 0",
+                    c
+                ),
+            }
+            .into());
+        }
+        if component.horizontal_separation != 1 || component.vertical_separation != 1 {
+            return Err(CodestreamError::EncodeError {
+                error: format!("component {} is subsampled", c),
+            }
+            .into());
+        }
+        if !(1..=16).contains(&component.precision) {
+            return Err(CodestreamError::EncodeError {
+                error: format!(
+                    "precision {} of component {} is not from 1 to 16 bits",
+                    component.precision, c
+                ),
+            }
+            .into());
+        }
+        if component.samples.len() != component.width as usize * component.height as usize {
+            return Err(CodestreamError::EncodeError {
+                error: format!(
+                    "{} samples for the {}x{} samples of component {}",
+                    component.samples.len(),
+                    component.width,
+                    component.height,
+                    c
+                ),
+            }
+            .into());
+        }
+    }
+    if first.width == 0 || first.height == 0 {
+        return Err(CodestreamError::EncodeError {
+            error: "the image area is empty".to_string(),
+        }
+        .into());
+    }
+    let x1 = first.x0.checked_add(first.width);
+    let y1 = first.y0.checked_add(first.height);
+    let (x1, y1) = match (x1, y1) {
+        (Some(x1), Some(y1)) => (x1, y1),
+        _ => {
+            return Err(CodestreamError::EncodeError {
+                error: "the image area exceeds the reference grid".to_string(),
+            }
+            .into())
+        }
+    };
+
+    let (tile_width, tile_height) = options.tile_size.unwrap_or((first.width, first.height));
+    if tile_width == 0 || tile_height == 0 {
+        return Err(CodestreamError::EncodeError {
+            error: format!("tile size {}x{} is empty", tile_width, tile_height),
+        }
+        .into());
+    }
+    let no_tiles =
+        first.width.div_ceil(tile_width) as u64 * first.height.div_ceil(tile_height) as u64;
+    if no_tiles > u16::MAX as u64 {
+        return Err(CodestreamError::EncodeError {
+            error: format!("{} tiles exceed the 65535 of an image", no_tiles),
+        }
+        .into());
+    }
+
+    let (code_block_width, code_block_height) = options.code_block_size;
+    let xcb = code_block_exponent(code_block_width)?;
+    let ycb = code_block_exponent(code_block_height)?;
+    if xcb + ycb > MAX_CODE_BLOCK_EXPONENTS {
+        return Err(CodestreamError::EncodeError {
+            error: format!(
+                "code-block size {}x{} exceeds 4096 coefficients",
+                code_block_width, code_block_height
+            ),
+        }
+        .into());
+    }
+    if options.no_decomposition_levels > MAX_DECOMPOSITION_LEVELS {
+        return Err(CodestreamError::EncodeError {
+            error: format!(
+                "{} decomposition levels exceed {}",
+                options.no_decomposition_levels, MAX_DECOMPOSITION_LEVELS
+            ),
+        }
+        .into());
+    }
+    let progression_order = match options.progression_order {
+        ProgressionOrder::Reserved { value } => {
+            return Err(CodestreamError::EncodeError {
+                error: format!("reserved progression order {:08b}", value),
+            }
+            .into())
+        }
+        progression_order => progression_order.value(),
+    };
+
+    // The tiles start at the image area, XTOsiz = XOsiz and YTOsiz = YOsiz
+    let no_components = components.len();
+    let siz = ImageAndTileSizeMarkerSegment {
+        reference_grid_width: x1.to_be_bytes(),
+        reference_grid_height: y1.to_be_bytes(),
+        image_horizontal_offset: first.x0.to_be_bytes(),
+        image_vertical_offset: first.y0.to_be_bytes(),
+        reference_tile_width: tile_width.to_be_bytes(),
+        reference_tile_height: tile_height.to_be_bytes(),
+        tile_horizontal_offset: first.x0.to_be_bytes(),
+        tile_vertical_offset: first.y0.to_be_bytes(),
+        no_components: (no_components as u16).to_be_bytes(),
+        precision: components
+            .iter()
+            .map(|component| [(component.precision - 1) | ((component.signed as u8) << 7)])
+            .collect(),
+        horizontal_separation: vec![[1]; no_components],
+        vertical_separation: vec![[1]; no_components],
+        ..ImageAndTileSizeMarkerSegment::default()
+    };
+
+    let cod = CodingStyleMarkerSegment {
+        progression_order: [progression_order],
+        no_layers: 1u16.to_be_bytes(),
+        multiple_component_transformation: [(no_components >= 3) as u8],
+        coding_style_parameters: CodingStyleParameters {
+            no_decomposition_levels: [options.no_decomposition_levels],
+            code_block_width: [xcb - MIN_CODE_BLOCK_EXPONENT],
+            code_block_height: [ycb - MIN_CODE_BLOCK_EXPONENT],
+            transformation: TRANSFORMATION_FILTER_REVERSIBLE,
+            ..CodingStyleParameters::default()
+        },
+        ..CodingStyleMarkerSegment::default()
+    };
+
+    Ok(Header {
+        image_and_tile_size_marker_segment: siz,
+        coding_style_marker_segment: Some(cod),
+        ..Header::default()
+    })
+}
+
+// The exponent of a code-block width or height, which is a power of two
+fn code_block_exponent(size: u16) -> Result<u8, Box<dyn error::Error>> {
+    let exponent = size.trailing_zeros() as u8;
+    if !size.is_power_of_two()
+        || !(MIN_CODE_BLOCK_EXPONENT..=MAX_CODE_BLOCK_EXPONENT).contains(&exponent)
+    {
+        return Err(CodestreamError::EncodeError {
+            error: format!(
+                "code-block size {} is not a power of two from 4 to 1024",
+                size
+            ),
+        }
+        .into());
+    }
+    Ok(exponent)
+}
+
+// G.1.2, G.2.1 and F.4 - The forward DC level shift, reversible component
+// transformation and 5-3 reversible wavelet transformation of the
+// tile-components of tile t
+fn transform_tile(
+    header: &Header,
+    components: &[Component],
+    t: u32,
+) -> Result<Vec<TileComponent>, Box<dyn error::Error>> {
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+    let no_decomposition_levels = parameters.no_decomposition_levels();
+    let region = geometry::tile(siz, t);
+
+    let mut samples: Vec<Vec<i32>> = components
+        .iter()
+        .map(|component| {
+            let mut samples = copy_samples(component, &region);
+            transformation::forward_dc_level_shift(
+                &mut samples,
+                component.precision,
+                component.signed,
+            );
+            samples
+        })
+        .collect();
+
+    if let [c0, c1, c2, ..] = &mut samples[..] {
+        transformation::forward_reversible(c0, c1, c2);
+    }
+
+    let mut tile_components = Vec::with_capacity(components.len());
+    for (c, mut coefficients) in samples.into_iter().enumerate() {
+        wavelet::forward_reversible(
+            &mut coefficients,
+            region.x0(),
+            region.y0(),
+            region.x1(),
+            region.y1(),
+            no_decomposition_levels,
+        );
+
+        let mut layouts = Vec::with_capacity(no_decomposition_levels as usize + 1);
+        for r in 0..=no_decomposition_levels {
+            layouts.push(geometry::layout(siz, parameters, t, c as u16, r)?);
+        }
+
+        tile_components.push(TileComponent {
+            region,
+            coefficients,
+            layouts,
+        });
+    }
+
+    Ok(tile_components)
+}
+
+// The samples of a component in a tile, without subsampling the tile is the
+// tile-component
+fn copy_samples(component: &Component, region: &Rectangle) -> Vec<i32> {
+    let width = region.width() as usize;
+    let mut samples = Vec::with_capacity(width * region.height() as usize);
+    for y in region.y0()..region.y1() {
+        let from = ((y - component.y0) * component.width + region.x0() - component.x0) as usize;
+        samples.extend_from_slice(&component.samples[from..from + width]);
+    }
+    samples
+}
+
+// E.1.1.2 - Exponent of a subband without quantization (ε_b), the precision
+// of the component plus the log2 gain of the subband from Table E.1
+fn exponent(precision: u8, orientation: SubbandOrientation) -> u8 {
+    precision
+        + match orientation {
+            SubbandOrientation::LL => 0,
+            SubbandOrientation::HL | SubbandOrientation::LH => 1,
+            SubbandOrientation::HH => 2,
+        }
+}
+
+// Equation E-2 - The fewest guard bits (G) for which the magnitude bits of
+// every subband, M_b = G + ε_b - 1, hold its largest coefficient
+fn guard_bits(tiles: &[Vec<TileComponent>], precision: u8) -> Result<u8, Box<dyn error::Error>> {
+    let mut guard_bits = DEFAULT_GUARD_BITS;
+    for tile_component in tiles.iter().flatten() {
+        for (r, layout) in tile_component.layouts.iter().enumerate() {
+            for subband in layout.subbands() {
+                let orientation = subband.orientation();
+                let magnitude =
+                    tile_component.max_magnitude(r as u8, orientation, subband.region());
+                let no_bits = (u32::BITS - magnitude.leading_zeros()) as u8;
+                let exponent = exponent(precision, orientation);
+                guard_bits = guard_bits.max((no_bits + 1).saturating_sub(exponent));
+            }
+        }
+    }
+
+    if guard_bits > MAX_GUARD_BITS {
+        return Err(CodestreamError::EncodeError {
+            error: format!(
+                "{} guard bits exceed the {} of QCD",
+                guard_bits, MAX_GUARD_BITS
+            ),
+        }
+        .into());
+    }
+    Ok(guard_bits)
+}
+
+// A.6.4 - Quantization default without quantization, with an exponent for
+// the NLLL subband and the HL, LH and HH subbands of each decomposition level
+// from NL down to 1
+fn quantization_default(
+    guard_bits: u8,
+    precision: u8,
+    no_decomposition_levels: u8,
+) -> QuantizationDefaultMarkerSegment {
+    let mut values = vec![QuantizationValue::Reversible {
+        value: [exponent(precision, SubbandOrientation::LL) << 3],
+    }];
+    for _ in 0..no_decomposition_levels {
+        for orientation in [
+            SubbandOrientation::HL,
+            SubbandOrientation::LH,
+            SubbandOrientation::HH,
+        ] {
+            values.push(QuantizationValue::Reversible {
+                value: [exponent(precision, orientation) << 3],
+            });
+        }
+    }
+
+    QuantizationDefaultMarkerSegment {
+        quantization_style: [guard_bits << 5],
+        values,
+        ..QuantizationDefaultMarkerSegment::default()
+    }
+}
+
+// B.9 and B.10 - Code the code-blocks of every precinct of a tile and write
+// their packets in progression order, each code-block with all of its coding
+// passes in the single layer
+fn encode_tile<F>(
+    region: &Rectangle,
+    tile_components: &[TileComponent],
+    magnitude_bits: F,
+    progression_order: ProgressionOrder,
+) -> Result<Vec<u8>, Box<dyn error::Error>>
+where
+    F: Fn(SubbandOrientation) -> u8,
+{
+    let mut states = Vec::with_capacity(tile_components.len());
+    let mut precincts = Vec::with_capacity(tile_components.len());
+    for tile_component in tile_components {
+        states.push(encode_tile_component(tile_component, &magnitude_bits)?);
+        precincts.push(TileComponentPrecincts {
+            horizontal_separation: 1,
+            vertical_separation: 1,
+            resolutions: tile_component
+                .layouts
+                .iter()
+                .map(|layout| ResolutionPrecincts {
+                    region: *layout.region(),
+                    ppx: layout.precinct_width_exponent(),
+                    ppy: layout.precinct_height_exponent(),
+                })
+                .collect(),
+        });
+    }
+
+    let no_resolutions = tile_components
+        .iter()
+        .map(|tile_component| tile_component.layouts.len() as u8)
+        .max()
+        .unwrap_or(0);
+    let progression = Progression::new(
+        progression_order,
+        1,
+        no_resolutions,
+        tile_components.len() as u16,
+    );
+    let packets = progression::packets(region, &precincts, &[progression])?;
+
+    let mut data = vec![];
+    for packet in packets {
+        let state = &mut states[packet.component as usize][packet.resolution as usize]
+            [packet.precinct as usize];
+
+        let mut contributions = vec![];
+        let mut body = vec![];
+        for (s, code_blocks) in state.code_blocks.iter().enumerate() {
+            for (i, code_block) in code_blocks.iter().enumerate() {
+                let no_passes = code_block.no_passes();
+                if no_passes == 0 {
+                    continue;
+                }
+                let segments = code_block.segments(no_passes);
+                contributions.push(CodeBlockContribution::new(
+                    s,
+                    i,
+                    no_passes,
+                    segments
+                        .iter()
+                        .map(|segment| segment.len() as u32)
+                        .collect(),
+                ));
+                body.extend(segments.concat());
+            }
+        }
+
+        data.extend(
+            state
+                .precinct
+                .encode_packet_header(&contributions, packet.layer)?,
+        );
+        data.extend(body);
+    }
+
+    Ok(data)
+}
+
+// Annex D - Code the code-blocks of every precinct of each resolution level of
+// a tile-component, with M_b magnitude bits for the coefficients of each
+// subband
+fn encode_tile_component<F>(
+    tile_component: &TileComponent,
+    magnitude_bits: F,
+) -> Result<Vec<Vec<PrecinctState>>, Box<dyn error::Error>>
+where
+    F: Fn(SubbandOrientation) -> u8,
+{
+    let mut resolutions = Vec::with_capacity(tile_component.layouts.len());
+    for (r, layout) in tile_component.layouts.iter().enumerate() {
+        let r = r as u8;
+
+        let mut precincts = Vec::with_capacity(layout.precincts().len());
+        for precinct_layout in layout.precincts() {
+            let no_code_blocks: Vec<(u32, u32)> = precinct_layout
+                .subbands()
+                .iter()
+                .map(|subband| subband.no_code_blocks())
+                .collect();
+            let mut precinct = Precinct::with_code_block_style(&no_code_blocks, 0);
+
+            let mut code_blocks = Vec::with_capacity(precinct_layout.subbands().len());
+            for (s, (subband, precinct_subband)) in layout
+                .subbands()
+                .iter()
+                .zip(precinct_layout.subbands())
+                .enumerate()
+            {
+                let orientation = subband.orientation();
+                let magnitude_bits = magnitude_bits(orientation);
+
+                let mut encoded = Vec::with_capacity(precinct_subband.code_blocks().len());
+                for (i, code_block) in precinct_subband.code_blocks().iter().enumerate() {
+                    let region = code_block.region();
+                    let coefficients =
+                        tile_component.code_block(r, orientation, subband.region(), region);
+                    let mut encoder =
+                        CodeBlockEncoder::new(region.width(), region.height(), orientation, 0);
+                    let code_block = encoder.encode(&coefficients, magnitude_bits)?;
+
+                    // Code-blocks without coding passes are never included
+                    let layer = if code_block.no_passes() > 0 {
+                        Some(0)
+                    } else {
+                        None
+                    };
+                    precinct.set_code_block(s, i, layer, code_block.zero_bit_planes());
+                    encoded.push(code_block);
+                }
+                code_blocks.push(encoded);
+            }
+
+            precincts.push(PrecinctState {
+                precinct,
+                code_blocks,
+            });
+        }
+        resolutions.push(precincts);
+    }
+
+    Ok(resolutions)
+}
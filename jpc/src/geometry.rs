@@ -0,0 +1,633 @@
+use std::error;
+
+use super::{
+    CodestreamError, CodingStyleParameters, ImageAndTileSizeMarkerSegment, SubbandOrientation,
+};
+
+/// A rectangle of points from (x0, y0) up to but excluding (x1, y1), on the
+/// reference grid or in the coordinates of a tile-component, resolution level
+/// or subband.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rectangle {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Rectangle {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Rectangle {
+        Rectangle {
+            x0,
+            y0,
+            x1: x1.max(x0),
+            y1: y1.max(y0),
+        }
+    }
+
+    pub fn x0(&self) -> u32 {
+        self.x0
+    }
+
+    pub fn y0(&self) -> u32 {
+        self.y0
+    }
+
+    pub fn x1(&self) -> u32 {
+        self.x1
+    }
+
+    pub fn y1(&self) -> u32 {
+        self.y1
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x0 == self.x1 || self.y0 == self.y1
+    }
+
+    /// The part of this rectangle which is also in the other rectangle.
+    pub fn intersection(&self, other: &Rectangle) -> Rectangle {
+        Rectangle::new(
+            self.x0.max(other.x0),
+            self.y0.max(other.y0),
+            self.x1.min(other.x1),
+            self.y1.min(other.y1),
+        )
+    }
+}
+
+fn ceil_div(a: u32, b: u32) -> u32 {
+    a.div_ceil(b)
+}
+
+// ceil(a / 2^exponent) for exponents up to the 32 decomposition levels of
+// Table A.10, where 2^32 no longer fits in a u32
+fn ceil_div_pow2(a: u32, exponent: u32) -> u32 {
+    let exponent = exponent.min(32);
+    ((a as u64 + (1 << exponent) - 1) >> exponent) as u32
+}
+
+/// Tile t on the reference grid, Equation B-7
+pub fn tile(siz: &ImageAndTileSizeMarkerSegment, t: u32) -> Rectangle {
+    Rectangle::new(
+        siz.tile_x_upper(t),
+        siz.tile_y_upper(t),
+        siz.tile_x_lower(t),
+        siz.tile_y_lower(t),
+    )
+}
+
+/// Tile-component of a tile for a component with the separations XRsiz and
+/// YRsiz, Equation B-12
+pub fn tile_component(
+    tile: &Rectangle,
+    horizontal_separation: u8,
+    vertical_separation: u8,
+) -> Rectangle {
+    let (dx, dy) = (horizontal_separation as u32, vertical_separation as u32);
+    Rectangle::new(
+        ceil_div(tile.x0, dx),
+        ceil_div(tile.y0, dy),
+        ceil_div(tile.x1, dx),
+        ceil_div(tile.y1, dy),
+    )
+}
+
+/// Resolution level r of a tile-component with NL decomposition levels,
+/// Equation B-14
+pub fn resolution(
+    tile_component: &Rectangle,
+    no_decomposition_levels: u8,
+    resolution: u8,
+) -> Rectangle {
+    reduce(tile_component, no_decomposition_levels - resolution)
+}
+
+/// A rectangle with the given number of decomposition levels discarded, that
+/// is at 1/2^levels of its size.
+pub fn reduce(rectangle: &Rectangle, levels: u8) -> Rectangle {
+    let levels = levels as u32;
+    Rectangle::new(
+        ceil_div_pow2(rectangle.x0, levels),
+        ceil_div_pow2(rectangle.y0, levels),
+        ceil_div_pow2(rectangle.x1, levels),
+        ceil_div_pow2(rectangle.y1, levels),
+    )
+}
+
+/// Decomposition level (n_b) of the subbands at resolution level r, the
+/// NLLL subband at resolution level 0 belongs to decomposition level NL.
+pub fn decomposition_level(no_decomposition_levels: u8, resolution: u8) -> u8 {
+    if resolution == 0 {
+        no_decomposition_levels
+    } else {
+        no_decomposition_levels - resolution + 1
+    }
+}
+
+/// Subbands at resolution level r in the order they appear in a packet
+pub fn subband_orientations(resolution: u8) -> &'static [SubbandOrientation] {
+    if resolution == 0 {
+        &[SubbandOrientation::LL]
+    } else {
+        &[
+            SubbandOrientation::HL,
+            SubbandOrientation::LH,
+            SubbandOrientation::HH,
+        ]
+    }
+}
+
+// Position in the tile-component of the first coefficient of a subband at
+// decomposition level n_b, Equation B-15
+fn subband_offset(level: u8, orientation: SubbandOrientation) -> (u32, u32) {
+    if level == 0 {
+        return (0, 0);
+    }
+
+    // Table B.1 - Quantities (xob, yob) for subband b
+    let (xob, yob) = match orientation {
+        SubbandOrientation::LL => (0, 0),
+        SubbandOrientation::HL => (1, 0),
+        SubbandOrientation::LH => (0, 1),
+        SubbandOrientation::HH => (1, 1),
+    };
+    (xob << (level - 1), yob << (level - 1))
+}
+
+/// Subband of a tile-component, Equation B-15
+pub fn subband(
+    tile_component: &Rectangle,
+    no_decomposition_levels: u8,
+    resolution: u8,
+    orientation: SubbandOrientation,
+) -> Rectangle {
+    let level = decomposition_level(no_decomposition_levels, resolution) as u32;
+    if level == 0 {
+        return *tile_component;
+    }
+
+    let (x_offset, y_offset) = subband_offset(level as u8, orientation);
+    Rectangle::new(
+        ceil_div_pow2(tile_component.x0.saturating_sub(x_offset), level),
+        ceil_div_pow2(tile_component.y0.saturating_sub(y_offset), level),
+        ceil_div_pow2(tile_component.x1.saturating_sub(x_offset), level),
+        ceil_div_pow2(tile_component.y1.saturating_sub(y_offset), level),
+    )
+}
+
+/// Number of precincts wide and high of a resolution level with precinct
+/// size exponents PPx and PPy, Equation B-16
+pub fn no_precincts(resolution: &Rectangle, ppx: u8, ppy: u8) -> (u32, u32) {
+    if resolution.is_empty() {
+        return (0, 0);
+    }
+
+    (
+        ceil_div(resolution.x1, 1 << ppx) - (resolution.x0 >> ppx),
+        ceil_div(resolution.y1, 1 << ppy) - (resolution.y0 >> ppy),
+    )
+}
+
+/// Precinct with index p of a resolution level on the grid of its resolution
+/// level, anchored at the origin and clipped to the resolution level, see B.6
+pub fn precinct(resolution: &Rectangle, ppx: u8, ppy: u8, precinct: u32) -> Rectangle {
+    let (wide, _) = no_precincts(resolution, ppx, ppy);
+    let x = (resolution.x0 >> ppx) + precinct % wide;
+    let y = (resolution.y0 >> ppy) + precinct / wide;
+
+    Rectangle::new(x << ppx, y << ppy, (x + 1) << ppx, (y + 1) << ppy).intersection(resolution)
+}
+
+/// Part of a subband at resolution level r covered by precinct p, where
+/// precincts of subbands other than NLLL are half the size in each direction,
+/// see B.6
+pub fn precinct_subband(
+    resolution: &Rectangle,
+    subband: &Rectangle,
+    r: u8,
+    ppx: u8,
+    ppy: u8,
+    precinct: u32,
+) -> Rectangle {
+    let (wide, _) = no_precincts(resolution, ppx, ppy);
+    let x = (resolution.x0 >> ppx) + precinct % wide;
+    let y = (resolution.y0 >> ppy) + precinct / wide;
+
+    let (ppx, ppy) = if r == 0 {
+        (ppx, ppy)
+    } else {
+        (ppx.saturating_sub(1), ppy.saturating_sub(1))
+    };
+    Rectangle::new(x << ppx, y << ppy, (x + 1) << ppx, (y + 1) << ppy).intersection(subband)
+}
+
+/// Precinct width and height exponents (PPx, PPy) of resolution level r,
+/// which are 15 unless defined in the coding style, see A.6.1
+pub fn precinct_size(coding_style_parameters: &CodingStyleParameters, r: u8) -> (u8, u8) {
+    if !coding_style_parameters.has_defined_precinct_size() {
+        return (15, 15);
+    }
+
+    coding_style_parameters
+        .precinct_sizes()
+        .and_then(|sizes| {
+            sizes
+                .get(r as usize)
+                .map(|size| (size.width_exponent(), size.height_exponent()))
+        })
+        .unwrap_or((15, 15))
+}
+
+/// Code-block size exponents at resolution level r, bounded by the precinct
+/// size, Equations B-17 and B-18
+pub fn code_block_size(xcb: u8, ycb: u8, r: u8, ppx: u8, ppy: u8) -> (u8, u8) {
+    if r == 0 {
+        (xcb.min(ppx), ycb.min(ppy))
+    } else {
+        (
+            xcb.min(ppx.saturating_sub(1)),
+            ycb.min(ppy.saturating_sub(1)),
+        )
+    }
+}
+
+/// Code-blocks of an area of a subband in raster order, on the code-block
+/// grid anchored at the origin and clipped to the area, see B.7
+pub fn code_blocks(area: &Rectangle, xcb: u8, ycb: u8) -> (u32, u32, Vec<Rectangle>) {
+    if area.is_empty() {
+        return (0, 0, vec![]);
+    }
+
+    let (x0, x1) = (area.x0 >> xcb, ceil_div(area.x1, 1 << xcb));
+    let (y0, y1) = (area.y0 >> ycb, ceil_div(area.y1, 1 << ycb));
+
+    let mut code_blocks = Vec::with_capacity(((x1 - x0) * (y1 - y0)) as usize);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            code_blocks.push(
+                Rectangle::new(x << xcb, y << ycb, (x + 1) << xcb, (y + 1) << ycb)
+                    .intersection(area),
+            );
+        }
+    }
+    (x1 - x0, y1 - y0, code_blocks)
+}
+
+/// Partition of a resolution level of a tile-component into subbands,
+/// precincts and code-blocks, see B.5 to B.7
+///
+/// The region of the resolution level and of its precincts is on the grid of
+/// the resolution level, and the region of subbands and code-blocks on the
+/// grid of their subband. Each also has the area of the reference grid it
+/// corresponds to, clipped to the tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolutionLayout {
+    resolution: u8,
+    region: Rectangle,
+    reference_grid: Rectangle,
+
+    // Precinct width and height exponents (PPx, PPy)
+    ppx: u8,
+    ppy: u8,
+
+    // Code-block width and height exponents (xcb', ycb'), bounded by the
+    // precinct size
+    xcb: u8,
+    ycb: u8,
+
+    no_precincts_wide: u32,
+    no_precincts_high: u32,
+
+    subbands: Vec<SubbandLayout>,
+    precincts: Vec<PrecinctLayout>,
+}
+
+impl ResolutionLayout {
+    pub fn resolution(&self) -> u8 {
+        self.resolution
+    }
+
+    pub fn region(&self) -> &Rectangle {
+        &self.region
+    }
+
+    pub fn reference_grid(&self) -> &Rectangle {
+        &self.reference_grid
+    }
+
+    pub fn precinct_width_exponent(&self) -> u8 {
+        self.ppx
+    }
+
+    pub fn precinct_height_exponent(&self) -> u8 {
+        self.ppy
+    }
+
+    pub fn code_block_width_exponent(&self) -> u8 {
+        self.xcb
+    }
+
+    pub fn code_block_height_exponent(&self) -> u8 {
+        self.ycb
+    }
+
+    pub fn no_precincts(&self) -> (u32, u32) {
+        (self.no_precincts_wide, self.no_precincts_high)
+    }
+
+    /// Subbands in the order they appear in a packet.
+    pub fn subbands(&self) -> &[SubbandLayout] {
+        &self.subbands
+    }
+
+    /// Precincts in raster order, the order of their index p.
+    pub fn precincts(&self) -> &[PrecinctLayout] {
+        &self.precincts
+    }
+}
+
+/// Subband of a resolution level, see B.5
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubbandLayout {
+    orientation: SubbandOrientation,
+
+    // Decomposition level (n_b)
+    decomposition_level: u8,
+
+    region: Rectangle,
+    reference_grid: Rectangle,
+}
+
+impl SubbandLayout {
+    pub fn orientation(&self) -> SubbandOrientation {
+        self.orientation
+    }
+
+    pub fn decomposition_level(&self) -> u8 {
+        self.decomposition_level
+    }
+
+    pub fn region(&self) -> &Rectangle {
+        &self.region
+    }
+
+    pub fn reference_grid(&self) -> &Rectangle {
+        &self.reference_grid
+    }
+}
+
+/// Precinct of a resolution level, see B.6
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecinctLayout {
+    index: u32,
+    region: Rectangle,
+    reference_grid: Rectangle,
+    subbands: Vec<PrecinctSubbandLayout>,
+}
+
+impl PrecinctLayout {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn region(&self) -> &Rectangle {
+        &self.region
+    }
+
+    pub fn reference_grid(&self) -> &Rectangle {
+        &self.reference_grid
+    }
+
+    /// The parts of the subbands covered by the precinct, in the order they
+    /// appear in a packet.
+    pub fn subbands(&self) -> &[PrecinctSubbandLayout] {
+        &self.subbands
+    }
+}
+
+/// Part of a subband covered by a precinct, with its code-blocks, see B.7
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecinctSubbandLayout {
+    orientation: SubbandOrientation,
+    region: Rectangle,
+    no_code_blocks_wide: u32,
+    no_code_blocks_high: u32,
+    code_blocks: Vec<CodeBlockLayout>,
+}
+
+impl PrecinctSubbandLayout {
+    pub fn orientation(&self) -> SubbandOrientation {
+        self.orientation
+    }
+
+    pub fn region(&self) -> &Rectangle {
+        &self.region
+    }
+
+    pub fn no_code_blocks(&self) -> (u32, u32) {
+        (self.no_code_blocks_wide, self.no_code_blocks_high)
+    }
+
+    /// Code-blocks in raster order, the order they appear in a packet.
+    pub fn code_blocks(&self) -> &[CodeBlockLayout] {
+        &self.code_blocks
+    }
+}
+
+/// Code-block of a subband, see B.7
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlockLayout {
+    region: Rectangle,
+    reference_grid: Rectangle,
+}
+
+impl CodeBlockLayout {
+    pub fn region(&self) -> &Rectangle {
+        &self.region
+    }
+
+    pub fn reference_grid(&self) -> &Rectangle {
+        &self.reference_grid
+    }
+}
+
+// The tile and tile-component a layout is made for, to map the regions of
+// resolution levels and subbands back onto the reference grid
+#[derive(Debug)]
+struct Grid {
+    tile: Rectangle,
+    tile_component: Rectangle,
+    horizontal_separation: u32,
+    vertical_separation: u32,
+}
+
+impl Grid {
+    // A rectangle on a grid at 1/2^level of the tile-component, offset by the
+    // given number of tile-component samples, Equation B-15
+    fn reference_grid(&self, rectangle: &Rectangle, level: u8, offset: (u32, u32)) -> Rectangle {
+        if rectangle.is_empty() {
+            return Rectangle::default();
+        }
+
+        // Up to 32 levels, a coordinate may only fit in a u64 before it is
+        // clipped to the tile-component
+        let (x_offset, y_offset) = offset;
+        let scale = |value: u32, offset: u32| {
+            ((value as u64) << level)
+                .saturating_add(offset as u64)
+                .min(u32::MAX as u64) as u32
+        };
+        let tile_component = Rectangle::new(
+            scale(rectangle.x0, x_offset),
+            scale(rectangle.y0, y_offset),
+            scale(rectangle.x1, x_offset),
+            scale(rectangle.y1, y_offset),
+        )
+        .intersection(&self.tile_component);
+
+        Rectangle::new(
+            tile_component.x0 * self.horizontal_separation,
+            tile_component.y0 * self.vertical_separation,
+            tile_component.x1 * self.horizontal_separation,
+            tile_component.y1 * self.vertical_separation,
+        )
+        .intersection(&self.tile)
+    }
+}
+
+/// Partition resolution level r of component c of tile t into subbands,
+/// precincts and code-blocks, using the coding style parameters of the
+/// tile-component
+pub fn layout(
+    siz: &ImageAndTileSizeMarkerSegment,
+    coding_style_parameters: &CodingStyleParameters,
+    t: u32,
+    c: u16,
+    r: u8,
+) -> Result<ResolutionLayout, Box<dyn error::Error>> {
+    if t >= siz.num_x_tiles() * siz.num_y_tiles() {
+        return Err(CodestreamError::DecodeError {
+            error: format!("tile {} is not in the image", t),
+        }
+        .into());
+    }
+    if c >= siz.no_components() {
+        return Err(CodestreamError::DecodeError {
+            error: format!("component {} is not in the image", c),
+        }
+        .into());
+    }
+    let no_decomposition_levels = coding_style_parameters.no_decomposition_levels();
+    if r > no_decomposition_levels {
+        return Err(CodestreamError::DecodeError {
+            error: format!(
+                "resolution level {} exceeds {} decomposition levels",
+                r, no_decomposition_levels
+            ),
+        }
+        .into());
+    }
+
+    let tile = tile(siz, t);
+    let horizontal_separation = siz.horizontal_separation(c as usize)?;
+    let vertical_separation = siz.vertical_separation(c as usize)?;
+    let grid = Grid {
+        tile,
+        tile_component: tile_component(&tile, horizontal_separation, vertical_separation),
+        horizontal_separation: horizontal_separation as u32,
+        vertical_separation: vertical_separation as u32,
+    };
+
+    let region = resolution(&grid.tile_component, no_decomposition_levels, r);
+    let (ppx, ppy) = precinct_size(coding_style_parameters, r);
+    let (xcb, ycb) = code_block_size(
+        coding_style_parameters.code_block_width().trailing_zeros() as u8,
+        coding_style_parameters.code_block_height().trailing_zeros() as u8,
+        r,
+        ppx,
+        ppy,
+    );
+
+    let level = decomposition_level(no_decomposition_levels, r);
+    let subbands: Vec<SubbandLayout> = subband_orientations(r)
+        .iter()
+        .map(|&orientation| {
+            let region = subband(
+                &grid.tile_component,
+                no_decomposition_levels,
+                r,
+                orientation,
+            );
+            SubbandLayout {
+                orientation,
+                decomposition_level: level,
+                region,
+                reference_grid: grid.reference_grid(
+                    &region,
+                    level,
+                    subband_offset(level, orientation),
+                ),
+            }
+        })
+        .collect();
+
+    let (no_precincts_wide, no_precincts_high) = no_precincts(&region, ppx, ppy);
+    let precincts = (0..no_precincts_wide * no_precincts_high)
+        .map(|p| {
+            let precinct_region = precinct(&region, ppx, ppy, p);
+            PrecinctLayout {
+                index: p,
+                region: precinct_region,
+                reference_grid: grid.reference_grid(
+                    &precinct_region,
+                    no_decomposition_levels - r,
+                    (0, 0),
+                ),
+                subbands: subbands
+                    .iter()
+                    .map(|subband| {
+                        let area = precinct_subband(&region, &subband.region, r, ppx, ppy, p);
+                        let (wide, high, code_blocks) = code_blocks(&area, xcb, ycb);
+                        let offset = subband_offset(level, subband.orientation);
+                        PrecinctSubbandLayout {
+                            orientation: subband.orientation,
+                            region: area,
+                            no_code_blocks_wide: wide,
+                            no_code_blocks_high: high,
+                            code_blocks: code_blocks
+                                .into_iter()
+                                .map(|region| CodeBlockLayout {
+                                    region,
+                                    reference_grid: grid.reference_grid(&region, level, offset),
+                                })
+                                .collect(),
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(ResolutionLayout {
+        resolution: r,
+        region,
+        reference_grid: grid.reference_grid(&region, no_decomposition_levels - r, (0, 0)),
+        ppx,
+        ppy,
+        xcb,
+        ycb,
+        no_precincts_wide,
+        no_precincts_high,
+        subbands,
+        precincts,
+    })
+}
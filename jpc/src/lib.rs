@@ -6,9 +6,22 @@ use std::error;
 use std::fmt;
 use std::io;
 use std::io::prelude::*;
+use std::mem;
 use std::str;
 
-mod coder;
+pub mod code_block;
+pub mod coder;
+mod decoder;
+mod encoder;
+pub mod geometry;
+pub mod packet;
+mod progression;
+pub mod quantization;
+pub mod region_of_interest;
+pub mod registration;
+pub mod transformation;
+pub mod wavelet;
+pub mod writer;
 
 #[derive(Debug)]
 enum CodestreamError {
@@ -37,6 +50,21 @@ enum CodestreamError {
         image_horizontal_offset: u32,
         image_vertical_offset: u32,
     },
+    CodeBlockError {
+        error: String,
+    },
+    PacketError {
+        error: String,
+    },
+    QuantizationError {
+        error: String,
+    },
+    DecodeError {
+        error: String,
+    },
+    EncodeError {
+        error: String,
+    },
 }
 
 impl error::Error for CodestreamError {}
@@ -96,6 +124,21 @@ impl fmt::Display for CodestreamError {
                     reference_tile_height,
                 )
             }
+            Self::CodeBlockError { error } => {
+                write!(f, "code-block error {}", error)
+            }
+            Self::PacketError { error } => {
+                write!(f, "packet error {}", error)
+            }
+            Self::QuantizationError { error } => {
+                write!(f, "quantization error {}", error)
+            }
+            Self::DecodeError { error } => {
+                write!(f, "decode error {}", error)
+            }
+            Self::EncodeError { error } => {
+                write!(f, "encode error {}", error)
+            }
         }
     }
 }
@@ -136,7 +179,7 @@ const MARKER_SYMBOL_EPH: MarkerSymbol = [255, 146]; // End of packet header
 const MARKER_SYMBOL_CRG: MarkerSymbol = [255, 99]; // Component registration
 const MARKER_SYMBOL_COM: MarkerSymbol = [255, 100]; // Comment
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ProgressionOrder {
     // 0000 0000 Layer-resolution level-component-position progression
     LRLCPP,
@@ -168,6 +211,17 @@ impl ProgressionOrder {
             _ => ProgressionOrder::Reserved { value },
         }
     }
+
+    fn value(&self) -> u8 {
+        match self {
+            ProgressionOrder::LRLCPP => 0b0000_0000,
+            ProgressionOrder::RLLCPP => 0b0000_0001,
+            ProgressionOrder::RLPCLP => 0b0000_0010,
+            ProgressionOrder::PCRLLP => 0b0000_0011,
+            ProgressionOrder::CPRLLP => 0b0000_0100,
+            ProgressionOrder::Reserved { value } => *value,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -256,6 +310,25 @@ impl CodingBlockStyle {
     }
 }
 
+// B.5 - Subband orientations
+//
+// Each decomposition level contains the HL, LH and HH subbands, the lowest
+// resolution level contains the single LL subband.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubbandOrientation {
+    // Low-pass horizontally and vertically
+    LL,
+
+    // High-pass horizontally, low-pass vertically
+    HL,
+
+    // Low-pass horizontally, high-pass vertically
+    LH,
+
+    // High-pass horizontally and vertically
+    HH,
+}
+
 // A.13 – Coding style parameter values for the Scod parameter
 #[derive(Debug, PartialEq)]
 pub enum CodingStyleDefault {
@@ -432,6 +505,32 @@ pub struct StartOfTileSegment {
     no_tile_parts: [u8; 1],
 }
 
+impl StartOfTileSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn tile_index(&self) -> u16 {
+        u16::from_be_bytes(self.tile_index)
+    }
+
+    pub fn tile_length(&self) -> u32 {
+        u32::from_be_bytes(self.tile_length)
+    }
+
+    pub fn tile_part_index(&self) -> u8 {
+        self.tile_part_index[0]
+    }
+
+    pub fn no_tile_parts(&self) -> u8 {
+        self.no_tile_parts[0]
+    }
+}
+
 // A.12
 //
 // Coding style default (COD)
@@ -534,6 +633,10 @@ impl CodingStyleComponentSegment {
     pub fn component_coding_style(&self) -> CodingStyleComponent {
         CodingStyleComponent::new(self.coding_style[0])
     }
+
+    pub fn coding_style_parameters(&self) -> &CodingStyleParameters {
+        &self.coding_style_parameters
+    }
 }
 
 #[derive(Debug, Default)]
@@ -543,13 +646,13 @@ pub struct CodingStyleParametersPrecinctSize {
 
 impl CodingStyleParametersPrecinctSize {
     pub fn height_exponent(&self) -> u8 {
-        // 4 LSBs are the precinct width exponent, PPx = value
-        self.value << 4 >> 4
+        // 4 MSBs are the precinct height exponent PPy = value
+        self.value >> 4
     }
 
     pub fn width_exponent(&self) -> u8 {
-        // 4 MSBs are the precinct height exponent PPy = value
-        self.value >> 4
+        // 4 LSBs are the precinct width exponent, PPx = value
+        self.value << 4 >> 4
     }
 }
 
@@ -636,6 +739,7 @@ impl CodingStyleParameters {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum RegionOfInterestStyle {
     ImplicitRegionOfInterest,
     Reserved { value: u8 },
@@ -672,6 +776,29 @@ pub struct RegionOfInterestSegment {
     region_of_interest_style_parameter: [u8; 1],
 }
 
+impl RegionOfInterestSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn component_index(&self) -> u16 {
+        u16::from_be_bytes(self.component_index)
+    }
+
+    pub fn region_of_interest_style(&self) -> RegionOfInterestStyle {
+        RegionOfInterestStyle::new(self.region_of_interest_style[0])
+    }
+
+    /// The ROI shift (s) of the Maxshift method, see Table A.25
+    pub fn region_of_interest_shift(&self) -> u8 {
+        self.region_of_interest_style_parameter[0]
+    }
+}
+
 // A.6.6
 //
 // Progression order change (POC)
@@ -686,6 +813,20 @@ pub struct ProgressionOrderChangeSegment {
     progressions: Vec<CodingStyleComponentSegmentProgression>,
 }
 
+impl ProgressionOrderChangeSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn progressions(&self) -> &[CodingStyleComponentSegmentProgression] {
+        &self.progressions
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct CodingStyleComponentSegmentProgression {
     // RSpoc: Resolution level index (inclusive) for the start of a progression.
@@ -711,13 +852,28 @@ pub struct CodingStyleComponentSegmentProgression {
 }
 
 impl CodingStyleComponentSegmentProgression {
+    pub fn resolution_level_index_start(&self) -> u8 {
+        self.resolution_level_index_start[0]
+    }
+
     pub fn component_index_start(&self) -> u16 {
         u16::from_be_bytes(self.component_index_start)
     }
 
+    pub fn layer_index_end(&self) -> u16 {
+        u16::from_be_bytes(self.layer_index_end)
+    }
+
+    pub fn resolution_level_index_end(&self) -> u8 {
+        self.resolution_level_index_end[0]
+    }
+
+    // A value of 0 for an 8 bit CEpoc is interpreted as 256
     pub fn component_index_end(&self) -> u16 {
-        // TODO: Verify
-        u16::from_be_bytes(self.component_index_end)
+        match u16::from_be_bytes(self.component_index_end) {
+            0 => 256,
+            value => value,
+        }
     }
 
     pub fn progression_order(&self) -> ProgressionOrder {
@@ -765,6 +921,32 @@ pub struct TilePartLengthsSegment {
 }
 
 impl TilePartLengthsSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn index(&self) -> usize {
+        u8::from_be_bytes(self.index) as usize
+    }
+
+    /// Ttlm and Ptlm of each tile-part, where Ttlm is zero when it is not
+    /// signalled
+    pub fn tile_part_lengths(&self) -> Vec<(u16, u32)> {
+        self.tile_part_lengths
+            .iter()
+            .map(|tile_part_length| {
+                (
+                    u16::from_be_bytes(tile_part_length.tile_index),
+                    u32::from_be_bytes(tile_part_length.tile_length),
+                )
+            })
+            .collect()
+    }
+
     fn parameter_sizes(&self) -> Vec<TilePartParameterSize> {
         TilePartParameterSize::new(self.parameter_sizes[0])
     }
@@ -855,8 +1037,7 @@ pub struct PacketLengthSegment {
     // There is one value for each tile-part. If a codestream contains one or
     // more tile-parts exceeding the limitations of PLM markers, these markers
     // shall not be used.
-    no_bytes: [u8; 1],
-
+    //
     // Iplm^ij: Length of the jth packet in the ith tile-part.
     //
     // If packet headers are stored with the packet, this length includes the
@@ -866,12 +1047,26 @@ pub struct PacketLengthSegment {
     //
     // There is one range of values for each tile-part.
     // There is one value for each packet in the tile.
-    packet_length: Vec<u8>,
+    data: Vec<u8>,
 }
 
 impl PacketLengthSegment {
-    fn no_bytes(&self) -> u8 {
-        u8::from_be_bytes(self.no_bytes)
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn index(&self) -> usize {
+        u8::from_be_bytes(self.index) as usize
+    }
+
+    /// The part of the series of Nplm and Iplm parameters in this marker
+    /// segment
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 }
 
@@ -898,7 +1093,7 @@ pub struct TilePacketLength {
     // header length.
     index: [u8; 1],
 
-    // Iplm^i: Length of the ith packet.
+    // Iplt^i: Length of the ith packet.
     //
     // If packet headers are stored with the packet, this length includes the
     // packet header. If packet headers are stored in the PPM or PPT, this
@@ -906,6 +1101,26 @@ pub struct TilePacketLength {
     packet_length: Vec<u8>,
 }
 
+impl TilePacketLength {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn index(&self) -> usize {
+        u8::from_be_bytes(self.index) as usize
+    }
+
+    /// Iplt: The packet lengths in this marker segment, each coded in bytes
+    /// of seven bits with the most significant bit set on all but the last
+    pub fn packet_length(&self) -> &[u8] {
+        &self.packet_length
+    }
+}
+
 // A.7.4
 //
 // Packed packet headers, main header (PPM)
@@ -924,21 +1139,34 @@ pub struct PackedPacketHeaderSegment {
 
     // Nppm^i: Number of bytes of Ippm information for the ith tile-part in the
     // order found in the codestream. One value for each tile-part (not tile).
-    number_of_bytes: [u8; 4],
-
+    //
     // Ippm^ij: Packet header for every packet in order in the tile-part.
     // The contents are exactly the packet header which would have been
     // distributed in the bit stream as described in B.10
+    //
+    // The series of Nppm and Ippm parameters is concatenated in the order of
+    // increasing Zppm, a marker segment may end in the middle of the Ippm
+    // parameters of a tile-part, which continue in the next marker segment.
     data: Vec<u8>,
 }
 
 impl PackedPacketHeaderSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
     pub fn index(&self) -> usize {
         u8::from_be_bytes(self.index) as usize
     }
 
-    pub fn number_of_bytes(&self) -> usize {
-        u32::from_be_bytes(self.number_of_bytes) as usize
+    /// The part of the series of Nppm and Ippm parameters in this marker
+    /// segment
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 }
 
@@ -974,9 +1202,22 @@ pub struct TilePackedPacketHeaderSegment {
 }
 
 impl TilePackedPacketHeaderSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
     pub fn index(&self) -> usize {
         u8::from_be_bytes(self.index) as usize
     }
+
+    /// Ippt: The packet headers in this marker segment
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
 }
 
 // A.9.1
@@ -1019,6 +1260,32 @@ pub struct ComponentRegistrationSegment {
     vertical_offset: Vec<[u8; 2]>,
 }
 
+impl ComponentRegistrationSegment {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    /// Xcrg of component i, in units of 1/65536 of XRsiz
+    pub fn horizontal_offset(&self, i: usize) -> u16 {
+        self.horizontal_offset
+            .get(i)
+            .map(|offset| u16::from_be_bytes(*offset))
+            .unwrap_or(0)
+    }
+
+    /// Ycrg of component i, in units of 1/65536 of YRsiz
+    pub fn vertical_offset(&self, i: usize) -> u16 {
+        self.vertical_offset
+            .get(i)
+            .map(|offset| u16::from_be_bytes(*offset))
+            .unwrap_or(0)
+    }
+}
+
 // A.5.1
 //
 // Image and tile size (SIZ)
@@ -1160,13 +1427,15 @@ impl ImageAndTileSizeMarkerSegment {
     // The number of tiles in the X direction (numXtiles) and the Y direction
     // (numYtiles) is the following
     //
-    // numXtiles = [(Xsiz - XTOsiz) / XTsiz]
-    // numYtiles = [(Ysiz - YTOsiz) / YTsiz]
+    // numXtiles = ⌈(Xsiz - XTOsiz) / XTsiz⌉
+    // numYtiles = ⌈(Ysiz - YTOsiz) / YTsiz⌉
     fn num_x_tiles(&self) -> u32 {
-        (self.reference_grid_width() - self.tile_horizontal_offset()) / self.reference_tile_width()
+        (self.reference_grid_width() - self.tile_horizontal_offset())
+            .div_ceil(self.reference_tile_width())
     }
     fn num_y_tiles(&self) -> u32 {
-        (self.reference_grid_height() - self.tile_vertical_offset()) / self.reference_tile_height()
+        (self.reference_grid_height() - self.tile_vertical_offset())
+            .div_ceil(self.reference_tile_height())
     }
 
     // Let p be the horizontal index of a tile, ranging from 0 to numXtiles -1
@@ -1203,24 +1472,24 @@ impl ImageAndTileSizeMarkerSegment {
         )
     }
 
-    // lower left x corner of the tile
-    // tx_1(p,q) = max(XTOsiz + (p + 1) · XTsiz, XOsiz)
+    // lower right x corner of the tile, which is not part of the tile
+    // tx_1(p,q) = min(XTOsiz + (p + 1) · XTsiz, Xsiz)
     fn tile_x_lower(&self, t: u32) -> u32 {
         cmp::min(
             self.tile_horizontal_offset()
                 + ((self.tile_horizontal_index(t) + 1) * self.reference_tile_width()),
-            self.image_horizontal_offset(),
-        ) - 1
+            self.reference_grid_width(),
+        )
     }
 
-    // lower left y corner of the tile
-    // ty_1(p,q) = max(YTOsiz + (q + 1) · YTsiz, YOsiz)
+    // lower right y corner of the tile, which is not part of the tile
+    // ty_1(p,q) = min(YTOsiz + (q + 1) · YTsiz, Ysiz)
     fn tile_y_lower(&self, t: u32) -> u32 {
         cmp::min(
             self.tile_vertical_offset()
                 + ((self.tile_vertical_index(t) + 1) * self.reference_tile_height()),
-            self.image_vertical_offset(),
-        ) - 1
+            self.reference_grid_height(),
+        )
     }
 
     fn tile_dimensions(&self, t: u32) -> (u32, u32) {
@@ -1278,7 +1547,7 @@ impl CommentMarkerSegment {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum QuantizationStyle {
     No { guard: u8 },
     ScalarDerived { guard: u8 },
@@ -1331,10 +1600,8 @@ impl QuantizationValue {
 
     fn mantissa(&self) -> u16 {
         match &self {
-            QuantizationValue::Reversible { value: _value } => {
-                // should't exist?
-                panic!();
-            }
+            // Without quantization only the exponent is signalled
+            QuantizationValue::Reversible { value: _value } => 0,
             // discard 5 most significant bits
             QuantizationValue::Irreversible { value } => {
                 u16::from_be_bytes([value[0] << 5 >> 5, value[1]])
@@ -1384,6 +1651,10 @@ impl QuantizationDefaultMarkerSegment {
     pub fn quantization_exponents(&self) -> Vec<u8> {
         self.values.iter().map(|e| e.exponent()).collect()
     }
+
+    pub fn quantization_mantissas(&self) -> Vec<u16> {
+        self.values.iter().map(|e| e.mantissa()).collect()
+    }
 }
 
 // A.6.5
@@ -1425,6 +1696,24 @@ impl QuantizationComponentSegment {
     pub fn quantization_style(&self) -> QuantizationStyle {
         QuantizationStyle::new(self.quantization_style[0])
     }
+
+    pub fn quantization_values(&self) -> Vec<u16> {
+        self.quantization_values.iter().map(|e| e.value()).collect()
+    }
+
+    pub fn quantization_exponents(&self) -> Vec<u8> {
+        self.quantization_values
+            .iter()
+            .map(|e| e.exponent())
+            .collect()
+    }
+
+    pub fn quantization_mantissas(&self) -> Vec<u16> {
+        self.quantization_values
+            .iter()
+            .map(|e| e.mantissa())
+            .collect()
+    }
 }
 
 // Contiguous Codestream
@@ -1556,11 +1845,13 @@ impl ContiguousCodestream {
         reader: &mut R,
     ) -> Result<StartOfTileSegment, Box<dyn error::Error>> {
         info!("SOT start at byte offset {}", reader.stream_position()? - 2);
-        let mut segment = StartOfTileSegment::default();
+        let mut segment = StartOfTileSegment {
+            offset: reader.stream_position()?,
+            ..Default::default()
+        };
 
         // LSot
-        let mut marker_segment_length: [u8; 2] = [0; 2];
-        reader.read_exact(&mut marker_segment_length)?;
+        segment.length = self.decode_length(reader)?;
 
         // ISot
         reader.read_exact(&mut segment.tile_index)?;
@@ -1710,10 +2001,10 @@ impl ContiguousCodestream {
         };
 
         // The number of progression changes can be derived from the length of the
-        // marker segment.
-        let no_progression_order_change = match no_components < 256 {
-            true => segment.length - 2 - 7,
-            false => segment.length - 2 - 9,
+        // marker segment, with 7 or 9 bytes per progression depending on Csiz.
+        let no_progression_order_change = match no_components < 257 {
+            true => segment.length.saturating_sub(2) / 7,
+            false => segment.length.saturating_sub(2) / 9,
         };
 
         segment.progressions = Vec::with_capacity(no_progression_order_change as usize);
@@ -1751,19 +2042,21 @@ impl ContiguousCodestream {
         info!("PPM start at byte offset {}", reader.stream_position()? - 2);
         let offset = reader.stream_position()?;
         let length = self.decode_length(reader)?;
+        if length < 3 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_PPM,
+                error: format!("length {} is too short", length),
+            }
+            .into());
+        }
         let mut segment = PackedPacketHeaderSegment {
             offset,
             length,
             index: [0],
-            number_of_bytes: [0; 4],
-            // TODO: It is possible that the next PPM marker segment will not
-            // have an Nppm parameter after Zppm, but the continuation of the
-            // Ippm series from the last PPM marker segment.
-            data: vec![0; (length as usize) - 7],
+            data: vec![0; (length as usize) - 3],
         };
 
         reader.read_exact(&mut segment.index)?;
-        reader.read_exact(&mut segment.number_of_bytes)?;
         reader.read_exact(&mut segment.data)?;
         info!("PPM end at byte offset {}", reader.stream_position()?);
 
@@ -1777,6 +2070,13 @@ impl ContiguousCodestream {
         info!("PPT start at byte offset {}", reader.stream_position()? - 2);
         let offset = reader.stream_position()?;
         let length = self.decode_length(reader)?;
+        if length < 3 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_PPT,
+                error: format!("length {} is too short", length),
+            }
+            .into());
+        }
         let mut segment = TilePackedPacketHeaderSegment {
             offset,
             length,
@@ -1802,6 +2102,7 @@ impl ContiguousCodestream {
             length: self.decode_length(reader)?,
             ..Default::default()
         };
+        reader.read_exact(&mut segment.index)?;
         reader.read_exact(&mut segment.parameter_sizes)?;
 
         let parameter_sizes = segment.parameter_sizes();
@@ -1819,31 +2120,23 @@ impl ContiguousCodestream {
         }
 
         // number of tile lengths
-        let no_tile_part_lengths = (segment.length - 4) / tile_part_size;
+        let no_tile_part_lengths = segment.length.saturating_sub(4) / tile_part_size;
 
         for _ in 0..no_tile_part_lengths {
             let mut tile_part_length = TilePartLength::default();
 
-            // Ttlm
+            // Ttlm, right aligned in the two bytes of the tile index
             if parameter_sizes.contains(&TilePartParameterSize::Ttlm8Bit) {
-                reader
-                    .take(1)
-                    .read_exact(&mut tile_part_length.tile_index)?;
+                reader.read_exact(&mut tile_part_length.tile_index[1..])?;
             } else if parameter_sizes.contains(&TilePartParameterSize::Ttlm16Bit) {
-                reader
-                    .take(2)
-                    .read_exact(&mut tile_part_length.tile_index)?;
+                reader.read_exact(&mut tile_part_length.tile_index)?;
             }
 
-            // Ptlm
+            // Ptlm, right aligned in the four bytes of the tile-part length
             if parameter_sizes.contains(&TilePartParameterSize::Ptlm16Bit) {
-                reader
-                    .take(2)
-                    .read_exact(&mut tile_part_length.tile_length)?;
+                reader.read_exact(&mut tile_part_length.tile_length[2..])?;
             } else if parameter_sizes.contains(&TilePartParameterSize::Ptlm32Bit) {
-                reader
-                    .take(4)
-                    .read_exact(&mut tile_part_length.tile_length)?;
+                reader.read_exact(&mut tile_part_length.tile_length)?;
             }
             segment.tile_part_lengths.push(tile_part_length);
         }
@@ -1855,16 +2148,31 @@ impl ContiguousCodestream {
     fn decode_quantization_values<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
+        marker: MarkerSymbol,
         quantization_style: QuantizationStyle,
-        no_decomposition_levels: u8,
+        no_bytes: u16,
     ) -> Result<Vec<QuantizationValue>, Box<dyn error::Error>> {
-        // Decomposition levels are divided into subbands. These include the HL, LH, and HH subbands of the same two dimensional subband decomposition. For the last decomposition level the LL subband is also included.
-        let no_subbands = no_decomposition_levels * 3 + 1;
+        // Without quantization each value is a single byte exponent, with
+        // scalar quantization each value is a two byte exponent and mantissa.
+        // Derived quantization only signals the value for the NLLL subband,
+        // expounded quantization signals one value for each subband.
+        let no_values = match quantization_style {
+            QuantizationStyle::No { guard: _ } => no_bytes,
+            QuantizationStyle::ScalarDerived { guard: _ } => 1,
+            QuantizationStyle::ScalarExpounded { guard: _ } => no_bytes / 2,
+            QuantizationStyle::Reserved { value } => {
+                return Err(CodestreamError::MarkerError {
+                    marker,
+                    error: format!("reserved quantization style {:08b}", value),
+                }
+                .into());
+            }
+        };
 
         let mut quantization_values: Vec<QuantizationValue> =
-            Vec::with_capacity(no_subbands as usize);
+            Vec::with_capacity(no_values as usize);
 
-        for _ in 0..no_subbands {
+        for _ in 0..no_values {
             match quantization_style {
                 // Reversible transformation values
                 QuantizationStyle::No { guard: _ } => {
@@ -1875,17 +2183,13 @@ impl ContiguousCodestream {
                     quantization_values.push(quantization_value);
                 }
                 // Irreversible transformation values
-                QuantizationStyle::ScalarExpounded { guard: _ }
-                | QuantizationStyle::ScalarDerived { guard: _ } => {
+                _ => {
                     let mut value: [u8; 2] = [0; 2];
                     reader.read_exact(&mut value)?;
 
                     let quantization_value = QuantizationValue::Irreversible { value };
                     quantization_values.push(quantization_value);
                 }
-                QuantizationStyle::Reserved { value: _value } => {
-                    todo!()
-                }
             }
         }
 
@@ -1902,19 +2206,15 @@ impl ContiguousCodestream {
             ..Default::default()
         };
 
+        // Sqcd
         reader.read_exact(&mut segment.quantization_style)?;
 
-        let no_decomposition_levels = match segment.quantization_style() {
-            QuantizationStyle::No { guard: _ } => (segment.length() - 4) / 3,
-            QuantizationStyle::ScalarDerived { guard: _ } => 5,
-            QuantizationStyle::ScalarExpounded { guard: _ } => (segment.length() - 5) / 6,
-            _ => panic!(),
-        } as u8;
-
+        // SPqcd, the remainder after Lqcd and Sqcd
         segment.values = self.decode_quantization_values(
             reader,
+            MARKER_SYMBOL_QCD,
             segment.quantization_style(),
-            no_decomposition_levels,
+            segment.length().saturating_sub(3),
         )?;
         info!("QCD end at byte offset {}", reader.stream_position()?);
 
@@ -1939,23 +2239,14 @@ impl ContiguousCodestream {
         // Sqcc
         reader.read_exact(&mut segment.quantization_style)?;
 
-        let mut no_decomposition_levels = match segment.quantization_style() {
-            QuantizationStyle::No { guard: _ } => (segment.length() - 5) / 3,
-            QuantizationStyle::ScalarDerived { guard: _ } => 6,
-            QuantizationStyle::ScalarExpounded { guard: _ } => (segment.length() - 6) / 6,
-            _ => panic!(),
-        } as u8;
-
-        if no_components >= 257 {
-            no_decomposition_levels += 1;
-        }
-
-        // SPqcc
-
+        // SPqcc, the remainder after Lqcc, Cqcc (one byte, or two bytes with
+        // 257 or more components) and Sqcc
+        let no_header_bytes = if no_components < 257 { 4 } else { 5 };
         segment.quantization_values = self.decode_quantization_values(
             reader,
+            MARKER_SYMBOL_QCC,
             segment.quantization_style(),
-            no_decomposition_levels,
+            segment.length().saturating_sub(no_header_bytes),
         )?;
         info!("QCC end at byte offset {}", reader.stream_position()?);
 
@@ -1967,73 +2258,55 @@ impl ContiguousCodestream {
         reader: &mut R,
     ) -> Result<PacketLengthSegment, Box<dyn error::Error>> {
         info!("PLM start at byte offset {}", reader.stream_position()? - 2);
+        let offset = reader.stream_position()?;
+        let length = self.decode_length(reader)?;
+        if length < 3 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_PLM,
+                error: format!("length {} is too short", length),
+            }
+            .into());
+        }
         let mut segment = PacketLengthSegment {
-            offset: reader.stream_position()?,
-            length: self.decode_length(reader)?,
-            ..Default::default()
+            offset,
+            length,
+            index: [0],
+            data: vec![0; (length as usize) - 3],
         };
 
+        // The series of Nplm and Iplm parameters may continue in the next PLM
+        // marker segment, so it is kept as it is
         reader.read_exact(&mut segment.index)?;
-        reader.read_exact(&mut segment.no_bytes)?;
-
-        segment.packet_length = Vec::with_capacity(segment.no_bytes() as usize);
-
-        // TODO: Handle multiple PLM where the next PLM is missing
-        // Nplm and is a continuation of previous Iplm
-        self.decode_packet_length(reader, &mut segment.packet_length)?;
+        reader.read_exact(&mut segment.data)?;
 
         info!("PLM end at byte offset {}", reader.stream_position()?);
 
         Ok(segment)
     }
 
-    fn decode_packet_length<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-        vec: &mut Vec<u8>,
-    ) -> Result<(), Box<dyn error::Error>> {
-        let mut packet_length: [u8; 1] = [0; 1];
-        loop {
-            reader.read_exact(&mut packet_length)?;
-            match packet_length[0] >> 7 {
-                // 0xxx xxxx - Last 7 bits of packet length, terminate number
-                0 => {
-                    vec.push((packet_length[0] << 1) >> 1);
-                    break;
-                }
-                // 1xxx xxxx - Continue reading
-                _ => {
-                    // These are not the last 7 bits that make up the packet
-                    // length. Instead, these 7 bits are a portion of those that
-                    // make up the packet length.
-                    //
-                    // The packet length has been broken into 7-bit segments
-                    // which are sent in order from the most significant segment
-                    // to the least significant segment.
-                    //
-                    // Furthermore, the bits in the most significant segment
-                    // are right justified to the byte boundary.
-                    vec.push((packet_length[0] << 1) >> 2);
-                }
-            }
-        }
-        Ok(())
-    }
-
     fn decode_plt<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
     ) -> Result<TilePacketLength, Box<dyn error::Error>> {
         info!("PLT start at byte offset {}", reader.stream_position()? - 2);
+        let offset = reader.stream_position()?;
+        let length = self.decode_length(reader)?;
+        if length < 3 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_PLT,
+                error: format!("length {} is too short", length),
+            }
+            .into());
+        }
         let mut segment = TilePacketLength {
-            offset: reader.stream_position()?,
-            length: self.decode_length(reader)?,
-            ..Default::default()
+            offset,
+            length,
+            index: [0],
+            packet_length: vec![0; (length as usize) - 3],
         };
 
         reader.read_exact(&mut segment.index)?;
-
-        self.decode_packet_length(reader, &mut segment.packet_length)?;
+        reader.read_exact(&mut segment.packet_length)?;
 
         info!("PLT end at byte offset {}", reader.stream_position()?);
 
@@ -2131,6 +2404,9 @@ pub struct Header {
 
     // COM (Optional, repeatable)
     comment_marker_segments: Vec<CommentMarkerSegment>,
+
+    // The markers of the marker segments after SIZ in the order found
+    markers: Vec<MarkerSymbol>,
 }
 
 impl Header {
@@ -2217,6 +2493,54 @@ impl Header {
         &self.packed_packet_headers
     }
 
+    // A.7.4 - The packet headers of every tile-part in the order found in the
+    // codestream, from the series of Nppm and Ippm parameters of the PPM
+    // marker segments in the order of Zppm
+    fn tile_part_packet_headers(&self) -> Result<Vec<Vec<u8>>, Box<dyn error::Error>> {
+        let mut segments: Vec<&PackedPacketHeaderSegment> =
+            self.packed_packet_headers.iter().collect();
+        segments.sort_by_key(|segment| segment.index());
+        let data: Vec<u8> = segments
+            .iter()
+            .flat_map(|segment| segment.data.iter().copied())
+            .collect();
+
+        let mut packet_headers = vec![];
+        let mut position = 0;
+        while position < data.len() {
+            let number_of_bytes = match data.get(position..position + 4) {
+                Some(bytes) => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                None => {
+                    return Err(CodestreamError::MarkerError {
+                        marker: MARKER_SYMBOL_PPM,
+                        error: format!("Nppm of tile-part {} is cut short", packet_headers.len()),
+                    }
+                    .into());
+                }
+            };
+            position += 4;
+
+            let end = position + number_of_bytes as usize;
+            match data.get(position..end) {
+                Some(bytes) => packet_headers.push(bytes.to_vec()),
+                None => {
+                    return Err(CodestreamError::MarkerError {
+                        marker: MARKER_SYMBOL_PPM,
+                        error: format!(
+                            "Ippm of tile-part {} is shorter than Nppm {}",
+                            packet_headers.len(),
+                            number_of_bytes
+                        ),
+                    }
+                    .into());
+                }
+            }
+            position = end;
+        }
+
+        Ok(packet_headers)
+    }
+
     /// Component registration (CRG) segment
     ///
     /// Allows specific registration of components with respect to each other.
@@ -2232,7 +2556,103 @@ impl Header {
 }
 
 // Many images have multiple components. This specification has a multiple component transformation to decorrelate threecomponents. This is the only function in this specification that relates components to each other
-struct Image {}
+#[derive(Debug)]
+pub struct Image {
+    area: ImageArea,
+    components: Vec<Component>,
+    concealed_code_blocks: Vec<ConcealedCodeBlock>,
+}
+
+impl Image {
+    /// The image area on the reference grid
+    pub fn area(&self) -> &ImageArea {
+        &self.area
+    }
+
+    pub fn width(&self) -> u32 {
+        self.area.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.area.height()
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    /// The code-blocks of which corrupt coding passes were discarded when
+    /// decoding resiliently
+    pub fn concealed_code_blocks(&self) -> &[ConcealedCodeBlock] {
+        &self.concealed_code_blocks
+    }
+}
+
+/// A code-block of which only the first coding passes were decoded, because
+/// the later coding passes were found to be corrupt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConcealedCodeBlock {
+    tile: u16,
+    component: u16,
+    resolution: u8,
+    orientation: SubbandOrientation,
+
+    // Position of the code-block in its subband
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+
+    no_passes: u8,
+    no_passes_decoded: u8,
+}
+
+impl ConcealedCodeBlock {
+    /// Index of the tile of the code-block
+    pub fn tile(&self) -> u16 {
+        self.tile
+    }
+
+    pub fn component(&self) -> u16 {
+        self.component
+    }
+
+    pub fn resolution(&self) -> u8 {
+        self.resolution
+    }
+
+    pub fn orientation(&self) -> SubbandOrientation {
+        self.orientation
+    }
+
+    /// Horizontal position of the code-block in its subband
+    pub fn x0(&self) -> u32 {
+        self.x0
+    }
+
+    /// Vertical position of the code-block in its subband
+    pub fn y0(&self) -> u32 {
+        self.y0
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of coding passes included in the codestream
+    pub fn no_passes(&self) -> u8 {
+        self.no_passes
+    }
+
+    /// Number of coding passes decoded before the first corrupt one
+    pub fn no_passes_decoded(&self) -> u8 {
+        self.no_passes_decoded
+    }
+}
 
 // The image components may be divided into tiles.
 //
@@ -2265,41 +2685,131 @@ struct Image {}
 // selected subset of these subbands.
 #[derive(Debug, Default)]
 struct Tile {
+    // Isot: Tile index
+    index: u16,
+
+    // The tile-part headers of the tile merged together
     header: TileHeader,
-    parts: Vec<u8>,
+
+    // Tile-parts of the tile in the order of TPsot
+    parts: Vec<TilePart>,
+}
+
+impl Tile {
+    // TNsot of the tile, which is signalled by any of its tile-parts, or
+    // none when every tile-part leaves it to be 0
+    fn no_tile_parts(&self) -> Option<u8> {
+        self.parts
+            .iter()
+            .map(|part| part.start_of_tile_segment.no_tile_parts())
+            .find(|no_tile_parts| *no_tile_parts != 0)
+    }
+
+    // The packets of a tile are the concatenation of the data of its
+    // tile-parts, see B.10
+    fn data(&self) -> Vec<u8> {
+        self.parts
+            .iter()
+            .flat_map(|part| part.data.iter().copied())
+            .collect()
+    }
+
+    // A.7.4 and A.7.5 - The packed packet headers of a tile, which are the
+    // concatenation of those of its tile-parts, or none when the packet
+    // headers are in the tile-part data
+    fn packet_headers(&self) -> Result<Option<Vec<u8>>, Box<dyn error::Error>> {
+        if self.parts.iter().all(|part| part.packet_headers.is_none()) {
+            return Ok(None);
+        }
+
+        // The packet headers shall be in only one of three places
+        if self
+            .parts
+            .iter()
+            .any(|part| part.packet_headers.is_none() && !part.data.is_empty())
+        {
+            return Err(CodestreamError::PacketError {
+                error: format!(
+                    "packet headers of tile {} are both packed and in the tile-part data",
+                    self.index
+                ),
+            }
+            .into());
+        }
+
+        Ok(Some(
+            self.parts
+                .iter()
+                .flat_map(|part| part.packet_headers.iter().flatten().copied())
+                .collect(),
+        ))
+    }
 }
 
 #[derive(Debug, Default)]
-struct TileHeader {
+struct TilePart {
     // SOT (Required)
     start_of_tile_segment: StartOfTileSegment,
 
+    // Packet headers and bodies following the SOD marker
+    data: Vec<u8>,
+
+    // Packet headers of the tile-part packed in PPM or PPT marker segments,
+    // in which case the data only has the packet bodies
+    packet_headers: Option<Vec<u8>>,
+
+    // The markers of the marker segments of the tile-part header in the order
+    // found, which are merged into the header of the tile
+    markers: Vec<MarkerSymbol>,
+}
+
+// A.4 - The COD, COC, QCD, QCC and RGN marker segments are only allowed in
+// the first tile-part header of a tile, while the POC, PPT, PLT and COM marker
+// segments are allowed in any tile-part header.
+#[derive(Debug, Default)]
+struct TileHeader {
     // COD (Optional)
-    coding_style_marker_segment: CodingStyleMarkerSegment,
+    coding_style_marker_segment: Option<CodingStyleMarkerSegment>,
 
-    // COC (Optional)
-    coding_style_component_segment: CodingStyleComponentSegment,
+    // COC (Optional, no more than one COC per component)
+    coding_style_component_segments: Vec<CodingStyleComponentSegment>,
 
     // QCD (Optional)
-    quantization_default_marker_segment: QuantizationDefaultMarkerSegment,
+    quantization_default_marker_segment: Option<QuantizationDefaultMarkerSegment>,
 
-    // QCC (Optional)
-    quantization_component_segment: QuantizationComponentSegment,
+    // QCC (Optional, no more than one QCC per component)
+    quantization_component_segments: Vec<QuantizationComponentSegment>,
 
     // RGN (Optional)
     regions: Vec<RegionOfInterestSegment>,
 
-    // POC (Required)
-    progression_order_change: ProgressionOrderChangeSegment,
+    // POC (Optional)
+    progression_order_changes: Vec<ProgressionOrderChangeSegment>,
 
     // PPT (Optional)
-    packed_packet_headers: Option<TilePackedPacketHeaderSegment>,
+    packed_packet_headers: Vec<TilePackedPacketHeaderSegment>,
 
     // PLT (Optional)
-    packet_lengths: Vec<PacketLengthSegment>,
+    packet_lengths: Vec<TilePacketLength>,
 
     // COM (Optional)
-    comment_marker_segment: Option<CommentMarkerSegment>,
+    comment_marker_segments: Vec<CommentMarkerSegment>,
+
+    // The markers of the marker segments in the order found
+    markers: Vec<MarkerSymbol>,
+}
+
+impl TileHeader {
+    // Add the marker segments of a later tile-part header of the same tile
+    fn append(&mut self, mut other: TileHeader) {
+        self.progression_order_changes
+            .append(&mut other.progression_order_changes);
+        self.packed_packet_headers
+            .append(&mut other.packed_packet_headers);
+        self.packet_lengths.append(&mut other.packet_lengths);
+        self.comment_marker_segments
+            .append(&mut other.comment_marker_segments);
+    }
 }
 
 impl ContiguousCodestream {
@@ -2347,84 +2857,86 @@ impl ContiguousCodestream {
 
         loop {
             match reader.read_exact(&mut marker_type) {
-                Ok(_) => match marker_type {
-                    // COC (Optional, no more than one COC per component)
-                    MARKER_SYMBOL_COC => {
-                        header
-                            .coding_style_component_segment
-                            .push(self.decode_coc(reader, no_components)?);
-                    }
-                    // QCD (Required)
-                    MARKER_SYMBOL_QCD => {
-                        header.quantization_default_marker_segment = Some(self.decode_qcd(reader)?);
-                    }
+                Ok(_) => {
+                    match marker_type {
+                        // COC (Optional, no more than one COC per component)
+                        MARKER_SYMBOL_COC => {
+                            header
+                                .coding_style_component_segment
+                                .push(self.decode_coc(reader, no_components)?);
+                        }
+                        // QCD (Required)
+                        MARKER_SYMBOL_QCD => {
+                            header.quantization_default_marker_segment =
+                                Some(self.decode_qcd(reader)?);
+                        }
 
-                    // COD (Required)
-                    MARKER_SYMBOL_COD => {
-                        header.coding_style_marker_segment = Some(self.decode_cod(reader)?);
-                    }
+                        // COD (Required)
+                        MARKER_SYMBOL_COD => {
+                            header.coding_style_marker_segment = Some(self.decode_cod(reader)?);
+                        }
 
-                    // QCC (Optional, no more than one QCC per component)
-                    MARKER_SYMBOL_QCC => {
-                        header
-                            .quantization_component_segments
-                            .push(self.decode_qcc(reader, no_components)?);
-                    }
+                        // QCC (Optional, no more than one QCC per component)
+                        MARKER_SYMBOL_QCC => {
+                            header
+                                .quantization_component_segments
+                                .push(self.decode_qcc(reader, no_components)?);
+                        }
 
-                    // RGN (Optional, no more than one RGN per component)
-                    MARKER_SYMBOL_RGN => {
-                        header.regions.push(self.decode_rgn(reader, no_components)?);
-                    }
+                        // RGN (Optional, no more than one RGN per component)
+                        MARKER_SYMBOL_RGN => {
+                            header.regions.push(self.decode_rgn(reader, no_components)?);
+                        }
 
-                    // POC (Required in main or tile for any progression order changes)
-                    MARKER_SYMBOL_POC => {
-                        header.progression_order_change =
-                            Some(self.decode_poc(reader, no_components)?);
-                    }
+                        // POC (Required in main or tile for any progression order changes)
+                        MARKER_SYMBOL_POC => {
+                            header.progression_order_change =
+                                Some(self.decode_poc(reader, no_components)?);
+                        }
 
-                    // PPM (Optional, either PPM or PPT or codestream packet headers required)
-                    MARKER_SYMBOL_PPM => {
-                        // TODO: If the PPM marker segment is present, all the packet headers shall be found in the
-                        // main header.
-                        header.packed_packet_headers.push(self.decode_ppm(reader)?);
-                    }
+                        // PPM (Optional, either PPM or PPT or codestream packet headers required)
+                        MARKER_SYMBOL_PPM => {
+                            header.packed_packet_headers.push(self.decode_ppm(reader)?);
+                        }
 
-                    // TLM (Optional)
-                    MARKER_SYMBOL_TLM => {
-                        header.tile_part_lengths = Some(self.decode_tlm(reader)?);
-                    }
+                        // TLM (Optional)
+                        MARKER_SYMBOL_TLM => {
+                            header.tile_part_lengths = Some(self.decode_tlm(reader)?);
+                        }
 
-                    // PLM (Optional)
-                    MARKER_SYMBOL_PLM => {
-                        let packet_length = self.decode_plm(reader)?;
-                        header.packet_lengths.push(packet_length);
-                    }
+                        // PLM (Optional)
+                        MARKER_SYMBOL_PLM => {
+                            let packet_length = self.decode_plm(reader)?;
+                            header.packet_lengths.push(packet_length);
+                        }
 
-                    // CRG (Optional)
-                    MARKER_SYMBOL_CRG => {
-                        header.component_registration =
-                            Some(self.decode_crg(reader, no_components)?);
-                    }
+                        // CRG (Optional)
+                        MARKER_SYMBOL_CRG => {
+                            header.component_registration =
+                                Some(self.decode_crg(reader, no_components)?);
+                        }
 
-                    // COM (Optional)
-                    MARKER_SYMBOL_COM => {
-                        let comment_marker_segment = self.decode_com(reader)?;
-                        header.comment_marker_segments.push(comment_marker_segment);
-                    }
+                        // COM (Optional)
+                        MARKER_SYMBOL_COM => {
+                            let comment_marker_segment = self.decode_com(reader)?;
+                            header.comment_marker_segments.push(comment_marker_segment);
+                        }
 
-                    // Start of tile bit-stream
-                    MARKER_SYMBOL_SOT => {
-                        reader.seek(io::SeekFrom::Current(-2))?;
-                        break;
-                    }
-                    _ => {
-                        return Err(CodestreamError::MarkerUnexpected {
-                            marker: marker_type,
-                            offset: reader.stream_position()? - 2,
+                        // Start of tile bit-stream
+                        MARKER_SYMBOL_SOT => {
+                            reader.seek(io::SeekFrom::Current(-2))?;
+                            break;
+                        }
+                        _ => {
+                            return Err(CodestreamError::MarkerUnexpected {
+                                marker: marker_type,
+                                offset: reader.stream_position()? - 2,
+                            }
+                            .into());
                         }
-                        .into());
                     }
-                },
+                    header.markers.push(marker_type);
+                }
                 Err(e) => return Err(e.into()),
             }
         }
@@ -2488,128 +3000,237 @@ impl ContiguousCodestream {
         Ok(header)
     }
 
-    // A.4 – Construction of the first tile-part header of a given tile
-    fn decode_first_tile_header<R: io::Read + io::Seek>(
+    // A.4 - Construction of a tile-part header, following its SOT marker
+    // segment, up to but excluding the SOD marker
+    fn decode_tile_part_header<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
         no_components: u16,
+        first_tile_part: bool,
     ) -> Result<TileHeader, Box<dyn error::Error>> {
         let mut tile_header = TileHeader::default();
 
         let mut marker_type: MarkerSymbol = [0; 2];
 
-        reader.read_exact(&mut marker_type)?;
-
-        // SOT (Required as the first marker segment of every tile-part header)
-        if marker_type != MARKER_SYMBOL_SOT {
-            return Err(CodestreamError::MarkerUnexpected {
-                marker: MARKER_SYMBOL_SOT,
-                offset: reader.stream_position()? - 2,
+        loop {
+            reader.read_exact(&mut marker_type)?;
+
+            // COD, COC, QCD, QCC and RGN (Only in the first tile-part header)
+            if !first_tile_part
+                && [
+                    MARKER_SYMBOL_COD,
+                    MARKER_SYMBOL_COC,
+                    MARKER_SYMBOL_QCD,
+                    MARKER_SYMBOL_QCC,
+                    MARKER_SYMBOL_RGN,
+                ]
+                .contains(&marker_type)
+            {
+                return Err(CodestreamError::MarkerUnexpected {
+                    marker: marker_type,
+                    offset: reader.stream_position()? - 2,
+                }
+                .into());
             }
-            .into());
-        }
 
-        tile_header.start_of_tile_segment = self.decode_sot(reader)?;
+            match marker_type {
+                // COD (Optional)
+                MARKER_SYMBOL_COD => {
+                    tile_header.coding_style_marker_segment = Some(self.decode_cod(reader)?);
+                }
 
-        loop {
-            match reader.read_exact(&mut marker_type) {
-                Ok(_) => match marker_type {
-                    // COD (Optional)
-                    MARKER_SYMBOL_COD => {
-                        tile_header.coding_style_marker_segment = self.decode_cod(reader)?;
-                    }
+                // COC (Optional)
+                MARKER_SYMBOL_COC => {
+                    tile_header
+                        .coding_style_component_segments
+                        .push(self.decode_coc(reader, no_components)?);
+                }
 
-                    // COC (Optional)
-                    MARKER_SYMBOL_COC => {
-                        tile_header.coding_style_component_segment =
-                            self.decode_coc(reader, no_components)?;
-                    }
+                // QCD (Optional)
+                MARKER_SYMBOL_QCD => {
+                    tile_header.quantization_default_marker_segment =
+                        Some(self.decode_qcd(reader)?);
+                }
 
-                    // QCD (Optional)
-                    MARKER_SYMBOL_QCD => {
-                        tile_header.quantization_default_marker_segment =
-                            self.decode_qcd(reader)?;
-                    }
+                // QCC (Optional)
+                MARKER_SYMBOL_QCC => {
+                    tile_header
+                        .quantization_component_segments
+                        .push(self.decode_qcc(reader, no_components)?);
+                }
 
-                    // QCC (Optional)
-                    MARKER_SYMBOL_QCC => {
-                        tile_header.quantization_component_segment =
-                            self.decode_qcc(reader, no_components)?;
-                    }
+                // RGN (Optional)
+                MARKER_SYMBOL_RGN => {
+                    tile_header
+                        .regions
+                        .push(self.decode_rgn(reader, no_components)?);
+                }
 
-                    // RGN (Optional)
-                    MARKER_SYMBOL_RGN => {
-                        tile_header
-                            .regions
-                            .push(self.decode_rgn(reader, no_components)?);
-                    }
+                // POC (Optional)
+                MARKER_SYMBOL_POC => {
+                    tile_header
+                        .progression_order_changes
+                        .push(self.decode_poc(reader, no_components)?);
+                }
 
-                    // POC (Optional)
-                    MARKER_SYMBOL_POC => {
-                        tile_header.progression_order_change =
-                            self.decode_poc(reader, no_components)?;
-                    }
+                // PPT (Optional)
+                MARKER_SYMBOL_PPT => {
+                    tile_header
+                        .packed_packet_headers
+                        .push(self.decode_ppt(reader)?);
+                }
 
-                    // PPT (Optional)
-                    MARKER_SYMBOL_PPT => {
-                        // The packet headers shall be in only one of three places within the codestream. If the PPM
-                        // marker segment is present, all the packet headers shall be found in the main header.
-                        //
-                        // In this case, the PPT marker segment and packets distributed in the bit stream of the
-                        // tile-parts are disallowed.
-                        if !self.header.packed_packet_headers.is_empty() {
-                            return Err(CodestreamError::MarkerUnexpected {
-                                marker: MARKER_SYMBOL_PPT,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
-                        }
+                // PLT (Optional)
+                MARKER_SYMBOL_PLT => {
+                    let packet_length = self.decode_plt(reader)?;
+                    tile_header.packet_lengths.push(packet_length);
+                }
 
-                        tile_header.packed_packet_headers = Some(self.decode_ppt(reader)?);
-                    }
+                // COM (Optional)
+                MARKER_SYMBOL_COM => {
+                    tile_header
+                        .comment_marker_segments
+                        .push(self.decode_com(reader)?);
+                }
 
-                    // PLT (Optional)
-                    MARKER_SYMBOL_PLT => {
-                        let packet_length = self.decode_plm(reader)?;
-                        tile_header.packet_lengths.push(packet_length);
-                    }
+                // SOD (Required as the last marker of every tile-part header)
+                MARKER_SYMBOL_SOD => {
+                    reader.seek(io::SeekFrom::Current(-2))?;
+                    break;
+                }
 
-                    // COM (Optional)
-                    MARKER_SYMBOL_COM => {
-                        tile_header.comment_marker_segment = Some(self.decode_com(reader)?);
-                    }
-                    // COM (Optional)
-                    MARKER_SYMBOL_SOD => {
-                        reader.seek(io::SeekFrom::Current(-2))?;
-                        break;
+                _ => {
+                    return Err(CodestreamError::MarkerUnexpected {
+                        marker: marker_type,
+                        offset: reader.stream_position()? - 2,
                     }
-                    _ => panic!(),
-                },
-
-                Err(e) => return Err(e.into()),
+                    .into());
+                }
             }
+            tile_header.markers.push(marker_type);
         }
 
         Ok(tile_header)
     }
 
-    fn decode<R: io::Read + io::Seek>(
+    // A.4.2 - The tile-part data follows the SOD marker and ends Psot bytes
+    // from the start of the SOT marker, or at the EOC marker when Psot is zero.
+    fn decode_tile_part_data<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
+        start_of_tile_segment: &StartOfTileSegment,
+    ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let start_of_data = reader.stream_position()?;
+        let mut data = vec![];
+
+        if start_of_tile_segment.tile_length() == 0 {
+            reader.read_to_end(&mut data)?;
+            if data.ends_with(&MARKER_SYMBOL_EOC) {
+                data.truncate(data.len() - 2);
+                reader.seek(io::SeekFrom::Current(-2))?;
+            }
+        } else {
+            let end_of_data =
+                start_of_tile_segment.offset() - 2 + start_of_tile_segment.tile_length() as u64;
+            if end_of_data < start_of_data {
+                return Err(CodestreamError::MarkerError {
+                    marker: MARKER_SYMBOL_SOT,
+                    error: format!(
+                        "tile-part length {} ends before the start of data at byte offset {}",
+                        start_of_tile_segment.tile_length(),
+                        start_of_data
+                    ),
+                }
+                .into());
+            }
+
+            // A truncated codestream leaves the tile-part data short
+            reader
+                .take(end_of_data - start_of_data)
+                .read_to_end(&mut data)?;
+        }
+
+        Ok(data)
+    }
+
+    // A.4 - Decode a tile-part, following its SOT marker, and add it to the
+    // tile it belongs to, with its packet headers from the PPM marker segments
+    // if any
+    fn decode_tile_part<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+        packet_headers: Option<Vec<u8>>,
     ) -> Result<(), Box<dyn error::Error>> {
-        // The main header is found at the beginning of the codestream
-        self.header = self.decode_main_header(reader)?;
+        let siz = &self.header.image_and_tile_size_marker_segment;
+        let no_components = siz.no_components();
+        let no_tiles = siz.num_x_tiles() * siz.num_y_tiles();
 
-        let no_components = self
-            .header
-            .image_and_tile_size_marker_segment
-            .no_components();
+        let start_of_tile_segment = self.decode_sot(reader)?;
+        let tile_index = start_of_tile_segment.tile_index();
+        if tile_index as u32 >= no_tiles {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SOT,
+                error: format!(
+                    "tile index {} exceeds number of tiles {}",
+                    tile_index, no_tiles
+                ),
+            }
+            .into());
+        }
+
+        // The tile-parts of a tile may be interleaved with those of other
+        // tiles, but shall appear in the order of their tile-part index
+        let position = self.tiles.iter().position(|tile| tile.index == tile_index);
+        let no_tile_parts = position.map_or(0, |i| self.tiles[i].parts.len());
+        if start_of_tile_segment.tile_part_index() as usize != no_tile_parts {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SOT,
+                error: format!(
+                    "tile-part index {} of tile {} is out of order, expected {}",
+                    start_of_tile_segment.tile_part_index(),
+                    tile_index,
+                    no_tile_parts
+                ),
+            }
+            .into());
+        }
+
+        // A non-zero TNsot is the number of tile-parts of the tile, the same
+        // in each tile-part which signals it
+        let tile_part_index = start_of_tile_segment.tile_part_index();
+        let no_tile_parts = start_of_tile_segment.no_tile_parts();
+        if no_tile_parts != 0 {
+            if tile_part_index >= no_tile_parts {
+                return Err(CodestreamError::MarkerError {
+                    marker: MARKER_SYMBOL_SOT,
+                    error: format!(
+                        "tile-part index {} of tile {} exceeds number of tile-parts {}",
+                        tile_part_index, tile_index, no_tile_parts
+                    ),
+                }
+                .into());
+            }
+            if let Some(signalled) = position.and_then(|i| self.tiles[i].no_tile_parts()) {
+                if signalled != no_tile_parts {
+                    return Err(CodestreamError::MarkerError {
+                        marker: MARKER_SYMBOL_SOT,
+                        error: format!(
+                            "number of tile-parts {} of tile {} differs from {}",
+                            no_tile_parts, tile_index, signalled
+                        ),
+                    }
+                    .into());
+                }
+            }
+        }
 
         // The tile-part headers are found at the beginning of each tile-part
-        let tile_header = self.decode_first_tile_header(reader, no_components)?;
-        let mut marker_type: MarkerSymbol = [0; 2];
+        let mut tile_header =
+            self.decode_tile_part_header(reader, no_components, position.is_none())?;
+        let markers = mem::take(&mut tile_header.markers);
 
         // Required as the last marker segment of every tile-part header
+        let mut marker_type: MarkerSymbol = [0; 2];
         reader.read_exact(&mut marker_type)?;
         if marker_type != MARKER_SYMBOL_SOD {
             return Err(CodestreamError::MarkerUnexpected {
@@ -2619,108 +3240,140 @@ impl ContiguousCodestream {
             .into());
         }
 
-        let coding_styles = self.header.coding_style_marker_segment().coding_styles();
+        info!("SOD start at byte offset {}", reader.stream_position()? - 2);
+
+        // Packet headers and bodies, decoded by decode_image once the
+        // precinct partition of the tile is known, see B.10 and J.10.
+        let data = self.decode_tile_part_data(reader, &start_of_tile_segment)?;
+
+        info!("SOD end at byte offset {}", reader.stream_position()?);
+
+        // A.7.4 and A.7.5 - The packet headers shall be in only one of three
+        // places within the codestream, with PPM marker segments in the main
+        // header the PPT marker segment is disallowed. Otherwise the packet
+        // headers of the PPT marker segments of the tile-part header are in
+        // the order of Zppt.
+        let packet_headers = match packet_headers {
+            Some(_) if !tile_header.packed_packet_headers.is_empty() => {
+                return Err(CodestreamError::MarkerError {
+                    marker: MARKER_SYMBOL_PPT,
+                    error: format!(
+                        "packet headers of tile-part {} of tile {} in both PPM and PPT marker segments",
+                        tile_part_index, tile_index
+                    ),
+                }
+                .into());
+            }
+            Some(packet_headers) => Some(packet_headers),
+            None if !tile_header.packed_packet_headers.is_empty() => {
+                let mut segments: Vec<&TilePackedPacketHeaderSegment> =
+                    tile_header.packed_packet_headers.iter().collect();
+                segments.sort_by_key(|segment| segment.index());
+                Some(
+                    segments
+                        .iter()
+                        .flat_map(|segment| segment.data.iter().copied())
+                        .collect(),
+                )
+            }
+            None => None,
+        };
 
-        let start_of_data = reader.stream_position()?;
-        info!("SOD start at byte offset {}", start_of_data - 2);
+        let part = TilePart {
+            start_of_tile_segment,
+            data,
+            packet_headers,
+            markers,
+        };
+        match position {
+            Some(i) => {
+                let tile = &mut self.tiles[i];
+                tile.header.append(tile_header);
+                tile.parts.push(part);
+            }
+            None => self.tiles.push(Tile {
+                index: tile_index,
+                header: tile_header,
+                parts: vec![part],
+            }),
+        }
 
+        Ok(())
+    }
+
+    fn decode<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<(), Box<dyn error::Error>> {
+        // The main header is found at the beginning of the codestream
+        self.header = self.decode_main_header(reader)?;
+
+        // A.7.4 - With PPM marker segments every tile-part has its packet
+        // headers in the main header
+        let packed = !self.header.packed_packet_headers.is_empty();
+        let mut packet_headers = self.header.tile_part_packet_headers()?.into_iter();
+        let mut no_tile_parts = 0;
+
+        let mut marker_type: MarkerSymbol = [0; 2];
         loop {
             match reader.read_exact(&mut marker_type) {
                 Ok(_) => match marker_type {
-                    // in bit-stream markers
-                    MARKER_SYMBOL_SOP => {
-                        info!("SOP start at byte offset {}", reader.stream_position()? - 2);
-                        if coding_styles.contains(&CodingStyleDefault::NoSOP) {
-                            return Err(CodestreamError::MarkerUnexpected {
-                                marker: MARKER_SYMBOL_SOP,
-                                offset: reader.stream_position()? - 2,
+                    // A.4.2 - Start of tile-part
+                    MARKER_SYMBOL_SOT => {
+                        let tile_part_packet_headers = match packet_headers.next() {
+                            Some(tile_part_packet_headers) => Some(tile_part_packet_headers),
+                            None if packed => {
+                                return Err(CodestreamError::MarkerError {
+                                    marker: MARKER_SYMBOL_PPM,
+                                    error: format!(
+                                        "no packet headers for tile-part {}",
+                                        no_tile_parts
+                                    ),
+                                }
+                                .into());
                             }
-                            .into());
-                        } else {
-                            // ITU-T H.800 or ISO/IEC 15444-1 2024, Section A.8.1
-                            let mut buf = [0u8; 2];
-                            reader.read_exact(&mut buf)?;
-                            let lsop = u16::from_be_bytes(buf);
-                            // TODO: if using strict parsing, check length == 4
-                            reader.read_exact(&mut buf)?;
-                            let nsop = u16::from_be_bytes(buf);
-                            // TODO: if using strict parsing, check nsop increment matches packet number,
-                            // even if SOP wasn't present
-                            info!("SOP length {lsop}, sequence number {nsop}");
-                        }
+                            None => None,
+                        };
+                        self.decode_tile_part(reader, tile_part_packet_headers)?;
+                        no_tile_parts += 1;
                     }
-                    MARKER_SYMBOL_EPH => {
-                        // If packet headers are not in-bit stream (i.e., PPM or PPT marker segments are used), this
-                        // marker shall not be used in the bit stream
-                        if !self.header.packed_packet_headers.is_empty()
-                            || tile_header.packed_packet_headers.is_some()
-                        {
-                            return Err(CodestreamError::MarkerUnexpected {
-                                marker: MARKER_SYMBOL_EPH,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
-                        }
+                    // A.4.4 - End of codestream
+                    MARKER_SYMBOL_EOC => {
+                        info!("EOC end at byte offset {}", reader.stream_position()?);
 
-                        if coding_styles.contains(&CodingStyleDefault::NoEPH) {
-                            return Err(CodestreamError::MarkerUnexpected {
-                                marker: MARKER_SYMBOL_EPH,
-                                offset: reader.stream_position()? - 2,
+                        // Unlike a truncated codestream, a complete one has
+                        // every tile-part of each tile
+                        for tile in self.tiles.iter() {
+                            match tile.no_tile_parts() {
+                                Some(no_tile_parts)
+                                    if tile.parts.len() < no_tile_parts as usize =>
+                                {
+                                    return Err(CodestreamError::MarkerError {
+                                        marker: MARKER_SYMBOL_SOT,
+                                        error: format!(
+                                            "tile {} has {} of its {} tile-parts",
+                                            tile.index,
+                                            tile.parts.len(),
+                                            no_tile_parts
+                                        ),
+                                    }
+                                    .into());
+                                }
+                                _ => {}
                             }
-                            .into());
-                        } else {
-                            // ITU-T H.800 or ISO/IEC 15444-1 2024, Section A.8.2
-                            // Empty marker, not even the length.
                         }
-                    }
-                    // delimiting markers
-                    MARKER_SYMBOL_EOC => {
-                        info!("EOC end at byte offset {}", reader.stream_position()?);
                         break;
                     }
-                    MARKER_SYMBOL_SOT => {
-                        // A.4.4
-                        todo!();
-                    }
                     _ => {
-                        // TODO: See J.10.3 Packet headers
-                        //
-                        // decode packet header B.10.8
-                        //   1 bit for zero or non-zero length packet
-                        //   for each sub-band (LL or HL, LH and HH)
-                        //     for all code-blocks in this sub-band confined to the relevant precinct, in raster order
-                        //       code-block inclusion bits (if not previously included then tag tree, else one bit)
-                        //       if code-block included
-                        //         if first instance of code-block
-                        //           zero bit-planes information
-                        //           number of coding passes included
-                        //           increase of code-block length indicator (Lblock)
-                        //           for each codeword segment
-                        //           length of codeword segment
-
-                        // decode packet data J.10.4
-                        //   Arithmetic-coded compressed data
-
-                        // Decode codestream packet header
-
-                        // Bits are packed into bytes from the MSB to the LSB.
-                        // Once a complete byte is assembled, it is appended to
-                        // the packet header.
-
-                        // If the value of the byte is 0xFF, the next byte
-                        // includes an extra zero bit stuffed into the MSB
-
-                        // Once all bits of the packet header have been
-                        // assembled, the last byte is packed to the byte
-                        // boundary and emitted
-
-                        // The last byte in the packet header shall not be an
-                        // 0xFF value (thus the single zero bit stuffed after a
-                        // byte with 0xFF must be included even if the 0xFF
-                        // would otherwise have been the last byte).
+                        return Err(CodestreamError::MarkerUnexpected {
+                            marker: marker_type,
+                            offset: reader.stream_position()? - 2,
+                        }
+                        .into());
                     }
                 },
 
+                // A truncated codestream ends without the EOC marker
                 Err(e) => match e.kind() {
                     io::ErrorKind::UnexpectedEof => break,
                     _ => return Err(e.into()),
@@ -2728,21 +3381,6 @@ impl ContiguousCodestream {
             }
         }
 
-        let end_of_data = reader.stream_position()?;
-        info!("SOD end at byte offset {}", end_of_data);
-
-        // TODO: Support multiple SOT
-
-        // TODO: avoid seeking
-        reader.seek(io::SeekFrom::Start(start_of_data))?;
-
-        reader.seek(io::SeekFrom::Start(end_of_data))?;
-
-        self.tiles.push(Tile {
-            header: tile_header,
-            parts: vec![],
-        });
-
         Ok(())
     }
 }
@@ -2777,7 +3415,94 @@ impl ContiguousCodestream {
 //
 // The parameters, Ysiz, Ysiz, YOsiz, YOsiz, YRsiz^c and YRsiz^c are all
 // defined in the SIZ marker segment
-struct Component {}
+#[derive(Debug)]
+pub struct Component {
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+
+    // Ssiz: Precision (depth) in bits and sign of the component samples
+    precision: u8,
+    signed: bool,
+
+    // XRsiz and YRsiz: Separation of the component samples on the reference
+    // grid
+    horizontal_separation: u8,
+    vertical_separation: u8,
+
+    // Samples in raster order, starting with the sample at (x0, y0)
+    samples: Vec<i32>,
+}
+
+impl Component {
+    /// A component of width by height samples in raster order to encode, with
+    /// the first sample at the origin of the reference grid and a sample at
+    /// every point of it.
+    pub fn new(
+        width: u32,
+        height: u32,
+        precision: u8,
+        signed: bool,
+        samples: Vec<i32>,
+    ) -> Component {
+        Component {
+            x0: 0,
+            y0: 0,
+            width,
+            height,
+            precision,
+            signed,
+            horizontal_separation: 1,
+            vertical_separation: 1,
+            samples,
+        }
+    }
+
+    /// Horizontal coordinate of the first sample, x0 = ⌈XOsiz / XRsiz⌉
+    pub fn x0(&self) -> u32 {
+        self.x0
+    }
+
+    /// Vertical coordinate of the first sample, y0 = ⌈YOsiz / YRsiz⌉
+    pub fn y0(&self) -> u32 {
+        self.y0
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn values_are_signed(&self) -> bool {
+        self.signed
+    }
+
+    pub fn horizontal_separation(&self) -> u8 {
+        self.horizontal_separation
+    }
+
+    pub fn vertical_separation(&self) -> u8 {
+        self.vertical_separation
+    }
+
+    pub fn samples(&self) -> &[i32] {
+        &self.samples
+    }
+
+    /// Sample at column x and row y of the component, relative to the first
+    /// sample.
+    pub fn sample(&self, x: u32, y: u32) -> i32 {
+        self.samples[(y * self.width + x) as usize]
+    }
+}
 
 // An “image area” is defined on the reference grid by the dimensional
 // parameters, (Xsiz, Ysiz) and (XOsiz, YOsiz).
@@ -2785,7 +3510,151 @@ struct Component {}
 // Specifically, the image area on the reference grid is defined by its upper
 // left hand reference grid point at location (XOsiz, YOsiz), and its lower
 // right hand reference grid point at location (Xsiz-1, Ysiz-1).
-struct ImageArea {}
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImageArea {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl ImageArea {
+    /// Area from the upper left point (x0, y0) up to but excluding the lower
+    /// right point (x1, y1) on the reference grid.
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> ImageArea {
+        ImageArea { x0, y0, x1, y1 }
+    }
+
+    pub fn x0(&self) -> u32 {
+        self.x0
+    }
+
+    pub fn y0(&self) -> u32 {
+        self.y0
+    }
+
+    pub fn x1(&self) -> u32 {
+        self.x1
+    }
+
+    pub fn y1(&self) -> u32 {
+        self.y1
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1.saturating_sub(self.y0)
+    }
+}
+
+/// Options for decoding the image of a codestream with [`decode_image`]
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Reconstruction bias (r) of the irreversibly quantized coefficients,
+    /// and of the reversible coefficients of code-blocks truncated before
+    /// their last bit-plane, see E.1.1.2
+    pub reconstruction_bias: f32,
+
+    /// Number of highest resolution levels to discard, which decodes the image
+    /// at 1/2^reduce of its size. It may not exceed the number of
+    /// decomposition levels of any tile-component.
+    pub reduce: u8,
+
+    /// Window of the image to decode on the reference grid, only the tiles,
+    /// precincts and code-blocks needed to reconstruct it are decoded. The
+    /// whole image is decoded when none is given.
+    pub area: Option<ImageArea>,
+
+    /// Maximum number of quality layers to decode, the contributions of later
+    /// layers to the code-blocks are ignored. Every layer is decoded when none
+    /// is given.
+    pub max_layers: Option<u16>,
+
+    /// Discard the corrupt coding passes of code-blocks found with
+    /// segmentation symbols and predictable termination, or any error decoding
+    /// a code-block, instead of failing. The code-blocks are reported by
+    /// Image::concealed_code_blocks.
+    pub resilient: bool,
+
+    /// Upsample the components with a sample separation other than 1, with
+    /// the component registration offsets of CRG, to a sample on every point
+    /// of the reference grid in the image area. The components then have the
+    /// size of the image.
+    pub upsample: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            reconstruction_bias: quantization::DEFAULT_RECONSTRUCTION_BIAS,
+            reduce: 0,
+            area: None,
+            max_layers: None,
+            resilient: false,
+            upsample: false,
+        }
+    }
+}
+
+/// Decode the headers and compressed image data of a codestream into the
+/// samples of each component.
+pub fn decode_image<R: io::Read + io::Seek>(
+    reader: &mut R,
+    options: &DecodeOptions,
+) -> Result<Image, Box<dyn error::Error>> {
+    let mut continuous_codestream = ContiguousCodestream::default();
+    continuous_codestream.decode(reader)?;
+
+    decoder::decode(&continuous_codestream, options)
+}
+
+/// Options for encoding the components of an image with [`encode_image`]
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Width and height of the tiles on the reference grid (XTsiz, YTsiz),
+    /// starting at the image area. The image is a single tile when none is
+    /// given.
+    pub tile_size: Option<(u32, u32)>,
+
+    /// Width and height of the code-blocks, powers of two from 4 to 1024 of
+    /// at most 4096 coefficients, see A.6.1.
+    pub code_block_size: (u16, u16),
+
+    /// Number of decomposition levels (NL) of the wavelet transformation, at
+    /// most 32.
+    pub no_decomposition_levels: u8,
+
+    /// Progression order of the packets of each tile
+    pub progression_order: ProgressionOrder,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> EncodeOptions {
+        EncodeOptions {
+            tile_size: None,
+            code_block_size: (64, 64),
+            no_decomposition_levels: 5,
+            progression_order: ProgressionOrder::LRLCPP,
+        }
+    }
+}
+
+/// Encode the samples of each component into a codestream, losslessly with
+/// the 5-3 reversible wavelet transformation, the reversible component
+/// transformation of the first three components and a single layer.
+///
+/// The components have the same size and position with a sample at every
+/// point of the reference grid, and a precision of 1 to 16 bits.
+pub fn encode_image<W: io::Write>(
+    writer: &mut W,
+    components: &[Component],
+    options: &EncodeOptions,
+) -> Result<(), Box<dyn error::Error>> {
+    encoder::encode(writer, components, options)
+}
 
 pub fn decode_jpc<R: io::Read + io::Seek>(
     reader: &mut R,
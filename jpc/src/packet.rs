@@ -0,0 +1,861 @@
+use std::error;
+
+use crate::code_block::is_terminated;
+use crate::{CodestreamError, CodingBlockStyle, MARKER_SYMBOL_EPH};
+
+// Initial value of a tag tree node that has not been decoded yet
+const TAG_TREE_UNKNOWN: u32 = u32::MAX;
+
+// Code-block length indicator, Lblock, starts at three bits, see B.10.7.1
+const LBLOCK_INITIAL: u8 = 3;
+
+// B.10.1 - Bit-stuffing routine
+//
+// Bits are packed into bytes from the MSB to the LSB. If the value of a byte
+// is 0xFF, the next byte includes an extra zero bit stuffed into the MSB.
+#[derive(Debug)]
+struct PacketHeaderReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    remaining: u8,
+}
+
+impl<'a> PacketHeaderReader<'a> {
+    fn new(data: &'a [u8]) -> PacketHeaderReader<'a> {
+        PacketHeaderReader {
+            data,
+            position: 0,
+            byte: 0,
+            remaining: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u8, Box<dyn error::Error>> {
+        if self.remaining == 0 {
+            let stuffed = self.position > 0 && self.byte == 0xFF;
+            self.byte = match self.data.get(self.position) {
+                Some(byte) => *byte,
+                None => {
+                    return Err(CodestreamError::PacketError {
+                        error: format!("packet header truncated at byte {}", self.position),
+                    }
+                    .into())
+                }
+            };
+            self.position += 1;
+            self.remaining = if stuffed { 7 } else { 8 };
+        }
+        self.remaining -= 1;
+        Ok((self.byte >> self.remaining) & 1)
+    }
+
+    fn read_bits(&mut self, n: u8) -> Result<u32, Box<dyn error::Error>> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    // Once all bits of the packet header have been read, the last byte is
+    // packed to the byte boundary. The last byte in the packet header shall
+    // not be an 0xFF value, thus the single zero bit stuffed after a byte with
+    // 0xFF is included even if the 0xFF would otherwise have been the last
+    // byte.
+    fn finish(self) -> usize {
+        if self.position > 0 && self.byte == 0xFF {
+            self.position + 1
+        } else {
+            self.position
+        }
+    }
+}
+
+// B.10.1 - Bit-stuffing routine when writing packet headers, which the
+// packet header reader reads back
+#[derive(Debug)]
+struct PacketHeaderWriter {
+    data: Vec<u8>,
+    byte: u8,
+    remaining: u8,
+
+    // Number of bits of the current byte, 7 after a 0xFF byte
+    capacity: u8,
+}
+
+impl PacketHeaderWriter {
+    fn new() -> PacketHeaderWriter {
+        PacketHeaderWriter {
+            data: vec![],
+            byte: 0,
+            remaining: 8,
+            capacity: 8,
+        }
+    }
+
+    fn write_bit(&mut self, bit: u8) {
+        self.remaining -= 1;
+        self.byte |= (bit & 1) << self.remaining;
+        if self.remaining == 0 {
+            self.data.push(self.byte);
+            self.capacity = if self.byte == 0xFF { 7 } else { 8 };
+            self.remaining = self.capacity;
+            self.byte = 0;
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, n: u8) {
+        for i in (0..n).rev() {
+            self.write_bit((value >> i) as u8 & 1);
+        }
+    }
+
+    // The last byte is packed to the byte boundary with zero bits, and a
+    // last 0xFF byte is followed by a byte with the stuffed zero bit
+    fn finish(mut self) -> Vec<u8> {
+        if self.remaining < self.capacity {
+            self.data.push(self.byte);
+        }
+        if self.data.last() == Some(&0xFF) {
+            self.data.push(0);
+        }
+        self.data
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TagTreeNode {
+    value: u32,
+    low: u32,
+
+    // Whether the value has been encoded, when encoding the tag tree
+    known: bool,
+}
+
+/// Tag tree, see B.10.2
+///
+/// A way of representing a two-dimensional array of non-negative integers in
+/// a hierarchical way. Successively reduced resolution versions of the array
+/// are created, each node holding the minimum of its (up to four) children,
+/// down to a single root node.
+#[derive(Debug)]
+pub struct TagTree {
+    width: u32,
+    height: u32,
+
+    // Width, height and offset of the nodes of each level, starting with the
+    // leaves
+    levels: Vec<(u32, u32, usize)>,
+    nodes: Vec<TagTreeNode>,
+}
+
+impl TagTree {
+    pub fn new(width: u32, height: u32) -> TagTree {
+        let mut levels = vec![];
+        let mut offset = 0;
+        let (mut level_width, mut level_height) = (width, height);
+        loop {
+            levels.push((level_width, level_height, offset));
+            offset += (level_width * level_height) as usize;
+            if level_width <= 1 && level_height <= 1 {
+                break;
+            }
+            level_width = level_width.div_ceil(2);
+            level_height = level_height.div_ceil(2);
+        }
+
+        TagTree {
+            width,
+            height,
+            levels,
+            nodes: vec![
+                TagTreeNode {
+                    value: TAG_TREE_UNKNOWN,
+                    low: 0,
+                    known: false,
+                };
+                offset
+            ],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Value of a leaf, if it has been fully decoded.
+    pub fn value(&self, x: u32, y: u32) -> Option<u32> {
+        let value = self.nodes[(y * self.width + x) as usize].value;
+        if value == TAG_TREE_UNKNOWN {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Set the value of a leaf to encode, with the value of every node above
+    /// it the minimum of the leaves below it.
+    pub fn set_value(&mut self, x: u32, y: u32, value: u32) {
+        for (level, (level_width, _, offset)) in self.levels.iter().enumerate() {
+            let i = offset + ((y >> level) * level_width + (x >> level)) as usize;
+            let node = &mut self.nodes[i];
+            if level == 0 {
+                node.value = value;
+            } else {
+                node.value = node.value.min(value);
+            }
+        }
+    }
+
+    // Encodes the bits of the tag tree the decoder reads to know whether the
+    // value of a leaf is below the threshold, see decode.
+    fn encode(&mut self, writer: &mut PacketHeaderWriter, x: u32, y: u32, threshold: u32) -> bool {
+        let mut low = 0;
+        for (level, (level_width, _, offset)) in self.levels.iter().enumerate().rev() {
+            let i = offset + ((y >> level) * level_width + (x >> level)) as usize;
+            let node = &mut self.nodes[i];
+
+            if low > node.low {
+                node.low = low;
+            } else {
+                low = node.low;
+            }
+
+            while low < threshold {
+                if low >= node.value {
+                    if !node.known {
+                        writer.write_bit(1);
+                        node.known = true;
+                    }
+                    break;
+                }
+                writer.write_bit(0);
+                low += 1;
+            }
+            node.low = low;
+        }
+
+        self.nodes[(y * self.width + x) as usize].value < threshold
+    }
+
+    // Decodes the bits of the tag tree needed to know whether the value of a
+    // leaf is below the threshold, visiting the nodes from the root down to
+    // the leaf.
+    fn decode(
+        &mut self,
+        reader: &mut PacketHeaderReader,
+        x: u32,
+        y: u32,
+        threshold: u32,
+    ) -> Result<bool, Box<dyn error::Error>> {
+        let mut low = 0;
+        for (level, (level_width, _, offset)) in self.levels.iter().enumerate().rev() {
+            let i = offset + ((y >> level) * level_width + (x >> level)) as usize;
+            let node = &mut self.nodes[i];
+
+            if low > node.low {
+                node.low = low;
+            } else {
+                low = node.low;
+            }
+
+            while low < threshold && low < node.value {
+                if reader.read_bit()? == 1 {
+                    node.value = low;
+                } else {
+                    low += 1;
+                }
+            }
+            node.low = low;
+        }
+
+        Ok(self.nodes[(y * self.width + x) as usize].value < threshold)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CodeBlockState {
+    // Whether the code-block has been included in a previous packet
+    included: bool,
+
+    // Lblock, the number of bits used to signal codeword segment lengths
+    lblock: u8,
+
+    // Number of coding passes included in previous packets
+    no_passes: u8,
+}
+
+#[derive(Debug)]
+struct PrecinctSubband {
+    no_code_blocks_wide: u32,
+    no_code_blocks_high: u32,
+    inclusion: TagTree,
+    zero_bit_planes: TagTree,
+    code_blocks: Vec<CodeBlockState>,
+}
+
+/// The state of a precinct carried between the packets of successive layers
+///
+/// Each subband of the precinct holds the code-block inclusion and zero
+/// bit-plane tag trees, and the Lblock value of every code-block.
+#[derive(Debug)]
+pub struct Precinct {
+    subbands: Vec<PrecinctSubband>,
+
+    // Whether coding passes are terminated, which splits the contribution of
+    // a code-block into several codeword segments, see B.10.7.2
+    selective_arithmetic_coding_bypass: bool,
+    termination_on_each_coding_pass: bool,
+}
+
+/// The contribution of a code-block to a packet, see B.10.8
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlockContribution {
+    // Index of the subband in the precinct
+    subband: usize,
+
+    // Index of the code-block in raster order within the precinct subband
+    code_block: usize,
+
+    // Number of missing most significant bit-planes, P, only signalled the
+    // first time the code-block is included
+    zero_bit_planes: Option<u8>,
+
+    // Number of new coding passes
+    no_passes: u8,
+
+    // Length in bytes of each codeword segment in the packet body
+    segment_lengths: Vec<u32>,
+
+    // Whether the first codeword segment continues the last one of the
+    // previous packet
+    continued: bool,
+}
+
+impl CodeBlockContribution {
+    /// The contribution of a code-block to a packet to encode, with the
+    /// number of new coding passes and the length of each of their codeword
+    /// segments, see Precinct::encode_packet_header.
+    pub fn new(
+        subband: usize,
+        code_block: usize,
+        no_passes: u8,
+        segment_lengths: Vec<u32>,
+    ) -> CodeBlockContribution {
+        CodeBlockContribution {
+            subband,
+            code_block,
+            zero_bit_planes: None,
+            no_passes,
+            segment_lengths,
+            continued: false,
+        }
+    }
+
+    pub fn subband(&self) -> usize {
+        self.subband
+    }
+
+    pub fn code_block(&self) -> usize {
+        self.code_block
+    }
+
+    pub fn zero_bit_planes(&self) -> Option<u8> {
+        self.zero_bit_planes
+    }
+
+    pub fn no_passes(&self) -> u8 {
+        self.no_passes
+    }
+
+    pub fn segment_lengths(&self) -> &[u32] {
+        &self.segment_lengths
+    }
+
+    /// Whether the first codeword segment continues the codeword segment the
+    /// code-block's previous contribution ended with, as its last coding pass
+    /// was not terminated.
+    pub fn is_continued(&self) -> bool {
+        self.continued
+    }
+
+    /// Total length in bytes of the code-block data in the packet body.
+    pub fn length(&self) -> u32 {
+        self.segment_lengths.iter().sum()
+    }
+}
+
+/// Packet header, see B.10
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PacketHeader {
+    // Number of bytes of the packet header, including any EPH marker
+    length: usize,
+
+    contributions: Vec<CodeBlockContribution>,
+}
+
+impl PacketHeader {
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// A zero length packet has no code-block contributions.
+    pub fn is_empty(&self) -> bool {
+        self.contributions.is_empty()
+    }
+
+    pub fn contributions(&self) -> &[CodeBlockContribution] {
+        &self.contributions
+    }
+
+    /// Length in bytes of the packet body.
+    pub fn body_length(&self) -> u32 {
+        self.contributions.iter().map(|c| c.length()).sum()
+    }
+}
+
+impl Precinct {
+    /// Create the precinct state from the number of code-blocks wide and high
+    /// of each subband in the precinct, in the order they appear in a packet
+    /// (LL for the lowest resolution level, otherwise HL, LH and HH).
+    pub fn new(code_blocks: &[(u32, u32)]) -> Precinct {
+        Precinct::with_code_block_style(code_blocks, 0)
+    }
+
+    /// Create the precinct state like new, for code-blocks coded with the
+    /// code-block style of SPcod or SPcoc, see Table A.19.
+    pub fn with_code_block_style(code_blocks: &[(u32, u32)], code_block_style: u8) -> Precinct {
+        let code_block_styles = CodingBlockStyle::new(code_block_style);
+
+        Precinct {
+            selective_arithmetic_coding_bypass: code_block_styles
+                .contains(&CodingBlockStyle::SelectiveArithmeticCodingBypass),
+            termination_on_each_coding_pass: code_block_styles
+                .contains(&CodingBlockStyle::TerminationOnEachCodingPass),
+            subbands: code_blocks
+                .iter()
+                .map(|(wide, high)| PrecinctSubband {
+                    no_code_blocks_wide: *wide,
+                    no_code_blocks_high: *high,
+                    inclusion: TagTree::new(*wide, *high),
+                    zero_bit_planes: TagTree::new(*wide, *high),
+                    code_blocks: vec![
+                        CodeBlockState {
+                            included: false,
+                            lblock: LBLOCK_INITIAL,
+                            no_passes: 0,
+                        };
+                        (wide * high) as usize
+                    ],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn no_subbands(&self) -> usize {
+        self.subbands.len()
+    }
+
+    pub fn no_code_blocks(&self, subband: usize) -> (u32, u32) {
+        let subband = &self.subbands[subband];
+        (subband.no_code_blocks_wide, subband.no_code_blocks_high)
+    }
+
+    /// Decode the header of the packet for a layer of this precinct, see
+    /// B.10.8 and J.10.3.
+    ///
+    /// 1 bit for zero or non-zero length packet
+    /// for each subband (LL or HL, LH and HH)
+    ///   for all code-blocks in this subband confined to the precinct, in
+    ///   raster order
+    ///     code-block inclusion bits (if not previously included then tag
+    ///     tree, else one bit)
+    ///     if code-block included
+    ///       if first instance of code-block
+    ///         zero bit-planes information
+    ///       number of coding passes included
+    ///       increase of code-block length indicator (Lblock)
+    ///       for each codeword segment
+    ///         length of codeword segment
+    ///
+    /// The packet header ends on a byte boundary and may be followed by an
+    /// EPH marker, which is included in the header length.
+    pub fn decode_packet_header(
+        &mut self,
+        data: &[u8],
+        layer: u16,
+    ) -> Result<PacketHeader, Box<dyn error::Error>> {
+        let mut reader = PacketHeaderReader::new(data);
+        let mut contributions = vec![];
+
+        // Zero length packet
+        if reader.read_bit()? == 1 {
+            for (subband_index, subband) in self.subbands.iter_mut().enumerate() {
+                for y in 0..subband.no_code_blocks_high {
+                    for x in 0..subband.no_code_blocks_wide {
+                        let code_block_index = (y * subband.no_code_blocks_wide + x) as usize;
+                        if let Some(contribution) = subband.decode_code_block(
+                            &mut reader,
+                            x,
+                            y,
+                            layer,
+                            self.selective_arithmetic_coding_bypass,
+                            self.termination_on_each_coding_pass,
+                        )? {
+                            contributions.push(CodeBlockContribution {
+                                subband: subband_index,
+                                code_block: code_block_index,
+                                ..contribution
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut length = reader.finish();
+
+        // B.10.8 - The EPH marker, if used, follows the packet header
+        if data.get(length..length + 2) == Some(&MARKER_SYMBOL_EPH) {
+            length += 2;
+        }
+
+        Ok(PacketHeader {
+            length,
+            contributions,
+        })
+    }
+
+    /// Set the layer a code-block is first included in, if any, and its
+    /// number of missing most significant bit-planes (P), which are coded
+    /// with the tag trees of the packet headers of the precinct.
+    ///
+    /// Every code-block is set before the header of the first packet of the
+    /// precinct is encoded.
+    pub fn set_code_block(
+        &mut self,
+        subband: usize,
+        code_block: usize,
+        layer: Option<u16>,
+        zero_bit_planes: u8,
+    ) {
+        let subband = &mut self.subbands[subband];
+        let x = code_block as u32 % subband.no_code_blocks_wide;
+        let y = code_block as u32 / subband.no_code_blocks_wide;
+        subband
+            .inclusion
+            .set_value(x, y, layer.map_or(TAG_TREE_UNKNOWN, |layer| layer as u32));
+        subband
+            .zero_bit_planes
+            .set_value(x, y, zero_bit_planes as u32);
+    }
+
+    /// Encode the header of the packet for a layer of this precinct, with the
+    /// contributions of the code-blocks included in it, see B.10.8.
+    ///
+    /// A code-block is included for the first time in the layer it was set
+    /// to with set_code_block. The codeword segment lengths of a contribution
+    /// are those of its coding passes up to and including each terminated
+    /// coding pass and the last one. The packet header is the same the
+    /// decoder reads with decode_packet_header, without an EPH marker.
+    pub fn encode_packet_header(
+        &mut self,
+        contributions: &[CodeBlockContribution],
+        layer: u16,
+    ) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let mut writer = PacketHeaderWriter::new();
+
+        // Zero length packet
+        if contributions.is_empty() {
+            writer.write_bit(0);
+            return Ok(writer.finish());
+        }
+        writer.write_bit(1);
+
+        for (subband_index, subband) in self.subbands.iter_mut().enumerate() {
+            for y in 0..subband.no_code_blocks_high {
+                for x in 0..subband.no_code_blocks_wide {
+                    let code_block_index = (y * subband.no_code_blocks_wide + x) as usize;
+                    let contribution = contributions.iter().find(|contribution| {
+                        contribution.subband == subband_index
+                            && contribution.code_block == code_block_index
+                    });
+                    subband.encode_code_block(
+                        &mut writer,
+                        x,
+                        y,
+                        layer,
+                        contribution,
+                        self.selective_arithmetic_coding_bypass,
+                        self.termination_on_each_coding_pass,
+                    )?;
+                }
+            }
+        }
+
+        Ok(writer.finish())
+    }
+}
+
+impl PrecinctSubband {
+    #[allow(clippy::too_many_arguments)]
+    fn encode_code_block(
+        &mut self,
+        writer: &mut PacketHeaderWriter,
+        x: u32,
+        y: u32,
+        layer: u16,
+        contribution: Option<&CodeBlockContribution>,
+        selective_arithmetic_coding_bypass: bool,
+        termination_on_each_coding_pass: bool,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let index = (y * self.no_code_blocks_wide + x) as usize;
+        let first_inclusion = !self.code_blocks[index].included;
+
+        // B.10.4 - Code-block inclusion
+        if first_inclusion {
+            let included = self.inclusion.encode(writer, x, y, layer as u32 + 1);
+            if included != contribution.is_some() {
+                return Err(CodestreamError::PacketError {
+                    error: format!(
+                        "code-block {} is first included in layer {:?}, not layer {}",
+                        index,
+                        self.inclusion.value(x, y),
+                        layer
+                    ),
+                }
+                .into());
+            }
+        } else {
+            writer.write_bit(contribution.is_some() as u8);
+        }
+        let contribution = match contribution {
+            Some(contribution) => contribution,
+            None => return Ok(()),
+        };
+
+        // B.10.5 - Zero bit-plane information
+        if first_inclusion {
+            let mut threshold = 1;
+            while !self.zero_bit_planes.encode(writer, x, y, threshold) {
+                threshold += 1;
+            }
+        }
+
+        // B.10.6 - Number of coding passes
+        let no_passes = contribution.no_passes;
+        if no_passes == 0 || no_passes > 164 {
+            return Err(CodestreamError::PacketError {
+                error: format!("{} coding passes cannot be signalled", no_passes),
+            }
+            .into());
+        }
+        encode_no_passes(writer, no_passes);
+
+        // B.10.7 - The codeword segments of the coding passes, each of which
+        // needs Lblock + floor(log2(passes)) bits for its length
+        let terminated = |pass| {
+            is_terminated(
+                pass,
+                selective_arithmetic_coding_bypass,
+                termination_on_each_coding_pass,
+            )
+        };
+        let code_block = &mut self.code_blocks[index];
+        let first_pass = code_block.no_passes;
+        let last_pass = first_pass.saturating_add(no_passes - 1);
+
+        let mut segments = vec![];
+        let mut segment_passes: u8 = 0;
+        for pass in first_pass..=last_pass {
+            segment_passes += 1;
+            if pass == last_pass || terminated(pass) {
+                segments.push((u8::BITS - 1 - segment_passes.leading_zeros()) as u8);
+                segment_passes = 0;
+            }
+        }
+        if segments.len() != contribution.segment_lengths.len() {
+            return Err(CodestreamError::PacketError {
+                error: format!(
+                    "{} codeword segment lengths for {} codeword segments",
+                    contribution.segment_lengths.len(),
+                    segments.len()
+                ),
+            }
+            .into());
+        }
+
+        // B.10.7.1 - Increase of the code-block length indicator to the
+        // fewest bits which signal every length
+        let mut lblock = code_block.lblock;
+        for (extra_bits, length) in segments.iter().zip(&contribution.segment_lengths) {
+            let no_bits = (u32::BITS - length.leading_zeros()) as u8;
+            lblock = lblock.max(no_bits.saturating_sub(*extra_bits));
+        }
+        for _ in code_block.lblock..lblock {
+            writer.write_bit(1);
+        }
+        writer.write_bit(0);
+        code_block.lblock = lblock;
+
+        for (extra_bits, length) in segments.iter().zip(&contribution.segment_lengths) {
+            writer.write_bits(*length, lblock + extra_bits);
+        }
+
+        code_block.included = true;
+        code_block.no_passes = last_pass.saturating_add(1);
+        Ok(())
+    }
+
+    fn decode_code_block(
+        &mut self,
+        reader: &mut PacketHeaderReader,
+        x: u32,
+        y: u32,
+        layer: u16,
+        selective_arithmetic_coding_bypass: bool,
+        termination_on_each_coding_pass: bool,
+    ) -> Result<Option<CodeBlockContribution>, Box<dyn error::Error>> {
+        let index = (y * self.no_code_blocks_wide + x) as usize;
+        let first_inclusion = !self.code_blocks[index].included;
+
+        // B.10.4 - Code-block inclusion, coded with the tag tree when the
+        // code-block has not been included before, otherwise a single bit.
+        let included = if first_inclusion {
+            self.inclusion.decode(reader, x, y, layer as u32 + 1)?
+        } else {
+            reader.read_bit()? == 1
+        };
+        if !included {
+            return Ok(None);
+        }
+
+        // B.10.5 - Zero bit-plane information, of at most 255 bit-planes in a
+        // valid packet header
+        let mut zero_bit_planes = None;
+        if first_inclusion {
+            let mut threshold = 1;
+            while !self.zero_bit_planes.decode(reader, x, y, threshold)? {
+                if threshold > u8::MAX as u32 {
+                    return Err(zero_bit_planes_error(index));
+                }
+                threshold += 1;
+            }
+            zero_bit_planes = self.zero_bit_planes.value(x, y).map(|value| value as u8);
+        }
+
+        // B.10.6 - Number of coding passes
+        let no_passes = decode_no_passes(reader)?;
+
+        // B.10.7.1 - Increase of the code-block length indicator, signalled
+        // as a number of 1 bits terminated by a 0 bit. Lengths are at most 32
+        // bits, which bounds Lblock in a valid packet header.
+        let code_block = &mut self.code_blocks[index];
+        while reader.read_bit()? == 1 {
+            code_block.lblock = match code_block.lblock.checked_add(1) {
+                Some(lblock) if lblock <= 32 => lblock,
+                _ => return Err(length_bits_error(index)),
+            };
+        }
+        code_block.included = true;
+
+        // B.10.7.2 - The length of each codeword segment is signalled with
+        // Lblock + floor(log2(passes)) bits, for the number of coding passes
+        // of the segment included in this packet
+        let terminated = |pass| {
+            is_terminated(
+                pass,
+                selective_arithmetic_coding_bypass,
+                termination_on_each_coding_pass,
+            )
+        };
+        let first_pass = code_block.no_passes;
+        let last_pass = first_pass.saturating_add(no_passes - 1);
+        let continued = first_pass > 0 && !terminated(first_pass - 1);
+
+        let mut segment_lengths = vec![];
+        let mut segment_passes: u8 = 0;
+        for pass in first_pass..=last_pass {
+            segment_passes += 1;
+            if pass == last_pass || terminated(pass) {
+                let no_bits =
+                    code_block.lblock + (u8::BITS - 1 - segment_passes.leading_zeros()) as u8;
+                if no_bits > 32 {
+                    return Err(length_bits_error(index));
+                }
+                segment_lengths.push(reader.read_bits(no_bits)?);
+                segment_passes = 0;
+            }
+        }
+        code_block.no_passes = last_pass.saturating_add(1);
+
+        Ok(Some(CodeBlockContribution {
+            subband: 0,
+            code_block: index,
+            zero_bit_planes,
+            no_passes,
+            segment_lengths,
+            continued,
+        }))
+    }
+}
+
+// The length of a codeword segment is signalled with more bits than it can
+// have
+fn length_bits_error(code_block: usize) -> Box<dyn error::Error> {
+    CodestreamError::PacketError {
+        error: format!(
+            "codeword segment length of code-block {} exceeds 32 bits",
+            code_block
+        ),
+    }
+    .into()
+}
+
+fn zero_bit_planes_error(code_block: usize) -> Box<dyn error::Error> {
+    CodestreamError::PacketError {
+        error: format!(
+            "zero bit-planes of code-block {} exceed 255 bit-planes",
+            code_block
+        ),
+    }
+    .into()
+}
+
+// Table B.4 - Codewords for the number of coding passes for each code-block
+fn decode_no_passes(reader: &mut PacketHeaderReader) -> Result<u8, Box<dyn error::Error>> {
+    if reader.read_bit()? == 0 {
+        return Ok(1);
+    }
+    if reader.read_bit()? == 0 {
+        return Ok(2);
+    }
+
+    let bits = reader.read_bits(2)? as u8;
+    if bits != 0b11 {
+        return Ok(3 + bits);
+    }
+
+    let bits = reader.read_bits(5)? as u8;
+    if bits != 0b1_1111 {
+        return Ok(6 + bits);
+    }
+
+    Ok(37 + reader.read_bits(7)? as u8)
+}
+
+// Table B.4 - Codewords for the number of coding passes, from 1 to 164
+fn encode_no_passes(writer: &mut PacketHeaderWriter, no_passes: u8) {
+    match no_passes {
+        1 => writer.write_bit(0),
+        2 => writer.write_bits(0b10, 2),
+        3..=5 => writer.write_bits(0b1100 | (no_passes - 3) as u32, 4),
+        6..=36 => writer.write_bits(0b1_1110_0000 | (no_passes - 6) as u32, 9),
+        _ => writer.write_bits(0b1111_1111_1000_0000 | (no_passes - 37) as u32, 16),
+    }
+}
@@ -0,0 +1,402 @@
+use std::error;
+
+use super::geometry::{self, Rectangle};
+use super::{CodestreamError, CodingStyleComponentSegmentProgression, ProgressionOrder};
+
+/// A packet of a tile, identified by its layer, resolution level, component
+/// and precinct, see B.9
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Packet {
+    pub layer: u16,
+    pub resolution: u8,
+    pub component: u16,
+    pub precinct: u32,
+}
+
+/// Precinct partition of a resolution level of a tile-component, see B.6
+#[derive(Debug, Clone, Default)]
+pub struct ResolutionPrecincts {
+    /// Resolution level on its own grid, (trx0, try0) to (trx1, try1)
+    pub region: Rectangle,
+
+    /// Precinct width and height exponents (PPx, PPy)
+    pub ppx: u8,
+    pub ppy: u8,
+}
+
+impl ResolutionPrecincts {
+    fn no_precincts(&self) -> u32 {
+        let (wide, high) = geometry::no_precincts(&self.region, self.ppx, self.ppy);
+        wide * high
+    }
+}
+
+/// Precincts of each resolution level of a tile-component
+#[derive(Debug, Clone, Default)]
+pub struct TileComponentPrecincts {
+    /// XRsiz and YRsiz of the component
+    pub horizontal_separation: u8,
+    pub vertical_separation: u8,
+
+    pub resolutions: Vec<ResolutionPrecincts>,
+}
+
+/// The bounds and order of a progression, either every packet of a tile in
+/// the progression order of COD, or a progression of a POC marker segment,
+/// see A.6.6
+#[derive(Debug, PartialEq)]
+pub struct Progression {
+    // LYEpoc: Layer index (exclusive) for the end of the progression
+    pub layer_end: u16,
+
+    // RSpoc and REpoc: Resolution level indexes (inclusive, exclusive)
+    pub resolution_start: u8,
+    pub resolution_end: u8,
+
+    // CSpoc and CEpoc: Component indexes (inclusive, exclusive)
+    pub component_start: u16,
+    pub component_end: u16,
+
+    // Ppoc: Progression order
+    pub progression_order: ProgressionOrder,
+}
+
+impl Progression {
+    /// Every packet of a tile with the number of layers, resolution levels and
+    /// components in a progression order
+    pub fn new(
+        progression_order: ProgressionOrder,
+        no_layers: u16,
+        no_resolutions: u8,
+        no_components: u16,
+    ) -> Progression {
+        Progression {
+            layer_end: no_layers,
+            resolution_start: 0,
+            resolution_end: no_resolutions,
+            component_start: 0,
+            component_end: no_components,
+            progression_order,
+        }
+    }
+}
+
+impl From<&CodingStyleComponentSegmentProgression> for Progression {
+    fn from(progression: &CodingStyleComponentSegmentProgression) -> Progression {
+        Progression {
+            layer_end: progression.layer_index_end(),
+            resolution_start: progression.resolution_level_index_start(),
+            resolution_end: progression.resolution_level_index_end(),
+            component_start: progression.component_index_start(),
+            component_end: progression.component_index_end(),
+            progression_order: progression.progression_order(),
+        }
+    }
+}
+
+// Packets of a tile in sequence, where every packet is only included once and
+// the layers of a precinct are included in order.
+struct Sequence<'a> {
+    components: &'a [TileComponentPrecincts],
+
+    // Next layer of each precinct of each resolution level of each component
+    next_layers: Vec<Vec<Vec<u16>>>,
+
+    packets: Vec<Packet>,
+}
+
+impl<'a> Sequence<'a> {
+    fn new(components: &'a [TileComponentPrecincts]) -> Sequence<'a> {
+        let next_layers = components
+            .iter()
+            .map(|component| {
+                component
+                    .resolutions
+                    .iter()
+                    .map(|resolution| vec![0; resolution.no_precincts() as usize])
+                    .collect()
+            })
+            .collect();
+
+        Sequence {
+            components,
+            next_layers,
+            packets: vec![],
+        }
+    }
+
+    fn resolution(&self, component: u16, resolution: u8) -> Option<&'a ResolutionPrecincts> {
+        self.components
+            .get(component as usize)
+            .and_then(|c| c.resolutions.get(resolution as usize))
+    }
+
+    fn include(&mut self, layer: u16, resolution: u8, component: u16, precinct: u32) {
+        let next_layer = match self
+            .next_layers
+            .get_mut(component as usize)
+            .and_then(|c| c.get_mut(resolution as usize))
+            .and_then(|r| r.get_mut(precinct as usize))
+        {
+            Some(next_layer) => next_layer,
+            None => return,
+        };
+
+        // Packets that have already been included are not included again
+        if layer != *next_layer {
+            return;
+        }
+        *next_layer += 1;
+
+        self.packets.push(Packet {
+            layer,
+            resolution,
+            component,
+            precinct,
+        });
+    }
+
+    // Every precinct of a resolution level of a component
+    fn include_precincts(&mut self, layer: u16, resolution: u8, component: u16) {
+        let no_precincts = self
+            .resolution(component, resolution)
+            .map_or(0, |r| r.no_precincts());
+        for precinct in 0..no_precincts {
+            self.include(layer, resolution, component, precinct);
+        }
+    }
+
+    // B.12.1.3 - The precinct of a resolution level of a component which
+    // starts at the reference grid point (x, y) of the tile, if any
+    fn precinct_at(
+        &self,
+        tile: &Rectangle,
+        component: u16,
+        resolution: u8,
+        x: u64,
+        y: u64,
+    ) -> Option<u32> {
+        let tile_component = self.components.get(component as usize)?;
+        let precincts = tile_component.resolutions.get(resolution as usize)?;
+        if precincts.region.is_empty() {
+            return None;
+        }
+
+        let level = (tile_component.resolutions.len() - 1 - resolution as usize) as u32;
+        let (dx, dy) = (
+            tile_component.horizontal_separation as u64,
+            tile_component.vertical_separation as u64,
+        );
+        let (trx0, try0) = (precincts.region.x0() as u64, precincts.region.y0() as u64);
+        let (ppx, ppy) = (precincts.ppx as u32, precincts.ppy as u32);
+
+        let starts_row = y.is_multiple_of(dy << (ppy + level))
+            || (y == tile.y0() as u64 && !(try0 << level).is_multiple_of(1 << (ppy + level)));
+        let starts_column = x.is_multiple_of(dx << (ppx + level))
+            || (x == tile.x0() as u64 && !(trx0 << level).is_multiple_of(1 << (ppx + level)));
+        if !starts_row || !starts_column {
+            return None;
+        }
+
+        let (wide, _) = geometry::no_precincts(&precincts.region, precincts.ppx, precincts.ppy);
+        let i = (x.div_ceil(dx << level) >> ppx) - (trx0 >> ppx);
+        let j = (y.div_ceil(dy << level) >> ppy) - (try0 >> ppy);
+        Some((i + j * wide as u64) as u32)
+    }
+
+    // Reference grid steps between the precincts of the resolution levels and
+    // components of a progression
+    fn steps(&self, progression: &Progression) -> Vec<(u64, u64)> {
+        let mut steps = vec![];
+        for c in progression.component_start..progression.component_end {
+            let component = match self.components.get(c as usize) {
+                Some(component) => component,
+                None => continue,
+            };
+            let no_resolutions = component.resolutions.len();
+            for r in progression.resolution_start..progression.resolution_end {
+                let precincts = match component.resolutions.get(r as usize) {
+                    Some(precincts) => precincts,
+                    None => continue,
+                };
+                let level = (no_resolutions - 1 - r as usize) as u32;
+                steps.push((
+                    (component.horizontal_separation as u64) << (precincts.ppx as u32 + level),
+                    (component.vertical_separation as u64) << (precincts.ppy as u32 + level),
+                ));
+            }
+        }
+        steps
+    }
+
+    // Reference grid points of the tile where a precinct may start, in raster
+    // order. The steps of subsampled components need not divide each other,
+    // so the origins of every step are gathered rather than only those of the
+    // smallest.
+    fn positions(&self, tile: &Rectangle, progression: &Progression) -> Vec<(u64, u64)> {
+        let steps = self.steps(progression);
+        let xs = origins(
+            tile.x0() as u64,
+            tile.x1() as u64,
+            steps.iter().map(|&(x_step, _)| x_step),
+        );
+        let ys = origins(
+            tile.y0() as u64,
+            tile.y1() as u64,
+            steps.iter().map(|&(_, y_step)| y_step),
+        );
+
+        let mut positions = Vec::with_capacity(xs.len() * ys.len());
+        for &y in &ys {
+            for &x in &xs {
+                positions.push((x, y));
+            }
+        }
+        positions
+    }
+
+    // The precinct starting at a position, for every layer of a progression
+    fn include_position(
+        &mut self,
+        tile: &Rectangle,
+        progression: &Progression,
+        resolution: u8,
+        component: u16,
+        (x, y): (u64, u64),
+    ) {
+        if let Some(precinct) = self.precinct_at(tile, component, resolution, x, y) {
+            for layer in 0..progression.layer_end {
+                self.include(layer, resolution, component, precinct);
+            }
+        }
+    }
+
+    fn progress(
+        &mut self,
+        tile: &Rectangle,
+        progression: &Progression,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let layers = 0..progression.layer_end;
+        let resolutions = progression.resolution_start..progression.resolution_end;
+        let components = progression.component_start
+            ..progression.component_end.min(self.components.len() as u16);
+
+        match progression.progression_order {
+            // B.12.1.1 - Layer-resolution level-component-position progression
+            ProgressionOrder::LRLCPP => {
+                for layer in layers {
+                    for resolution in resolutions.clone() {
+                        for component in components.clone() {
+                            self.include_precincts(layer, resolution, component);
+                        }
+                    }
+                }
+            }
+            // B.12.1.2 - Resolution level-layer-component-position progression
+            ProgressionOrder::RLLCPP => {
+                for resolution in resolutions {
+                    for layer in layers.clone() {
+                        for component in components.clone() {
+                            self.include_precincts(layer, resolution, component);
+                        }
+                    }
+                }
+            }
+            // B.12.1.3 - Resolution level-position-component-layer progression
+            ProgressionOrder::RLPCLP => {
+                let positions = self.positions(tile, progression);
+                for resolution in resolutions {
+                    for &position in &positions {
+                        for component in components.clone() {
+                            self.include_position(
+                                tile,
+                                progression,
+                                resolution,
+                                component,
+                                position,
+                            );
+                        }
+                    }
+                }
+            }
+            // B.12.1.4 - Position-component-resolution level-layer progression
+            ProgressionOrder::PCRLLP => {
+                for position in self.positions(tile, progression) {
+                    for component in components.clone() {
+                        for resolution in resolutions.clone() {
+                            self.include_position(
+                                tile,
+                                progression,
+                                resolution,
+                                component,
+                                position,
+                            );
+                        }
+                    }
+                }
+            }
+            // B.12.1.5 - Component-position-resolution level-layer progression
+            ProgressionOrder::CPRLLP => {
+                let positions = self.positions(tile, progression);
+                for component in components {
+                    for &position in &positions {
+                        for resolution in resolutions.clone() {
+                            self.include_position(
+                                tile,
+                                progression,
+                                resolution,
+                                component,
+                                position,
+                            );
+                        }
+                    }
+                }
+            }
+            ProgressionOrder::Reserved { value } => {
+                return Err(CodestreamError::PacketError {
+                    error: format!("reserved progression order {}", value),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The start and every multiple of any of the steps from start (exclusive) to
+// end (exclusive), in increasing order
+fn origins(start: u64, end: u64, steps: impl Iterator<Item = u64>) -> Vec<u64> {
+    if start >= end {
+        return vec![];
+    }
+
+    let mut origins = vec![];
+    for step in steps.filter(|&step| step > 0) {
+        origins.push(start);
+        let mut origin = start + step - start % step;
+        while origin < end {
+            origins.push(origin);
+            origin += step;
+        }
+    }
+    origins.sort_unstable();
+    origins.dedup();
+    origins
+}
+
+/// The packets of a tile in the order they appear in the codestream, for the
+/// progression order of COD or the progressions of POC marker segments in
+/// turn, see B.12
+pub fn packets(
+    tile: &Rectangle,
+    components: &[TileComponentPrecincts],
+    progressions: &[Progression],
+) -> Result<Vec<Packet>, Box<dyn error::Error>> {
+    let mut sequence = Sequence::new(components);
+    for progression in progressions {
+        sequence.progress(tile, progression)?;
+    }
+
+    Ok(sequence.packets)
+}
@@ -0,0 +1,219 @@
+use std::error;
+
+use super::{CodestreamError, QuantizationStyle, SubbandOrientation};
+
+/// Reconstruction bias placing reconstructed values at the midpoint of the
+/// quantization interval, r in Equation E-6.
+pub const DEFAULT_RECONSTRUCTION_BIAS: f32 = 0.5;
+
+/// Quantization of the subbands of a tile-component, see Annex E
+///
+/// Built from the quantization style and values of the QCD or QCC marker
+/// segment which applies to the tile-component. Subbands are identified by
+/// their resolution level and orientation, with the NLLL subband at
+/// resolution level 0 and the HL, LH and HH subbands of decomposition level
+/// NL - r + 1 at resolution level r.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantization {
+    style: QuantizationStyle,
+    no_decomposition_levels: u8,
+    exponents: Vec<u8>,
+    mantissas: Vec<u16>,
+}
+
+impl Quantization {
+    pub fn new(
+        style: QuantizationStyle,
+        exponents: &[u8],
+        mantissas: &[u16],
+        no_decomposition_levels: u8,
+    ) -> Result<Quantization, Box<dyn error::Error>> {
+        let no_subbands = 3 * no_decomposition_levels as usize + 1;
+        let no_values = match style {
+            QuantizationStyle::No { guard: _ }
+            | QuantizationStyle::ScalarExpounded { guard: _ } => no_subbands,
+            QuantizationStyle::ScalarDerived { guard: _ } => 1,
+            QuantizationStyle::Reserved { value } => {
+                return Err(CodestreamError::QuantizationError {
+                    error: format!("reserved quantization style {:08b}", value),
+                }
+                .into());
+            }
+        };
+
+        if exponents.len() < no_values || mantissas.len() < no_values {
+            return Err(CodestreamError::QuantizationError {
+                error: format!(
+                    "{} step sizes signalled for {} subbands",
+                    exponents.len().min(mantissas.len()),
+                    no_values
+                ),
+            }
+            .into());
+        }
+
+        // E.1.1.1 - Derived exponents decrease by one for every decomposition
+        // level above the lowest, and must not fall below zero.
+        if let QuantizationStyle::ScalarDerived { guard: _ } = style {
+            if exponents[0] + 1 < no_decomposition_levels {
+                return Err(CodestreamError::QuantizationError {
+                    error: format!(
+                        "exponent {} is too small to derive {} decomposition levels",
+                        exponents[0], no_decomposition_levels
+                    ),
+                }
+                .into());
+            }
+        }
+
+        Ok(Quantization {
+            style,
+            no_decomposition_levels,
+            exponents: exponents[..no_values].to_vec(),
+            mantissas: mantissas[..no_values].to_vec(),
+        })
+    }
+
+    pub fn style(&self) -> QuantizationStyle {
+        self.style
+    }
+
+    pub fn no_decomposition_levels(&self) -> u8 {
+        self.no_decomposition_levels
+    }
+
+    /// Number of guard bits (G)
+    pub fn guard_bits(&self) -> u8 {
+        match self.style {
+            QuantizationStyle::No { guard }
+            | QuantizationStyle::ScalarDerived { guard }
+            | QuantizationStyle::ScalarExpounded { guard } => guard,
+            QuantizationStyle::Reserved { value: _ } => 0,
+        }
+    }
+
+    /// Exponent of the subband step size (ε_b)
+    pub fn exponent(&self, resolution: u8, orientation: SubbandOrientation) -> u8 {
+        match self.style {
+            // Equation E-5, with n_b = NL - r + 1 for all but the NLLL subband
+            QuantizationStyle::ScalarDerived { guard: _ } => {
+                if resolution == 0 {
+                    self.exponents[0]
+                } else {
+                    self.exponents[0] + 1 - resolution
+                }
+            }
+            _ => self.exponents[subband_index(resolution, orientation)],
+        }
+    }
+
+    /// Mantissa of the subband step size (μ_b)
+    pub fn mantissa(&self, resolution: u8, orientation: SubbandOrientation) -> u16 {
+        match self.style {
+            // Equation E-5
+            QuantizationStyle::ScalarDerived { guard: _ } => self.mantissas[0],
+            _ => self.mantissas[subband_index(resolution, orientation)],
+        }
+    }
+
+    /// Number of magnitude bits in the quantized coefficients of the
+    /// subband (M_b), Equation E-2
+    pub fn magnitude_bits(&self, resolution: u8, orientation: SubbandOrientation) -> u8 {
+        (self.guard_bits() + self.exponent(resolution, orientation)).saturating_sub(1)
+    }
+
+    /// Quantization step size of the subband (Δ_b), Equation E-3
+    ///
+    /// The nominal dynamic range of the subband (R_b) is the precision of the
+    /// component samples plus the log2 gain of the subband from Table E.1.
+    /// Without quantization the step size is one.
+    pub fn step_size(&self, resolution: u8, orientation: SubbandOrientation, precision: u8) -> f32 {
+        if let QuantizationStyle::No { guard: _ } = self.style {
+            return 1.0;
+        }
+
+        let gain = match orientation {
+            SubbandOrientation::LL => 0,
+            SubbandOrientation::HL | SubbandOrientation::LH => 1,
+            SubbandOrientation::HH => 2,
+        };
+        let dynamic_range = precision as i32 + gain;
+        let exponent = self.exponent(resolution, orientation) as i32;
+        let mantissa = self.mantissa(resolution, orientation) as f32;
+
+        2f32.powi(dynamic_range - exponent) * (1.0 + mantissa / 2048.0)
+    }
+}
+
+// Subbands are signalled in the order NLLL, then HL, LH and HH for each
+// decomposition level from NL down to 1.
+fn subband_index(resolution: u8, orientation: SubbandOrientation) -> usize {
+    if resolution == 0 {
+        return 0;
+    }
+
+    let offset = match orientation {
+        SubbandOrientation::LL => return 0,
+        SubbandOrientation::HL => 1,
+        SubbandOrientation::LH => 2,
+        SubbandOrientation::HH => 3,
+    };
+    3 * (resolution as usize - 1) + offset
+}
+
+/// Reconstructed transformation coefficients of a code-block in an
+/// irreversible subband, Equation E-6
+///
+/// The quantized coefficients have their decoded bit-planes aligned to the
+/// magnitude bits (M_b), of which no_bit_planes (N_b) were decoded including
+/// the missing most significant bit-planes. Non-zero coefficients are
+/// reconstructed within their quantization interval at the reconstruction
+/// bias (r), which is commonly 0.5.
+pub fn dequantize(
+    coefficients: &[i32],
+    step_size: f32,
+    magnitude_bits: u8,
+    no_bit_planes: u8,
+    reconstruction_bias: f32,
+) -> Vec<f32> {
+    let undecoded = magnitude_bits.saturating_sub(no_bit_planes) as i32;
+    let bias = reconstruction_bias * 2f32.powi(undecoded);
+
+    coefficients
+        .iter()
+        .map(|&coefficient| match coefficient {
+            0 => 0.0,
+            q if q > 0 => (q as f32 + bias) * step_size,
+            q => (q as f32 - bias) * step_size,
+        })
+        .collect()
+}
+
+/// Reconstructed transformation coefficients of a code-block in a reversible
+/// subband, Equation E-6 with a step size of one
+///
+/// Coefficients are exact once all of their magnitude bits (M_b) are decoded.
+/// Those of a code-block truncated before its last bit-plane are
+/// reconstructed within the interval of the missing bit-planes at the
+/// reconstruction bias (r), rounded down to an integer.
+pub fn dequantize_reversible(
+    coefficients: &[i32],
+    magnitude_bits: u8,
+    no_bit_planes: u8,
+    reconstruction_bias: f32,
+) -> Vec<i32> {
+    let undecoded = magnitude_bits.saturating_sub(no_bit_planes) as i32;
+    if undecoded == 0 {
+        return coefficients.to_vec();
+    }
+    let bias = (reconstruction_bias * 2f32.powi(undecoded)) as i32;
+
+    coefficients
+        .iter()
+        .map(|&coefficient| match coefficient {
+            0 => 0,
+            q if q > 0 => q + bias,
+            q => q - bias,
+        })
+        .collect()
+}
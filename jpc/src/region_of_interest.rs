@@ -0,0 +1,50 @@
+/// Descale the quantized coefficients of a code-block coded with the Maxshift
+/// method, see H.2
+///
+/// The encoder scales the coefficients of the region of interest up by s, the
+/// ROI shift of SPrgn, so that their magnitudes are at least 2^s while those
+/// of the background are below it. After decoding with M_b + s magnitude bits
+/// the background is left as is and the region of interest is scaled back
+/// down by s.
+pub fn descale(coefficients: &mut [i32], shift: u8) {
+    if shift == 0 || shift > 31 {
+        return;
+    }
+
+    for coefficient in coefficients.iter_mut() {
+        let magnitude = coefficient.unsigned_abs();
+        if magnitude >> shift != 0 {
+            let magnitude = (magnitude >> shift) as i32;
+            *coefficient = if *coefficient < 0 {
+                -magnitude
+            } else {
+                magnitude
+            };
+        }
+    }
+}
+
+/// Split the quantized coefficients of a code-block coded with the Maxshift
+/// method into the background and the region of interest, descaled by s,
+/// each zero where the other has its coefficients, see H.2
+///
+/// The bit-planes decoded of the code-block are those of M_b + s magnitude
+/// bits. The background keeps its magnitudes and misses as many of them as
+/// the code-block, while the region of interest misses s fewer of its M_b
+/// magnitude bits, so each has its own reconstruction bias.
+pub fn split(coefficients: &[i32], shift: u8) -> (Vec<i32>, Vec<i32>) {
+    let mut background = coefficients.to_vec();
+    let mut region = vec![0; coefficients.len()];
+    if shift == 0 || shift > 31 {
+        return (background, region);
+    }
+
+    for (coefficient, value) in background.iter_mut().zip(region.iter_mut()) {
+        if coefficient.unsigned_abs() >> shift != 0 {
+            *value = *coefficient;
+            *coefficient = 0;
+        }
+    }
+    descale(&mut region, shift);
+    (background, region)
+}
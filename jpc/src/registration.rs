@@ -0,0 +1,81 @@
+use crate::geometry::Rectangle;
+
+// Xcrg and Ycrg are in units of 1/65536 of the sample separation, see A.9.1
+const OFFSET_SCALE: f64 = 65536.0;
+
+/// Upsample the samples of a component to the points of a rectangle on the
+/// reference grid, by bilinear interpolation of the samples around each
+/// point, see B.2 and A.9.1
+///
+/// The samples cover a rectangle of the component, with the sample at (u, v)
+/// registered at the point ((u + Xcrg / 65536) * XRsiz, (v + Ycrg / 65536) *
+/// YRsiz) of the reference grid, given the separations XRsiz and YRsiz and
+/// the component registration offsets Xcrg and Ycrg. Points before the first
+/// or after the last sample take the value of that sample.
+pub fn upsample(
+    samples: &[i32],
+    component: &Rectangle,
+    separation: (u8, u8),
+    offset: (u16, u16),
+    target: &Rectangle,
+) -> Vec<i32> {
+    let size = target.width() as usize * target.height() as usize;
+    if component.is_empty() {
+        return vec![0; size];
+    }
+
+    let columns = weights(
+        target.x0(),
+        target.x1(),
+        component.x0(),
+        component.x1(),
+        separation.0,
+        offset.0,
+    );
+    let rows = weights(
+        target.y0(),
+        target.y1(),
+        component.y0(),
+        component.y1(),
+        separation.1,
+        offset.1,
+    );
+
+    let width = component.width() as usize;
+    let mut upsampled = Vec::with_capacity(size);
+    for &(v0, v1, wy) in &rows {
+        for &(u0, u1, wx) in &columns {
+            let top = interpolate(samples[v0 * width + u0], samples[v0 * width + u1], wx);
+            let bottom = interpolate(samples[v1 * width + u0], samples[v1 * width + u1], wx);
+            upsampled.push((top + (bottom - top) * wy).round() as i32);
+        }
+    }
+    upsampled
+}
+
+fn interpolate(a: i32, b: i32, weight: f64) -> f64 {
+    a as f64 + (b - a) as f64 * weight
+}
+
+// For each point from p0 up to p1 on one axis of the reference grid, the
+// indexes of the samples on either side of it, relative to the first sample
+// at s0, and the weight of the second one
+fn weights(
+    p0: u32,
+    p1: u32,
+    s0: u32,
+    s1: u32,
+    separation: u8,
+    offset: u16,
+) -> Vec<(usize, usize, f64)> {
+    let last = (s1 - s0 - 1) as f64;
+    (p0..p1)
+        .map(|p| {
+            let position = p as f64 / separation.max(1) as f64 - offset as f64 / OFFSET_SCALE;
+            let position = (position - s0 as f64).clamp(0.0, last);
+            let first = position.floor();
+            let second = (first + 1.0).min(last);
+            (first as usize, second as usize, position - first)
+        })
+        .collect()
+}
@@ -0,0 +1,131 @@
+// G.3 - Irreversible component transformation (ICT) coefficients
+const ICT_CR_TO_RED: f32 = 1.402;
+const ICT_CB_TO_GREEN: f32 = 0.344_13;
+const ICT_CR_TO_GREEN: f32 = 0.714_14;
+const ICT_CB_TO_BLUE: f32 = 1.772;
+
+// G.3 - Irreversible component transformation (ICT) coefficients of the
+// forward transformation
+const ICT_RED_TO_Y: f32 = 0.299;
+const ICT_GREEN_TO_Y: f32 = 0.587;
+const ICT_BLUE_TO_Y: f32 = 0.114;
+const ICT_RED_TO_CB: f32 = -0.168_75;
+const ICT_GREEN_TO_CB: f32 = -0.331_26;
+const ICT_BLUE_TO_CB: f32 = 0.5;
+const ICT_RED_TO_CR: f32 = 0.5;
+const ICT_GREEN_TO_CR: f32 = -0.418_69;
+const ICT_BLUE_TO_CR: f32 = -0.081_31;
+
+/// Forward reversible component transformation (RCT), see G.2.1
+///
+/// Transforms the first three components (I0, I1, I2) of a tile in place into
+/// (Y0, Y1, Y2). Only used with the 5-3 reversible filter.
+pub fn forward_reversible(c0: &mut [i32], c1: &mut [i32], c2: &mut [i32]) {
+    for ((i0, i1), i2) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let y0 = (*i0 + 2 * *i1 + *i2) >> 2;
+        let y1 = *i2 - *i1;
+        let y2 = *i0 - *i1;
+
+        *i0 = y0;
+        *i1 = y1;
+        *i2 = y2;
+    }
+}
+
+/// Forward irreversible component transformation (ICT), see G.3.1
+///
+/// Transforms the first three components (I0, I1, I2) of a tile in place into
+/// (Y0, Y1, Y2). Only used with the 9-7 irreversible filter.
+pub fn forward_irreversible(c0: &mut [f32], c1: &mut [f32], c2: &mut [f32]) {
+    for ((i0, i1), i2) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let y0 = ICT_RED_TO_Y * *i0 + ICT_GREEN_TO_Y * *i1 + ICT_BLUE_TO_Y * *i2;
+        let y1 = ICT_RED_TO_CB * *i0 + ICT_GREEN_TO_CB * *i1 + ICT_BLUE_TO_CB * *i2;
+        let y2 = ICT_RED_TO_CR * *i0 + ICT_GREEN_TO_CR * *i1 + ICT_BLUE_TO_CR * *i2;
+
+        *i0 = y0;
+        *i1 = y1;
+        *i2 = y2;
+    }
+}
+
+/// Inverse reversible component transformation (RCT), see G.2.2
+///
+/// Transforms the first three components (Y0, Y1, Y2) of a tile in place
+/// back into (I0, I1, I2). Only used with the 5-3 reversible filter.
+pub fn inverse_reversible(c0: &mut [i32], c1: &mut [i32], c2: &mut [i32]) {
+    for ((y0, y1), y2) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let i1 = *y0 - ((*y2 + *y1) >> 2);
+        let i0 = *y2 + i1;
+        let i2 = *y1 + i1;
+
+        *y0 = i0;
+        *y1 = i1;
+        *y2 = i2;
+    }
+}
+
+/// Inverse irreversible component transformation (ICT), see G.3.2
+///
+/// Transforms the first three components (Y0, Y1, Y2) of a tile in place
+/// back into (I0, I1, I2). Only used with the 9-7 irreversible filter.
+pub fn inverse_irreversible(c0: &mut [f32], c1: &mut [f32], c2: &mut [f32]) {
+    for ((y0, y1), y2) in c0.iter_mut().zip(c1.iter_mut()).zip(c2.iter_mut()) {
+        let i0 = *y0 + ICT_CR_TO_RED * *y2;
+        let i1 = *y0 - ICT_CB_TO_GREEN * *y1 - ICT_CR_TO_GREEN * *y2;
+        let i2 = *y0 + ICT_CB_TO_BLUE * *y1;
+
+        *y0 = i0;
+        *y1 = i1;
+        *y2 = i2;
+    }
+}
+
+/// Forward DC level shifting of component samples, see G.1.1
+///
+/// Unsigned samples are shifted down by 2^(Ssiz - 1), signed samples are
+/// left unchanged.
+pub fn forward_dc_level_shift(samples: &mut [i32], precision: u8, signed: bool) {
+    let (shift, _, _) = nominal_range(precision, signed);
+
+    for sample in samples.iter_mut() {
+        *sample = (*sample as i64 - shift) as i32;
+    }
+}
+
+/// Inverse DC level shifting of component samples, see G.1.2
+///
+/// Unsigned samples are shifted up by 2^(Ssiz - 1), then every sample is
+/// clamped to the nominal range of its precision.
+pub fn inverse_dc_level_shift(samples: &mut [i32], precision: u8, signed: bool) {
+    let (shift, min, max) = nominal_range(precision, signed);
+
+    for sample in samples.iter_mut() {
+        *sample = (*sample as i64 + shift).clamp(min, max) as i32;
+    }
+}
+
+/// Inverse DC level shifting of irreversibly transformed component samples,
+/// which are rounded to the nearest integer first, see G.1.2
+pub fn inverse_dc_level_shift_irreversible(
+    samples: &[f32],
+    precision: u8,
+    signed: bool,
+) -> Vec<i32> {
+    let (shift, min, max) = nominal_range(precision, signed);
+
+    samples
+        .iter()
+        .map(|sample| (sample.round() as i64 + shift).clamp(min, max) as i32)
+        .collect()
+}
+
+// The DC level shift and the minimum and maximum sample value of a component
+// with the given precision in bits.
+fn nominal_range(precision: u8, signed: bool) -> (i64, i64, i64) {
+    let half = 1i64 << (precision.clamp(1, 32) - 1);
+    if signed {
+        (0, -half, half - 1)
+    } else {
+        (half, 0, 2 * half - 1)
+    }
+}
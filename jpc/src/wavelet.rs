@@ -0,0 +1,359 @@
+// Table F.4 - Definition of lifting parameters for the 9-7 irreversible filter
+const ALPHA: f32 = -1.586_134_3;
+const BETA: f32 = -0.052_980_118;
+const GAMMA: f32 = 0.882_911_1;
+const DELTA: f32 = 0.443_506_87;
+const K: f32 = 1.230_174_1;
+
+fn ceil_div(a: u32, b: u32) -> u32 {
+    a.div_ceil(b)
+}
+
+/// Inverse discrete wavelet transformation of a tile-component with the 5-3
+/// reversible filter, see F.3
+///
+/// The coefficients are stored row by row with the width of the
+/// tile-component (tcx1 - tcx0) as the stride. Before the transformation the
+/// subbands of each decomposition level are arranged with the LL subband in
+/// the top left corner, the HL subband to its right, the LH subband below it
+/// and the HH subband diagonally, recursively for the LL subband. Afterwards
+/// the tile-component samples occupy the whole buffer.
+pub fn inverse_reversible(
+    data: &mut [i32],
+    tcx0: u32,
+    tcy0: u32,
+    tcx1: u32,
+    tcy1: u32,
+    no_decomposition_levels: u8,
+) {
+    inverse(
+        data,
+        (tcx0, tcy0, tcx1, tcy1),
+        no_decomposition_levels,
+        inverse_1d_reversible,
+    );
+}
+
+/// Inverse discrete wavelet transformation of a tile-component with the 9-7
+/// irreversible filter, see F.3
+///
+/// The coefficients are arranged as for [`inverse_reversible`].
+pub fn inverse_irreversible(
+    data: &mut [f32],
+    tcx0: u32,
+    tcy0: u32,
+    tcx1: u32,
+    tcy1: u32,
+    no_decomposition_levels: u8,
+) {
+    inverse(
+        data,
+        (tcx0, tcy0, tcx1, tcy1),
+        no_decomposition_levels,
+        inverse_1d_irreversible,
+    );
+}
+
+/// Forward discrete wavelet transformation of a tile-component with the 5-3
+/// reversible filter, see F.4
+///
+/// The tile-component samples occupy the whole buffer, stored row by row with
+/// the width of the tile-component (tcx1 - tcx0) as the stride. Afterwards
+/// the subbands are arranged as [`inverse_reversible`] takes them, with the
+/// number of low-pass and high-pass coefficients of each row and column
+/// following from the parity of the tile-component coordinates.
+pub fn forward_reversible(
+    data: &mut [i32],
+    tcx0: u32,
+    tcy0: u32,
+    tcx1: u32,
+    tcy1: u32,
+    no_decomposition_levels: u8,
+) {
+    forward(
+        data,
+        (tcx0, tcy0, tcx1, tcy1),
+        no_decomposition_levels,
+        forward_1d_reversible,
+    );
+}
+
+/// Forward discrete wavelet transformation of a tile-component with the 9-7
+/// irreversible filter, see F.4
+///
+/// The samples and coefficients are arranged as for [`forward_reversible`].
+pub fn forward_irreversible(
+    data: &mut [f32],
+    tcx0: u32,
+    tcy0: u32,
+    tcx1: u32,
+    tcy1: u32,
+    no_decomposition_levels: u8,
+) {
+    forward(
+        data,
+        (tcx0, tcy0, tcx1, tcy1),
+        no_decomposition_levels,
+        forward_1d_irreversible,
+    );
+}
+
+// F.3.2 - The 2D_SR procedure
+//
+// Starting from the lowest resolution, each decomposition level is
+// reconstructed by interleaving its four subbands (2D_INTERLEAVE) and
+// filtering first every row (HOR_SR) then every column (VER_SR).
+fn inverse<T: Copy + Default>(
+    data: &mut [T],
+    (tcx0, tcy0, tcx1, tcy1): (u32, u32, u32, u32),
+    no_decomposition_levels: u8,
+    filter: fn(&mut [T], u32),
+) {
+    let stride = (tcx1 - tcx0) as usize;
+    let mut line: Vec<T> = vec![];
+
+    for level in (1..=no_decomposition_levels as u32).rev() {
+        // Coordinates of the resolution being reconstructed, which is the LL
+        // subband of the next lower decomposition level
+        let scale = 1 << (level - 1);
+        let (u0, u1) = (ceil_div(tcx0, scale), ceil_div(tcx1, scale));
+        let (v0, v1) = (ceil_div(tcy0, scale), ceil_div(tcy1, scale));
+        let width = (u1 - u0) as usize;
+        let height = (v1 - v0) as usize;
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        // HOR_SR
+        line.resize(width, T::default());
+        for y in 0..height {
+            let row = &mut data[y * stride..y * stride + width];
+            interleave(row.iter().copied(), &mut line, u0, u1);
+            filter(&mut line, u0);
+            row.copy_from_slice(&line);
+        }
+
+        // VER_SR
+        line.resize(height, T::default());
+        for x in 0..width {
+            let column = (0..height).map(|y| data[y * stride + x]);
+            interleave(column, &mut line, v0, v1);
+            filter(&mut line, v0);
+            for (y, value) in line.iter().enumerate() {
+                data[y * stride + x] = *value;
+            }
+        }
+    }
+}
+
+// F.4.2 - The 2D_SD procedure
+//
+// Starting from the tile-component, each decomposition level is computed by
+// filtering first every column (VER_SD) then every row (HOR_SD) of the LL
+// subband of the level above, and splitting the result into its four
+// subbands (2D_DEINTERLEAVE).
+fn forward<T: Copy + Default>(
+    data: &mut [T],
+    (tcx0, tcy0, tcx1, tcy1): (u32, u32, u32, u32),
+    no_decomposition_levels: u8,
+    filter: fn(&mut [T], u32),
+) {
+    let stride = (tcx1 - tcx0) as usize;
+    let mut line: Vec<T> = vec![];
+
+    for level in 1..=no_decomposition_levels as u32 {
+        // Coordinates of the resolution being decomposed
+        let scale = 1 << (level - 1);
+        let (u0, u1) = (ceil_div(tcx0, scale), ceil_div(tcx1, scale));
+        let (v0, v1) = (ceil_div(tcy0, scale), ceil_div(tcy1, scale));
+        let width = (u1 - u0) as usize;
+        let height = (v1 - v0) as usize;
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        // VER_SD
+        line.resize(height, T::default());
+        for x in 0..width {
+            for (y, value) in line.iter_mut().enumerate() {
+                *value = data[y * stride + x];
+            }
+            filter(&mut line, v0);
+            for (y, value) in deinterleave(&line, v0, v1).enumerate() {
+                data[y * stride + x] = value;
+            }
+        }
+
+        // HOR_SD
+        line.resize(width, T::default());
+        for y in 0..height {
+            let row = &mut data[y * stride..y * stride + width];
+            line.copy_from_slice(row);
+            filter(&mut line, u0);
+            for (value, coefficient) in row.iter_mut().zip(deinterleave(&line, u0, u1)) {
+                *value = coefficient;
+            }
+        }
+    }
+}
+
+// F.4.5 - The 2D_DEINTERLEAVE procedure, in one dimension
+//
+// The low-pass coefficients at the even indices of the interval [i0, i1) come
+// first, followed by the high-pass coefficients at the odd indices.
+fn deinterleave<T: Copy>(line: &[T], i0: u32, i1: u32) -> impl Iterator<Item = T> + '_ {
+    let first_low = (i0 % 2) as usize;
+    let first_high = 1 - first_low;
+    debug_assert_eq!(line.len(), (i1 - i0) as usize);
+
+    line.iter()
+        .skip(first_low)
+        .step_by(2)
+        .chain(line.iter().skip(first_high).step_by(2))
+        .copied()
+}
+
+// F.3.3 - The 2D_INTERLEAVE procedure, in one dimension
+//
+// The low-pass coefficients are placed at the even indices and the high-pass
+// coefficients at the odd indices of the interval [i0, i1).
+fn interleave<T: Copy>(coefficients: impl Iterator<Item = T>, line: &mut [T], i0: u32, i1: u32) {
+    let no_low = (ceil_div(i1, 2) - ceil_div(i0, 2)) as usize;
+    let first_low = (i0 % 2) as usize;
+    let first_high = 1 - first_low;
+
+    for (k, coefficient) in coefficients.enumerate() {
+        if k < no_low {
+            line[first_low + 2 * k] = coefficient;
+        } else {
+            line[first_high + 2 * (k - no_low)] = coefficient;
+        }
+    }
+}
+
+// F.3.6 - The 1D_SR procedure for the 5-3 reversible filter, Equation F-5
+//
+// The signal is periodically symmetrically extended (1D_EXTR) by mirroring
+// the neighbours of the first and last sample.
+fn inverse_1d_reversible(x: &mut [i32], i0: u32) {
+    let n = x.len();
+    if n == 1 {
+        if i0 % 2 == 1 {
+            x[0] /= 2;
+        }
+        return;
+    }
+
+    let first_even = (i0 % 2) as usize;
+
+    for k in (first_even..n).step_by(2) {
+        let (left, right) = neighbours(x, k);
+        x[k] -= (left + right + 2) >> 2;
+    }
+    for k in (1 - first_even..n).step_by(2) {
+        let (left, right) = neighbours(x, k);
+        x[k] += (left + right) >> 1;
+    }
+}
+
+// F.3.8.2 - The 1D_FILTR_9-7I procedure, Equation F-7
+fn inverse_1d_irreversible(x: &mut [f32], i0: u32) {
+    let n = x.len();
+    if n == 1 {
+        if i0 % 2 == 1 {
+            x[0] /= 2.0;
+        }
+        return;
+    }
+
+    let first_even = (i0 % 2) as usize;
+    let first_odd = 1 - first_even;
+
+    // STEP1 and STEP2
+    for k in (first_even..n).step_by(2) {
+        x[k] *= K;
+    }
+    for k in (first_odd..n).step_by(2) {
+        x[k] *= 1.0 / K;
+    }
+
+    // STEP3 to STEP6
+    for (first, coefficient) in [
+        (first_even, DELTA),
+        (first_odd, GAMMA),
+        (first_even, BETA),
+        (first_odd, ALPHA),
+    ] {
+        for k in (first..n).step_by(2) {
+            let (left, right) = neighbours(x, k);
+            x[k] -= coefficient * (left + right);
+        }
+    }
+}
+
+// F.4.8.1 - The 1D_FILTD procedure for the 5-3 reversible filter,
+// Equation F-9
+fn forward_1d_reversible(x: &mut [i32], i0: u32) {
+    let n = x.len();
+    if n == 1 {
+        if i0 % 2 == 1 {
+            x[0] *= 2;
+        }
+        return;
+    }
+
+    let first_even = (i0 % 2) as usize;
+
+    for k in (1 - first_even..n).step_by(2) {
+        let (left, right) = neighbours(x, k);
+        x[k] -= (left + right) >> 1;
+    }
+    for k in (first_even..n).step_by(2) {
+        let (left, right) = neighbours(x, k);
+        x[k] += (left + right + 2) >> 2;
+    }
+}
+
+// F.4.8.2 - The 1D_FILTD procedure for the 9-7 irreversible filter,
+// Equation F-10
+fn forward_1d_irreversible(x: &mut [f32], i0: u32) {
+    let n = x.len();
+    if n == 1 {
+        if i0 % 2 == 1 {
+            x[0] *= 2.0;
+        }
+        return;
+    }
+
+    let first_even = (i0 % 2) as usize;
+    let first_odd = 1 - first_even;
+
+    // STEP1 to STEP4
+    for (first, coefficient) in [
+        (first_odd, ALPHA),
+        (first_even, BETA),
+        (first_odd, GAMMA),
+        (first_even, DELTA),
+    ] {
+        for k in (first..n).step_by(2) {
+            let (left, right) = neighbours(x, k);
+            x[k] += coefficient * (left + right);
+        }
+    }
+
+    // STEP5 and STEP6
+    for k in (first_odd..n).step_by(2) {
+        x[k] *= K;
+    }
+    for k in (first_even..n).step_by(2) {
+        x[k] *= 1.0 / K;
+    }
+}
+
+// F.3.7 - The 1D_EXTR procedure, with the periodic symmetric extension of a
+// signal of at least two samples only needing the immediate neighbours.
+fn neighbours<T: Copy>(x: &[T], k: usize) -> (T, T) {
+    let left = if k == 0 { x[1] } else { x[k - 1] };
+    let right = if k + 1 == x.len() { x[k - 1] } else { x[k + 1] };
+    (left, right)
+}
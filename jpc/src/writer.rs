@@ -0,0 +1,675 @@
+use std::error;
+use std::io;
+
+use super::{
+    CodestreamError, CodingStyleComponentSegment, CodingStyleMarkerSegment, CodingStyleParameters,
+    CommentMarkerSegment, ComponentRegistrationSegment, ContiguousCodestream, Header,
+    ImageAndTileSizeMarkerSegment, MarkerSymbol, PackedPacketHeaderSegment, PacketLengthSegment,
+    ProgressionOrderChangeSegment, QuantizationComponentSegment, QuantizationDefaultMarkerSegment,
+    QuantizationValue, RegionOfInterestSegment, StartOfTileSegment, Tile,
+    TilePackedPacketHeaderSegment, TilePacketLength, TilePartLengthsSegment, TilePartParameterSize,
+    MARKER_SYMBOL_COC, MARKER_SYMBOL_COD, MARKER_SYMBOL_COM, MARKER_SYMBOL_CRG, MARKER_SYMBOL_EOC,
+    MARKER_SYMBOL_PLM, MARKER_SYMBOL_PLT, MARKER_SYMBOL_POC, MARKER_SYMBOL_PPM, MARKER_SYMBOL_PPT,
+    MARKER_SYMBOL_QCC, MARKER_SYMBOL_QCD, MARKER_SYMBOL_RGN, MARKER_SYMBOL_SIZ, MARKER_SYMBOL_SOC,
+    MARKER_SYMBOL_SOD, MARKER_SYMBOL_SOT, MARKER_SYMBOL_TLM,
+};
+
+// Size of the SOT marker segment and the SOD marker, which Psot counts along
+// with the tile-part header and data
+const SOT_LENGTH: usize = 12;
+const SOD_LENGTH: usize = 2;
+
+/// Writes the markers and marker segments of a codestream, see Annex A.
+///
+/// The length of each marker segment is computed from its parameters, so a
+/// codestream that is decoded and written again has the same bytes.
+pub struct CodestreamWriter<W: io::Write> {
+    writer: W,
+
+    // Csiz of the last SIZ marker segment written, which decides whether
+    // component indexes take one or two bytes
+    no_components: u16,
+}
+
+impl<W: io::Write> CodestreamWriter<W> {
+    pub fn new(writer: W) -> CodestreamWriter<W> {
+        CodestreamWriter {
+            writer,
+            no_components: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write the main header, the tile-parts in the order they were found in
+    /// and the EOC marker
+    pub fn write_codestream(
+        &mut self,
+        codestream: &ContiguousCodestream,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.write_header(codestream.header())?;
+
+        let mut parts: Vec<(&Tile, usize)> = codestream
+            .tiles
+            .iter()
+            .flat_map(|tile| (0..tile.parts.len()).map(move |i| (tile, i)))
+            .collect();
+        parts.sort_by_key(|(tile, i)| tile.parts[*i].start_of_tile_segment.offset());
+        for (tile, i) in parts {
+            self.write_part(tile, i)?;
+        }
+
+        self.write_eoc()
+    }
+
+    // A.3 - The main header, the SOC marker and the SIZ marker segment
+    // followed by the other marker segments in the order they were found,
+    // or in the order of Table A.2 for a header that was not decoded
+    pub fn write_header(&mut self, header: &Header) -> Result<(), Box<dyn error::Error>> {
+        self.write_soc()?;
+        self.write_siz(header.image_and_tile_size_marker_segment())?;
+
+        let markers = if header.markers.is_empty() {
+            header_markers(header)
+        } else {
+            header.markers.clone()
+        };
+
+        let mut counts = MarkerCounts::default();
+        for marker in markers {
+            let n = counts.next(marker);
+            match marker {
+                MARKER_SYMBOL_COD => {
+                    if let Some(segment) = &header.coding_style_marker_segment {
+                        self.write_cod(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_COC => {
+                    if let Some(segment) = header.coding_style_component_segment.get(n) {
+                        self.write_coc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_QCD => {
+                    if let Some(segment) = &header.quantization_default_marker_segment {
+                        self.write_qcd(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_QCC => {
+                    if let Some(segment) = header.quantization_component_segments.get(n) {
+                        self.write_qcc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_RGN => {
+                    if let Some(segment) = header.regions.get(n) {
+                        self.write_rgn(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_POC => {
+                    if let Some(segment) = &header.progression_order_change {
+                        self.write_poc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_PPM => {
+                    if let Some(segment) = header.packed_packet_headers.get(n) {
+                        self.write_ppm(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_TLM => {
+                    if let Some(segment) = &header.tile_part_lengths {
+                        self.write_tlm(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_PLM => {
+                    if let Some(segment) = header.packet_lengths.get(n) {
+                        self.write_plm(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_CRG => {
+                    if let Some(segment) = &header.component_registration {
+                        self.write_crg(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_COM => {
+                    if let Some(segment) = header.comment_marker_segments.get(n) {
+                        self.write_com(segment)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // A.4 - A tile-part of a tile, with the marker segments of its header
+    // taken in turn from those merged into the tile header, and Psot
+    // computed unless it is zero
+    fn write_part(&mut self, tile: &Tile, i: usize) -> Result<(), Box<dyn error::Error>> {
+        let part = &tile.parts[i];
+
+        // The marker segments of the earlier tile-parts come first in the tile
+        // header
+        let mut counts = MarkerCounts::default();
+        for earlier in &tile.parts[..i] {
+            for &marker in &earlier.markers {
+                counts.next(marker);
+            }
+        }
+        let markers = if tile.parts.iter().all(|part| part.markers.is_empty()) {
+            match i {
+                0 => tile_part_markers(tile),
+                _ => vec![],
+            }
+        } else {
+            part.markers.clone()
+        };
+
+        let mut header = CodestreamWriter {
+            writer: vec![],
+            no_components: self.no_components,
+        };
+        let tile_header = &tile.header;
+        for marker in markers {
+            let n = counts.next(marker);
+            match marker {
+                MARKER_SYMBOL_COD => {
+                    if let Some(segment) = &tile_header.coding_style_marker_segment {
+                        header.write_cod(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_COC => {
+                    if let Some(segment) = tile_header.coding_style_component_segments.get(n) {
+                        header.write_coc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_QCD => {
+                    if let Some(segment) = &tile_header.quantization_default_marker_segment {
+                        header.write_qcd(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_QCC => {
+                    if let Some(segment) = tile_header.quantization_component_segments.get(n) {
+                        header.write_qcc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_RGN => {
+                    if let Some(segment) = tile_header.regions.get(n) {
+                        header.write_rgn(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_POC => {
+                    if let Some(segment) = tile_header.progression_order_changes.get(n) {
+                        header.write_poc(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_PPT => {
+                    if let Some(segment) = tile_header.packed_packet_headers.get(n) {
+                        header.write_ppt(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_PLT => {
+                    if let Some(segment) = tile_header.packet_lengths.get(n) {
+                        header.write_plt(segment)?;
+                    }
+                }
+                MARKER_SYMBOL_COM => {
+                    if let Some(segment) = tile_header.comment_marker_segments.get(n) {
+                        header.write_com(segment)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let header = header.into_inner();
+
+        self.write_tile_part(
+            &part.start_of_tile_segment,
+            &header,
+            &part.data,
+            part.start_of_tile_segment.tile_length() == 0,
+        )
+    }
+
+    /// Write a tile-part with its SOT marker segment, header marker segments,
+    /// SOD marker and data, with Psot as the length of all of them, or zero
+    /// for a last tile-part that runs up to the EOC marker
+    pub fn write_tile_part(
+        &mut self,
+        segment: &StartOfTileSegment,
+        header: &[u8],
+        data: &[u8],
+        up_to_eoc: bool,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let tile_length = if up_to_eoc {
+            0
+        } else {
+            let tile_length = SOT_LENGTH + header.len() + SOD_LENGTH + data.len();
+            if tile_length > u32::MAX as usize {
+                return Err(CodestreamError::MarkerError {
+                    marker: MARKER_SYMBOL_SOT,
+                    error: format!("tile-part length {} is too long", tile_length),
+                }
+                .into());
+            }
+            tile_length as u32
+        };
+
+        self.write_sot(&StartOfTileSegment {
+            tile_length: tile_length.to_be_bytes(),
+            ..*segment
+        })?;
+
+        self.writer.write_all(header)?;
+        self.write_sod()?;
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// A.4.1 - Start of codestream
+    pub fn write_soc(&mut self) -> Result<(), Box<dyn error::Error>> {
+        self.write_marker(MARKER_SYMBOL_SOC)
+    }
+
+    /// A.4.2 - Start of tile-part, with Psot as given
+    pub fn write_sot(&mut self, segment: &StartOfTileSegment) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.tile_index);
+        body.extend_from_slice(&segment.tile_length);
+        body.extend_from_slice(&segment.tile_part_index);
+        body.extend_from_slice(&segment.no_tile_parts);
+        self.write_segment(MARKER_SYMBOL_SOT, &body)
+    }
+
+    /// A.4.3 - Start of data
+    pub fn write_sod(&mut self) -> Result<(), Box<dyn error::Error>> {
+        self.write_marker(MARKER_SYMBOL_SOD)
+    }
+
+    /// A.4.4 - End of codestream
+    pub fn write_eoc(&mut self) -> Result<(), Box<dyn error::Error>> {
+        self.write_marker(MARKER_SYMBOL_EOC)
+    }
+
+    /// A.5.1 - Image and tile size, which sets the number of components of the
+    /// marker segments written after it
+    pub fn write_siz(
+        &mut self,
+        segment: &ImageAndTileSizeMarkerSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.decoder_capabilities);
+        body.extend_from_slice(&segment.reference_grid_width);
+        body.extend_from_slice(&segment.reference_grid_height);
+        body.extend_from_slice(&segment.image_horizontal_offset);
+        body.extend_from_slice(&segment.image_vertical_offset);
+        body.extend_from_slice(&segment.reference_tile_width);
+        body.extend_from_slice(&segment.reference_tile_height);
+        body.extend_from_slice(&segment.tile_horizontal_offset);
+        body.extend_from_slice(&segment.tile_vertical_offset);
+        body.extend_from_slice(&segment.no_components);
+        for ((precision, horizontal_separation), vertical_separation) in segment
+            .precision
+            .iter()
+            .zip(&segment.horizontal_separation)
+            .zip(&segment.vertical_separation)
+        {
+            body.extend_from_slice(precision);
+            body.extend_from_slice(horizontal_separation);
+            body.extend_from_slice(vertical_separation);
+        }
+        self.write_segment(MARKER_SYMBOL_SIZ, &body)?;
+
+        self.no_components = segment.no_components();
+        Ok(())
+    }
+
+    /// A.6.1 - Coding style default
+    pub fn write_cod(
+        &mut self,
+        segment: &CodingStyleMarkerSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.coding_style);
+        body.extend_from_slice(&segment.progression_order);
+        body.extend_from_slice(&segment.no_layers);
+        body.extend_from_slice(&segment.multiple_component_transformation);
+        coding_style_parameters(&mut body, &segment.coding_style_parameters);
+        self.write_segment(MARKER_SYMBOL_COD, &body)
+    }
+
+    /// A.6.2 - Coding style component
+    pub fn write_coc(
+        &mut self,
+        segment: &CodingStyleComponentSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        self.component_index(&mut body, segment.index);
+        body.extend_from_slice(&segment.coding_style);
+        coding_style_parameters(&mut body, &segment.coding_style_parameters);
+        self.write_segment(MARKER_SYMBOL_COC, &body)
+    }
+
+    /// A.6.3 - Region of interest
+    pub fn write_rgn(
+        &mut self,
+        segment: &RegionOfInterestSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        self.component_index(&mut body, segment.component_index);
+        body.extend_from_slice(&segment.region_of_interest_style);
+        body.extend_from_slice(&segment.region_of_interest_style_parameter);
+        self.write_segment(MARKER_SYMBOL_RGN, &body)
+    }
+
+    /// A.6.4 - Quantization default
+    pub fn write_qcd(
+        &mut self,
+        segment: &QuantizationDefaultMarkerSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.quantization_style);
+        quantization_values(&mut body, &segment.values);
+        self.write_segment(MARKER_SYMBOL_QCD, &body)
+    }
+
+    /// A.6.5 - Quantization component
+    pub fn write_qcc(
+        &mut self,
+        segment: &QuantizationComponentSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        self.component_index(&mut body, segment.component_index);
+        body.extend_from_slice(&segment.quantization_style);
+        quantization_values(&mut body, &segment.quantization_values);
+        self.write_segment(MARKER_SYMBOL_QCC, &body)
+    }
+
+    /// A.6.6 - Progression order change
+    pub fn write_poc(
+        &mut self,
+        segment: &ProgressionOrderChangeSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        for progression in &segment.progressions {
+            body.extend_from_slice(&progression.resolution_level_index_start);
+            self.component_index(&mut body, progression.component_index_start);
+            body.extend_from_slice(&progression.layer_index_end);
+            body.extend_from_slice(&progression.resolution_level_index_end);
+            self.component_index(&mut body, progression.component_index_end);
+            body.extend_from_slice(&progression.progression_order);
+        }
+        self.write_segment(MARKER_SYMBOL_POC, &body)
+    }
+
+    /// A.7.1 - Tile-part lengths, with Ttlm and Ptlm in the sizes of Stlm
+    pub fn write_tlm(
+        &mut self,
+        segment: &TilePartLengthsSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let parameter_sizes = segment.parameter_sizes();
+
+        let mut body = vec![];
+        body.extend_from_slice(&segment.index);
+        body.extend_from_slice(&segment.parameter_sizes);
+        for tile_part_length in &segment.tile_part_lengths {
+            if parameter_sizes.contains(&TilePartParameterSize::Ttlm8Bit) {
+                body.extend_from_slice(&tile_part_length.tile_index[1..]);
+            } else if parameter_sizes.contains(&TilePartParameterSize::Ttlm16Bit) {
+                body.extend_from_slice(&tile_part_length.tile_index);
+            }
+
+            if parameter_sizes.contains(&TilePartParameterSize::Ptlm16Bit) {
+                body.extend_from_slice(&tile_part_length.tile_length[2..]);
+            } else if parameter_sizes.contains(&TilePartParameterSize::Ptlm32Bit) {
+                body.extend_from_slice(&tile_part_length.tile_length);
+            }
+        }
+        self.write_segment(MARKER_SYMBOL_TLM, &body)
+    }
+
+    /// A.7.2 - Packet length, main header
+    pub fn write_plm(
+        &mut self,
+        segment: &PacketLengthSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.index);
+        body.extend_from_slice(&segment.data);
+        self.write_segment(MARKER_SYMBOL_PLM, &body)
+    }
+
+    /// A.7.3 - Packet length, tile-part header
+    pub fn write_plt(&mut self, segment: &TilePacketLength) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.index);
+        body.extend_from_slice(&segment.packet_length);
+        self.write_segment(MARKER_SYMBOL_PLT, &body)
+    }
+
+    /// A.7.4 - Packed packet headers, main header
+    pub fn write_ppm(
+        &mut self,
+        segment: &PackedPacketHeaderSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.index);
+        body.extend_from_slice(&segment.data);
+        self.write_segment(MARKER_SYMBOL_PPM, &body)
+    }
+
+    /// A.7.5 - Packed packet headers, tile-part header
+    pub fn write_ppt(
+        &mut self,
+        segment: &TilePackedPacketHeaderSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.index);
+        body.extend_from_slice(&segment.data);
+        self.write_segment(MARKER_SYMBOL_PPT, &body)
+    }
+
+    /// A.9.1 - Component registration
+    pub fn write_crg(
+        &mut self,
+        segment: &ComponentRegistrationSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        for (horizontal_offset, vertical_offset) in segment
+            .horizontal_offset
+            .iter()
+            .zip(&segment.vertical_offset)
+        {
+            body.extend_from_slice(horizontal_offset);
+            body.extend_from_slice(vertical_offset);
+        }
+        self.write_segment(MARKER_SYMBOL_CRG, &body)
+    }
+
+    /// A.9.2 - Comment
+    pub fn write_com(
+        &mut self,
+        segment: &CommentMarkerSegment,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let mut body = vec![];
+        body.extend_from_slice(&segment.registration_value);
+        body.extend_from_slice(&segment.comment);
+        self.write_segment(MARKER_SYMBOL_COM, &body)
+    }
+
+    fn write_marker(&mut self, marker: MarkerSymbol) -> Result<(), Box<dyn error::Error>> {
+        self.writer.write_all(&marker)?;
+        Ok(())
+    }
+
+    // A marker segment is the marker, the length of the parameters including
+    // the two bytes of the length itself, and the parameters
+    fn write_segment(
+        &mut self,
+        marker: MarkerSymbol,
+        body: &[u8],
+    ) -> Result<(), Box<dyn error::Error>> {
+        let length = body.len() + 2;
+        if length > u16::MAX as usize {
+            return Err(CodestreamError::MarkerError {
+                marker,
+                error: format!("length {} is too long", length),
+            }
+            .into());
+        }
+
+        self.writer.write_all(&marker)?;
+        self.writer.write_all(&(length as u16).to_be_bytes())?;
+        self.writer.write_all(body)?;
+        Ok(())
+    }
+
+    // Component indexes are 8 bits with fewer than 257 components, and 16
+    // bits otherwise
+    fn component_index(&self, body: &mut Vec<u8>, index: [u8; 2]) {
+        if self.no_components < 257 {
+            body.push(index[1]);
+        } else {
+            body.extend_from_slice(&index);
+        }
+    }
+}
+
+// The number of marker segments of each kind written so far, to take the
+// next one of that kind from a header
+#[derive(Default)]
+struct MarkerCounts {
+    counts: Vec<(MarkerSymbol, usize)>,
+}
+
+impl MarkerCounts {
+    fn next(&mut self, marker: MarkerSymbol) -> usize {
+        match self.counts.iter_mut().find(|(m, _)| *m == marker) {
+            Some((_, count)) => {
+                *count += 1;
+                *count - 1
+            }
+            None => {
+                self.counts.push((marker, 1));
+                0
+            }
+        }
+    }
+}
+
+// Table A.2 - The marker segments of a main header
+fn header_markers(header: &Header) -> Vec<MarkerSymbol> {
+    let mut markers = vec![];
+    if header.coding_style_marker_segment.is_some() {
+        markers.push(MARKER_SYMBOL_COD);
+    }
+    markers.extend(
+        header
+            .coding_style_component_segment
+            .iter()
+            .map(|_| MARKER_SYMBOL_COC),
+    );
+    if header.quantization_default_marker_segment.is_some() {
+        markers.push(MARKER_SYMBOL_QCD);
+    }
+    markers.extend(
+        header
+            .quantization_component_segments
+            .iter()
+            .map(|_| MARKER_SYMBOL_QCC),
+    );
+    markers.extend(header.regions.iter().map(|_| MARKER_SYMBOL_RGN));
+    if header.progression_order_change.is_some() {
+        markers.push(MARKER_SYMBOL_POC);
+    }
+    markers.extend(
+        header
+            .packed_packet_headers
+            .iter()
+            .map(|_| MARKER_SYMBOL_PPM),
+    );
+    if header.tile_part_lengths.is_some() {
+        markers.push(MARKER_SYMBOL_TLM);
+    }
+    markers.extend(header.packet_lengths.iter().map(|_| MARKER_SYMBOL_PLM));
+    if header.component_registration.is_some() {
+        markers.push(MARKER_SYMBOL_CRG);
+    }
+    markers.extend(
+        header
+            .comment_marker_segments
+            .iter()
+            .map(|_| MARKER_SYMBOL_COM),
+    );
+    markers
+}
+
+// Table A.3 - The marker segments of a tile header, all of which go in the
+// first tile-part header
+fn tile_part_markers(tile: &Tile) -> Vec<MarkerSymbol> {
+    let header = &tile.header;
+    let mut markers = vec![];
+    if header.coding_style_marker_segment.is_some() {
+        markers.push(MARKER_SYMBOL_COD);
+    }
+    markers.extend(
+        header
+            .coding_style_component_segments
+            .iter()
+            .map(|_| MARKER_SYMBOL_COC),
+    );
+    if header.quantization_default_marker_segment.is_some() {
+        markers.push(MARKER_SYMBOL_QCD);
+    }
+    markers.extend(
+        header
+            .quantization_component_segments
+            .iter()
+            .map(|_| MARKER_SYMBOL_QCC),
+    );
+    markers.extend(header.regions.iter().map(|_| MARKER_SYMBOL_RGN));
+    markers.extend(
+        header
+            .progression_order_changes
+            .iter()
+            .map(|_| MARKER_SYMBOL_POC),
+    );
+    markers.extend(
+        header
+            .packed_packet_headers
+            .iter()
+            .map(|_| MARKER_SYMBOL_PPT),
+    );
+    markers.extend(header.packet_lengths.iter().map(|_| MARKER_SYMBOL_PLT));
+    markers.extend(
+        header
+            .comment_marker_segments
+            .iter()
+            .map(|_| MARKER_SYMBOL_COM),
+    );
+    markers
+}
+
+// SPcod and SPcoc, with the precinct sizes only when they are defined
+fn coding_style_parameters(body: &mut Vec<u8>, parameters: &CodingStyleParameters) {
+    body.extend_from_slice(&parameters.no_decomposition_levels);
+    body.extend_from_slice(&parameters.code_block_width);
+    body.extend_from_slice(&parameters.code_block_height);
+    body.extend_from_slice(&parameters.code_block_style);
+    body.extend_from_slice(&parameters.transformation);
+    if parameters.has_defined_precinct_size() {
+        body.extend_from_slice(&parameters.precinct_size);
+    }
+}
+
+// SPqcd and SPqcc, one byte for each exponent without quantization and two
+// bytes for each exponent and mantissa otherwise
+fn quantization_values(body: &mut Vec<u8>, values: &[QuantizationValue]) {
+    for value in values {
+        match value {
+            QuantizationValue::Reversible { value } => body.extend_from_slice(value),
+            QuantizationValue::Irreversible { value } => body.extend_from_slice(value),
+        }
+    }
+}
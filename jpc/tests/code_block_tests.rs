@@ -0,0 +1,365 @@
+mod common;
+
+use jpc::code_block::{CodeBlockDecoder, CodeBlockEncoder};
+use jpc::SubbandOrientation;
+
+use common::Random;
+
+#[test]
+fn test_code_block_no_passes() {
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::LL, 0);
+    assert!(decoder.decode(&[], 0, 0, 8).is_ok());
+    assert_eq!(decoder.no_bit_planes_decoded(), 0);
+    assert_eq!(decoder.coefficients(), vec![0; 16]);
+}
+
+#[test]
+fn test_code_block_empty_segment() {
+    // An empty segment is decoded as if a marker immediately followed, which
+    // feeds 1-bits to the arithmetic decoder.
+    let mut decoder = CodeBlockDecoder::new(8, 8, SubbandOrientation::HH, 0);
+    assert!(decoder.decode(&[&[]], 3, 2, 8).is_ok());
+    assert_eq!(decoder.no_bit_planes_decoded(), 2);
+    assert_eq!(decoder.coefficients().len(), 64);
+}
+
+#[test]
+fn test_code_block_too_many_passes() {
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::HL, 0);
+    // Two bit-planes allow at most four coding passes.
+    assert!(decoder.decode(&[&[0x00, 0x00]], 4, 6, 8).is_ok());
+    assert!(decoder.decode(&[&[0x00, 0x00]], 5, 6, 8).is_err());
+}
+
+#[test]
+fn test_code_block_zero_bit_planes_overflow() {
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::LH, 0);
+    assert!(decoder.decode(&[&[0x00]], 1, 8, 8).is_err());
+}
+
+#[test]
+fn test_code_block_segmentation_symbol() {
+    // A segment of zeros decodes the segmentation symbol as all zeros, which
+    // is not the expected 1010.
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::LL, 0b0010_0000);
+    assert!(decoder.decode(&[&[0x00; 8]], 1, 0, 4).is_err());
+}
+
+#[test]
+fn test_code_block_resilient_segmentation_symbol() {
+    // The segmentation symbol of the first cleanup pass is 1010, but the one
+    // of the second bit-plane is not, which is discarded with every coding
+    // pass after it.
+    let segment: &[u8] = &[0x00, 0x00, 0xAA, 0x11];
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::LL, 0b0010_0000);
+    assert!(decoder.decode(&[segment], 1, 0, 4).is_ok());
+    let coefficients = decoder.coefficients();
+    assert_eq!(
+        coefficients,
+        vec![0, 8, 8, 0, 0, -8, -8, 8, 0, 8, 0, -8, -8, 8, -8, -8]
+    );
+    assert!(decoder.decode(&[segment], 4, 0, 4).is_err());
+
+    assert!(decoder.decode_resilient(&[segment], 7, 0, 4).is_ok());
+    assert_eq!(decoder.no_passes_decoded(), 1);
+    assert_eq!(decoder.no_bit_planes_decoded(), 1);
+    assert_eq!(decoder.coefficients(), coefficients);
+}
+
+#[test]
+fn test_code_block_resilient_predictable_termination() {
+    // A single cleanup pass, predictably terminated
+    let coefficients = vec![0, 1, 0, 0, 0, 0, 0, 0, 0, -1, 0, -1, -1, 0, 1, 0];
+    let data: &[u8] = &[0x00, 0x03, 0xFF, 0x32];
+
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::LL, 0b0001_0000);
+    assert!(decoder.decode_resilient(&[data], 1, 0, 1).is_ok());
+    assert_eq!(decoder.no_passes_decoded(), 1);
+    assert_eq!(decoder.coefficients(), coefficients);
+
+    // The coding pass ends before the end of the segment, or reads too far
+    // past its end
+    let longer = [data, &[0; 8]].concat();
+    let shorter = &data[..data.len() - 1];
+    for segment in [&longer[..], shorter].iter() {
+        assert!(decoder.decode(&[segment], 1, 0, 1).is_ok());
+        assert_eq!(decoder.no_passes_decoded(), 1);
+
+        assert!(decoder.decode_resilient(&[segment], 1, 0, 1).is_ok());
+        assert_eq!(decoder.no_passes_decoded(), 0);
+        assert_eq!(decoder.coefficients(), vec![0; 16]);
+    }
+}
+
+#[test]
+fn test_code_block_resilient_predictable_termination_truncated() {
+    // The code-block is a single codeword segment, which the layers split
+    // after any coding pass. Only the last coding pass of the code-block ends
+    // it as predicted.
+    let coefficients = vec![0, 5, -3, 0, 7, 0, 1, -6, 0, 0, 2, 0, -4, 0, 0, 3];
+    let data: &[u8] = &[0x0E, 0xC9, 0x75, 0xE8, 0xC5, 0x44, 0x4D];
+
+    let mut decoder = CodeBlockDecoder::new(4, 4, SubbandOrientation::HL, 0b0001_0000);
+    let mut resilient = CodeBlockDecoder::new(4, 4, SubbandOrientation::HL, 0b0001_0000);
+    for no_passes in 1..=7 {
+        decoder.decode(&[data], no_passes, 0, 3).unwrap();
+        resilient
+            .decode_resilient(&[data], no_passes, 0, 3)
+            .unwrap();
+        assert_eq!(resilient.no_passes_decoded(), no_passes);
+        assert_eq!(resilient.coefficients(), decoder.coefficients());
+    }
+    assert_eq!(resilient.coefficients(), coefficients);
+}
+
+#[test]
+fn test_code_block_termination_on_each_coding_pass() {
+    // A single coefficient, which becomes significant in the cleanup pass of
+    // the first segment. There is nothing to decode in the significance
+    // propagation pass, and the magnitude refinement pass restarts on the
+    // third segment.
+    let mut decoder = CodeBlockDecoder::new(1, 1, SubbandOrientation::LL, 0b0000_0100);
+    decoder.decode(&[&[0x00]], 1, 0, 2).unwrap();
+    assert_eq!(decoder.coefficients(), vec![2]);
+
+    for segment in [0x00, 0x55].iter() {
+        decoder
+            .decode(&[&[0x00], &[*segment], &[0x00]], 3, 0, 2)
+            .unwrap();
+        assert_eq!(decoder.coefficients(), vec![2]);
+        decoder
+            .decode(&[&[0x00], &[*segment], &[0xFF]], 3, 0, 2)
+            .unwrap();
+        assert_eq!(decoder.coefficients(), vec![3]);
+    }
+
+    // Without termination the same bytes continue a single codeword segment
+    let mut decoder = CodeBlockDecoder::new(1, 1, SubbandOrientation::LL, 0);
+    decoder.decode(&[&[0x00, 0x55, 0x00]], 3, 0, 2).unwrap();
+    assert_eq!(decoder.coefficients(), vec![3]);
+}
+
+#[test]
+fn test_code_block_selective_arithmetic_coding_bypass() {
+    // The first ten coding passes are arithmetically coded in the first
+    // segment, after which the magnitude refinement bits of the last two
+    // bit-planes are raw, with the cleanup pass in between restarting the
+    // arithmetic decoder.
+    let mut decoder = CodeBlockDecoder::new(1, 1, SubbandOrientation::LL, 0b0000_0001);
+    decoder.decode(&[&[0x00]], 10, 0, 6).unwrap();
+    assert_eq!(decoder.coefficients(), vec![60]);
+
+    let raw: [(u8, u8, i32); 4] = [
+        (0x00, 0x00, 60),
+        (0x80, 0x00, 62),
+        (0x00, 0x80, 61),
+        (0x80, 0x80, 63),
+    ];
+    for (first, second, coefficient) in raw.iter() {
+        decoder
+            .decode(&[&[0x00], &[*first], &[0x12], &[*second]], 15, 0, 6)
+            .unwrap();
+        assert_eq!(decoder.coefficients(), vec![*coefficient]);
+        assert_eq!(decoder.no_bit_planes_decoded(), 6);
+    }
+}
+
+#[test]
+fn test_code_block_vertically_causal_context() {
+    // The last coefficient of the first stripe is decoded without the first
+    // coefficient of the second stripe, which is significant, as a neighbour
+    let segment: &[u8] = &[0x17, 0x45, 0x8B, 0x12];
+    let mut decoder = CodeBlockDecoder::new(1, 8, SubbandOrientation::LL, 0);
+    decoder.decode(&[segment], 4, 0, 3).unwrap();
+    assert_eq!(decoder.coefficients(), vec![0, -2, 0, -2, -4, 0, 0, 0]);
+
+    let mut decoder = CodeBlockDecoder::new(1, 8, SubbandOrientation::LL, 0b0000_1000);
+    decoder.decode(&[segment], 4, 0, 3).unwrap();
+    assert_eq!(decoder.coefficients(), vec![-2, 0, 0, 0, -4, -2, 0, 0]);
+}
+
+#[test]
+fn test_code_block_reset_context_probabilities() {
+    let segment: &[u8] = &[0x00, 0x00, 0xAA, 0x11];
+    let mut decoder = CodeBlockDecoder::new(2, 8, SubbandOrientation::LL, 0);
+    decoder.decode(&[segment], 4, 0, 4).unwrap();
+    assert_eq!(
+        decoder.coefficients(),
+        vec![0, 12, 4, -12, 0, 12, -12, 12, -12, 0, 12, -12, 12, -8, -12, 0]
+    );
+
+    let mut decoder = CodeBlockDecoder::new(2, 8, SubbandOrientation::LL, 0b0000_0010);
+    decoder.decode(&[segment], 4, 0, 4).unwrap();
+    assert_eq!(
+        decoder.coefficients(),
+        vec![0, 8, 0, -12, 4, 12, -12, 8, -8, 0, 12, -12, 12, -12, -8, 0]
+    );
+}
+
+// Quantized coefficients of a code-block, mostly small with a few large ones
+// like those of a high-pass subband
+fn coefficients(n: usize, magnitude_bits: u8) -> Vec<i32> {
+    let mut random = Random::new(0x9E37_79B9);
+    (0..n)
+        .map(|_| {
+            let state = random.next_u32();
+            let magnitude = match state % 8 {
+                0..=3 => 0,
+                4..=6 => (state >> 8) % 16,
+                _ => (state >> 8) % (1 << magnitude_bits),
+            } as i32;
+            if state & 0x10_0000 != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_code_block_encoder_round_trip() {
+    // Every code-block style, with stripes cut short by the height
+    let coefficients = coefficients(17 * 13, 9);
+    for orientation in [
+        SubbandOrientation::LL,
+        SubbandOrientation::HL,
+        SubbandOrientation::LH,
+        SubbandOrientation::HH,
+    ]
+    .iter()
+    {
+        for code_block_style in 0..0b0100_0000 {
+            let mut encoder = CodeBlockEncoder::new(17, 13, *orientation, code_block_style);
+            let encoded = encoder.encode(&coefficients, 10).unwrap();
+            assert_eq!(encoded.zero_bit_planes(), 1);
+            assert_eq!(encoded.no_passes(), 25);
+
+            let mut decoder = CodeBlockDecoder::new(17, 13, *orientation, code_block_style);
+            decoder
+                .decode(&encoded.segments(25), 25, encoded.zero_bit_planes(), 10)
+                .unwrap();
+            assert_eq!(
+                decoder.coefficients(),
+                coefficients,
+                "{:?} {:06b}",
+                orientation,
+                code_block_style
+            );
+        }
+    }
+}
+
+#[test]
+fn test_code_block_encoder_truncation() {
+    // Decoding the code-block truncated after each coding pass decodes the
+    // same coefficients as decoding those coding passes of all of it
+    let coefficients = coefficients(32 * 32, 8);
+    for code_block_style in [0, 0b0000_0001, 0b0000_0100, 0b0011_1111].iter() {
+        let mut encoder = CodeBlockEncoder::new(32, 32, SubbandOrientation::HL, *code_block_style);
+        let encoded = encoder.encode(&coefficients, 8).unwrap();
+        let passes = encoded.passes();
+        let all = encoded.segments(encoded.no_passes());
+        assert_eq!(passes.last().unwrap().length(), encoded.data().len());
+        assert!(passes.last().unwrap().is_terminated());
+
+        let mut decoder = CodeBlockDecoder::new(32, 32, SubbandOrientation::HL, *code_block_style);
+        let mut truncated =
+            CodeBlockDecoder::new(32, 32, SubbandOrientation::HL, *code_block_style);
+        for no_passes in 1..=encoded.no_passes() {
+            let length = passes[no_passes as usize - 1].length();
+            assert!(no_passes == 1 || length >= passes[no_passes as usize - 2].length());
+            assert!(encoded.data()[length - 1] != 0xFF);
+
+            decoder
+                .decode(&all, no_passes, encoded.zero_bit_planes(), 8)
+                .unwrap();
+            let segments = encoded.segments(no_passes);
+            assert_eq!(
+                segments.iter().map(|segment| segment.len()).sum::<usize>(),
+                length
+            );
+            truncated
+                .decode(&segments, no_passes, encoded.zero_bit_planes(), 8)
+                .unwrap();
+            assert_eq!(
+                truncated.coefficients(),
+                decoder.coefficients(),
+                "{:06b} {} coding passes",
+                code_block_style,
+                no_passes
+            );
+        }
+        assert_eq!(truncated.coefficients(), coefficients);
+
+        // More coding passes than the code-block has are all of them
+        assert_eq!(encoded.segments(u8::MAX), all);
+    }
+}
+
+#[test]
+fn test_code_block_encoder_distortion() {
+    // Once every bit-plane is coded the distortion of the coefficients is
+    // reduced to zero, and no coding pass increases it
+    let coefficients = coefficients(16 * 16, 12);
+    let mut encoder = CodeBlockEncoder::new(16, 16, SubbandOrientation::HH, 0);
+    let encoded = encoder.encode(&coefficients, 12).unwrap();
+
+    let distortion: f64 = encoded.passes().iter().map(|pass| pass.distortion()).sum();
+    let energy: f64 = coefficients
+        .iter()
+        .map(|coefficient| (*coefficient as f64).powi(2))
+        .sum();
+    assert!((distortion - energy).abs() < 1e-6);
+    assert!(encoded.passes().iter().all(|pass| pass.distortion() >= 0.0));
+
+    // After each cleanup pass every coefficient is known down to the same
+    // bit-plane, and the squared error of the coefficients reconstructed at
+    // the midpoint of their interval is what the estimates leave of the energy
+    let mut decoder = CodeBlockDecoder::new(16, 16, SubbandOrientation::HH, 0);
+    for no_passes in (1..=encoded.no_passes()).step_by(3) {
+        decoder
+            .decode(
+                &encoded.segments(no_passes),
+                no_passes,
+                encoded.zero_bit_planes(),
+                12,
+            )
+            .unwrap();
+        let bit_plane = 12 - decoder.no_bit_planes_decoded();
+        let error: f64 = decoder
+            .coefficients()
+            .iter()
+            .zip(coefficients.iter())
+            .map(|(decoded, coefficient)| {
+                let magnitude = match decoded.unsigned_abs() {
+                    0 => 0.0,
+                    magnitude if bit_plane == 0 => magnitude as f64,
+                    magnitude => magnitude as f64 + (1u32 << (bit_plane - 1)) as f64,
+                };
+                (coefficient.unsigned_abs() as f64 - magnitude).powi(2)
+            })
+            .sum();
+        let distortion: f64 = encoded.passes()[..no_passes as usize]
+            .iter()
+            .map(|pass| pass.distortion())
+            .sum();
+        assert!((energy - distortion - error).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_code_block_encoder_zero() {
+    let mut encoder = CodeBlockEncoder::new(4, 4, SubbandOrientation::LL, 0);
+    let encoded = encoder.encode(&[0; 16], 8).unwrap();
+    assert_eq!(encoded.no_passes(), 0);
+    assert!(encoded.data().is_empty());
+}
+
+#[test]
+fn test_code_block_encoder_errors() {
+    let mut encoder = CodeBlockEncoder::new(4, 4, SubbandOrientation::LL, 0);
+    assert!(encoder.encode(&[0; 15], 8).is_err());
+    assert!(encoder.encode(&[256; 16], 8).is_err());
+    assert!(encoder.encode(&[-255; 16], 8).is_ok());
+}
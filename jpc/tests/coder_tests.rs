@@ -0,0 +1,243 @@
+mod common;
+
+use jpc::coder::{
+    MqDecoder, MqEncoder, RawDecoder, Termination, CONTEXT_INDEX_SIGNIFICANCE, NO_CONTEXTS,
+};
+
+use common::Random;
+
+// Test sequence for the arithmetic coder, from ITU-T T.88 Annex H.2, which
+// uses the same MQ coder as this specification.
+const DECODED: [u8; 32] = [
+    0x00, 0x02, 0x00, 0x51, 0x00, 0x00, 0x00, 0xC0, 0x03, 0x52, 0x87, 0x2A, 0xAA, 0xAA, 0xAA, 0xAA,
+    0x82, 0xC0, 0x20, 0x00, 0xFC, 0xD7, 0x9E, 0xF6, 0xBF, 0x7F, 0xED, 0x90, 0x4F, 0x46, 0xA3, 0xBF,
+];
+
+const ENCODED: [u8; 30] = [
+    0x84, 0xC7, 0x3B, 0xFC, 0xE1, 0xA1, 0x43, 0x04, 0x02, 0x20, 0x00, 0x00, 0x41, 0x0D, 0xBB, 0x86,
+    0xF4, 0x31, 0x7F, 0xFF, 0x88, 0xFF, 0x37, 0x47, 0x1A, 0xDB, 0x6A, 0xDF, 0xFF, 0xAC,
+];
+
+#[test]
+fn test_mq_decoder() {
+    let mut decoder = MqDecoder::new(&ENCODED);
+
+    // The test sequence uses a single context starting in state 0 with an
+    // MPS of 0, which is the initial state of every context after the first
+    // significance context.
+    let cx = CONTEXT_INDEX_SIGNIFICANCE + 1;
+
+    let mut decoded = [0u8; 32];
+    for byte in decoded.iter_mut() {
+        for _ in 0..8 {
+            *byte = (*byte << 1) | decoder.decode(cx);
+        }
+    }
+
+    assert_eq!(decoded, DECODED);
+}
+
+#[test]
+fn test_mq_decoder_reset_contexts() {
+    let cx = CONTEXT_INDEX_SIGNIFICANCE + 1;
+
+    let mut decoder = MqDecoder::new(&ENCODED);
+    let first: Vec<u8> = (0..64).map(|_| decoder.decode(cx)).collect();
+
+    // Restarting on the same segment after resetting the contexts decodes the
+    // same decisions again.
+    decoder.reset_contexts();
+    decoder.restart(&ENCODED);
+    let second: Vec<u8> = (0..64).map(|_| decoder.decode(cx)).collect();
+
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_mq_decoder_marker_termination() {
+    // Once a marker is reached 1-bits are fed to the decoder, which must keep
+    // producing decisions without reading past the marker.
+    let data = [0x84, 0xC7, 0xFF, 0xD9];
+    let mut decoder = MqDecoder::new(&data);
+    for _ in 0..256 {
+        decoder.decode(CONTEXT_INDEX_SIGNIFICANCE + 1);
+    }
+    assert_eq!(decoder.position(), 2);
+}
+
+// Decisions in pseudo-random contexts, skewed to the MPS of 0
+fn decisions(n: usize) -> Vec<(u8, usize)> {
+    let mut random = Random::new(0x1234_5678);
+    (0..n)
+        .map(|_| {
+            let state = random.next_u32();
+            let d = (state % 5 < 1) as u8;
+            let cx = (state >> 8) as usize % NO_CONTEXTS;
+            (d, cx)
+        })
+        .collect()
+}
+
+// The first 512 decisions, predictably terminated
+const PREDICTABLE: [u8; 47] = [
+    0xA8, 0x76, 0x7D, 0xDF, 0x33, 0x5E, 0x29, 0x17, 0x90, 0x8B, 0x73, 0xA0, 0xD5, 0x8B, 0xAC, 0x45,
+    0x4F, 0x6A, 0xF1, 0x8E, 0x76, 0xBE, 0xD8, 0x72, 0x00, 0xDC, 0x06, 0x57, 0x8E, 0x80, 0xE7, 0xC9,
+    0x77, 0xBB, 0x29, 0xD8, 0xA5, 0x25, 0x36, 0x6A, 0xEF, 0xE9, 0xBB, 0x2E, 0xD4, 0xDD, 0x7E,
+];
+
+#[test]
+fn test_mq_decoder_predictable_termination() {
+    let decisions = decisions(512);
+    let mut decoder = MqDecoder::new(&PREDICTABLE);
+    for (i, &(d, cx)) in decisions.iter().enumerate() {
+        assert_eq!(decoder.decode(cx), d, "decision {}", i);
+    }
+    assert!(decoder.is_predictably_terminated());
+
+    // Bytes past the predictable termination, or missing from it
+    for corrupt in [
+        [&PREDICTABLE[..], &[0x00]].concat(),
+        PREDICTABLE[..PREDICTABLE.len() - 1].to_vec(),
+    ]
+    .iter()
+    {
+        let mut decoder = MqDecoder::new(corrupt);
+        for &(_, cx) in decisions.iter() {
+            decoder.decode(cx);
+        }
+        assert!(!decoder.is_predictably_terminated());
+    }
+}
+
+#[test]
+fn test_mq_encoder() {
+    let mut encoder = MqEncoder::new();
+    let cx = CONTEXT_INDEX_SIGNIFICANCE + 1;
+    for byte in DECODED.iter() {
+        for i in (0..8).rev() {
+            encoder.encode((byte >> i) & 1, cx);
+        }
+    }
+
+    // The test sequence ends with the 0xFFAC marker of T.88, which is not
+    // part of the codeword segment, and a last 0xFF byte is left out.
+    assert_eq!(encoder.flush(Termination::Easy), &ENCODED[..28]);
+}
+
+#[test]
+fn test_mq_encoder_round_trip() {
+    let decisions = decisions(4096);
+
+    for termination in [Termination::Easy, Termination::Predictable].iter() {
+        let mut encoder = MqEncoder::new();
+        for &(d, cx) in decisions.iter() {
+            encoder.encode(d, cx);
+        }
+        let data = encoder.flush(*termination);
+        assert_eq!(encoder.no_bytes(), 0);
+
+        let mut decoder = MqDecoder::new(&data);
+        for (i, &(d, cx)) in decisions.iter().enumerate() {
+            assert_eq!(decoder.decode(cx), d, "{:?} decision {}", termination, i);
+        }
+    }
+}
+
+#[test]
+fn test_mq_encoder_segments() {
+    let decisions = decisions(3000);
+
+    // The contexts are kept from one codeword segment to the next, like the
+    // decoder restarting on each segment
+    let mut encoder = MqEncoder::new();
+    let segments: Vec<Vec<u8>> = decisions
+        .chunks(1000)
+        .map(|chunk| {
+            for &(d, cx) in chunk.iter() {
+                encoder.encode(d, cx);
+            }
+            encoder.flush(Termination::Predictable)
+        })
+        .collect();
+
+    let mut decoder = MqDecoder::new(&segments[0]);
+    for (segment, chunk) in segments.iter().zip(decisions.chunks(1000)) {
+        decoder.restart(segment);
+        for &(d, cx) in chunk.iter() {
+            assert_eq!(decoder.decode(cx), d);
+        }
+        assert!(decoder.is_predictably_terminated());
+    }
+}
+
+#[test]
+fn test_mq_encoder_predictable_termination() {
+    // Every prefix of the decisions is predictably terminated, however many
+    // bits are left in the C-register
+    let decisions = decisions(200);
+    for n in 0..decisions.len() {
+        let mut encoder = MqEncoder::new();
+        for &(d, cx) in decisions[..n].iter() {
+            encoder.encode(d, cx);
+        }
+        let data = encoder.flush(Termination::Predictable);
+        assert!(data.last() != Some(&0xFF));
+
+        let mut decoder = MqDecoder::new(&data);
+        for &(d, cx) in decisions[..n].iter() {
+            assert_eq!(decoder.decode(cx), d);
+        }
+        assert!(decoder.is_predictably_terminated(), "{} decisions", n);
+    }
+}
+
+#[test]
+fn test_mq_decoder_predictable_termination_errors() {
+    // A segment with a flipped byte is decoded into other decisions, which
+    // nearly always end elsewhere than the predictable termination of the
+    // segment
+    let decisions = decisions(4096);
+    let mut encoder = MqEncoder::new();
+    for &(d, cx) in decisions.iter() {
+        encoder.encode(d, cx);
+    }
+    let data = encoder.flush(Termination::Predictable);
+
+    let mut undetected = 0;
+    for i in 0..data.len() {
+        let mut corrupt = data.clone();
+        corrupt[i] ^= 0xFF;
+        let mut decoder = MqDecoder::new(&corrupt);
+        for &(_, cx) in decisions.iter() {
+            decoder.decode(cx);
+        }
+        if decoder.is_predictably_terminated() {
+            undetected += 1;
+        }
+    }
+    assert!(undetected * 20 < data.len(), "{} undetected", undetected);
+}
+
+#[test]
+fn test_raw_decoder() {
+    // 0x2A follows 0xFF, so its MSB is a stuffed bit, and 0xFF90 is a marker
+    let mut decoder = RawDecoder::new(&[0xA5, 0xFF, 0x2A, 0xFF, 0x90]);
+    let bits: Vec<u8> = (0..31).map(|_| decoder.decode()).collect();
+    assert_eq!(
+        bits,
+        vec![
+            1, 0, 1, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 1, 1, 1, 1, 1,
+            1, 1
+        ]
+    );
+
+    // 1-bits are fed at the marker, which is not consumed
+    assert!((0..16).all(|_| decoder.decode() == 1));
+    assert_eq!(decoder.position(), 4);
+
+    // and past the end of the codeword segment
+    let mut decoder = RawDecoder::new(&[0x00]);
+    assert!((0..8).all(|_| decoder.decode() == 0));
+    assert!((0..16).all(|_| decoder.decode() == 1));
+    assert_eq!(decoder.position(), 1);
+}
@@ -0,0 +1,70 @@
+// Helpers shared by the integration tests, each of which only uses some of
+// them
+#![allow(dead_code)]
+
+use std::{fs::File, io::Read, path::Path};
+
+fn read(path: &Path) -> Vec<u8> {
+    let mut bytes = vec![];
+    File::open(path)
+        .expect("file should exist")
+        .read_to_end(&mut bytes)
+        .expect("file should be read");
+    bytes
+}
+
+// A codestream of the tests directory
+pub fn read_codestream(filename: &str) -> Vec<u8> {
+    read(
+        &Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join(filename),
+    )
+}
+
+// The codestream of a JP2 sample is the contents of its contiguous codestream
+// box, which some samples follow with other boxes
+pub fn read_sample(filename: &str) -> Vec<u8> {
+    let mut bytes = read(
+        &Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("samples")
+            .join(filename),
+    );
+    let start = bytes
+        .windows(4)
+        .position(|window| window == b"jp2c")
+        .expect("codestream box should exist")
+        + 4;
+    let length = u32::from_be_bytes([
+        bytes[start - 8],
+        bytes[start - 7],
+        bytes[start - 6],
+        bytes[start - 5],
+    ]) as usize;
+    let mut codestream = bytes.split_off(start);
+    if length != 0 {
+        codestream.truncate(length - 8);
+    }
+    codestream
+}
+
+// Xorshift generator of pseudo-random test data, which repeats the same
+// sequence for the same seed
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Random {
+        assert_ne!(seed, 0, "xorshift needs a seed other than 0");
+        Random { state: seed }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+}
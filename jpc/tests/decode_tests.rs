@@ -0,0 +1,1220 @@
+mod common;
+
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jpc::geometry::Rectangle;
+use jpc::registration::upsample;
+use jpc::{decode_image, DecodeOptions, Image, ImageArea, SubbandOrientation};
+
+use common::{read_codestream, read_sample};
+
+// Byte offsets of the COD marker and the SOT marker in blue.j2k
+const BLUE_COD: usize = 0x33;
+const BLUE_SOT: usize = 0x7D;
+
+fn decode_codestream(filename: &str) -> Image {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(filename);
+    let file = File::open(path).expect("file should exist");
+    let mut reader = BufReader::new(file);
+    decode_image(&mut reader, &DecodeOptions::default()).expect("image should decode")
+}
+
+fn decode_sample(filename: &str) -> Image {
+    let mut reader = Cursor::new(read_sample(filename));
+    decode_image(&mut reader, &DecodeOptions::default()).expect("image should decode")
+}
+
+// The main header of blue.j2k, for an image of the given width with tiles of
+// 128 by 64.
+fn blue_main_header(blue: &[u8], width: u32) -> Vec<u8> {
+    let mut header = blue[..BLUE_SOT].to_vec();
+    header[8..12].copy_from_slice(&width.to_be_bytes());
+    header
+}
+
+// The packets of the single tile of blue.j2k, between SOD and EOC
+fn blue_tile_data(blue: &[u8]) -> &[u8] {
+    &blue[BLUE_SOT + 14..blue.len() - 2]
+}
+
+fn blue_cod(blue: &[u8]) -> &[u8] {
+    &blue[BLUE_COD..BLUE_COD + 14]
+}
+
+// A tile-part with an optional header of marker segments, Psot is zero when
+// the length is not given.
+fn tile_part(
+    tile_index: u16,
+    tile_part_index: u8,
+    no_tile_parts: u8,
+    header: &[u8],
+    data: &[u8],
+    psot: bool,
+) -> Vec<u8> {
+    let length = if psot {
+        12 + header.len() as u32 + 2 + data.len() as u32
+    } else {
+        0
+    };
+
+    let mut part = vec![0xFF, 0x90, 0x00, 0x0A];
+    part.extend_from_slice(&tile_index.to_be_bytes());
+    part.extend_from_slice(&length.to_be_bytes());
+    part.push(tile_part_index);
+    part.push(no_tile_parts);
+    part.extend_from_slice(header);
+    part.extend_from_slice(&[0xFF, 0x93]);
+    part.extend_from_slice(data);
+    part
+}
+
+fn assert_halves_are_blue(image: &Image) {
+    let blue = decode_codestream("blue.j2k");
+    assert_eq!(image.width(), 256);
+    assert_eq!(image.height(), 64);
+
+    for (component, expected) in image.components().iter().zip(blue.components()) {
+        assert_eq!(component.width(), 256);
+        assert_eq!(component.height(), 64);
+        for y in 0..64 {
+            for x in 0..128 {
+                assert_eq!(component.sample(x, y), expected.sample(x, y));
+                assert_eq!(component.sample(x + 128, y), expected.sample(x, y));
+            }
+        }
+    }
+}
+
+fn mean(samples: &[i32]) -> f64 {
+    samples.iter().map(|&sample| sample as f64).sum::<f64>() / samples.len() as f64
+}
+
+// 64-bit FNV-1a hash of the samples of a component, as little-endian bytes
+fn checksum(samples: &[i32]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for sample in samples {
+        for &byte in sample.to_le_bytes().iter() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+#[test]
+fn test_decode_blue() {
+    let image = decode_codestream("blue.j2k");
+    assert_eq!(image.width(), 128);
+    assert_eq!(image.height(), 64);
+    assert_eq!(image.area().x0(), 0);
+    assert_eq!(image.area().y0(), 0);
+
+    let components = image.components();
+    assert_eq!(components.len(), 3);
+    for component in components {
+        assert_eq!(component.x0(), 0);
+        assert_eq!(component.y0(), 0);
+        assert_eq!(component.width(), 128);
+        assert_eq!(component.height(), 64);
+        assert_eq!(component.precision(), 8);
+        assert!(!component.values_are_signed());
+        assert_eq!(component.horizontal_separation(), 1);
+        assert_eq!(component.vertical_separation(), 1);
+        assert_eq!(component.samples().len(), 128 * 64);
+        assert!(component.samples().iter().all(|&s| (0..=255).contains(&s)));
+    }
+
+    // The image is mostly blue
+    let red = mean(components[0].samples());
+    let green = mean(components[1].samples());
+    let blue = mean(components[2].samples());
+    assert!(blue > green && green > red, "{} {} {}", red, green, blue);
+
+    // Samples at the corners and the centre, and the samples of each component
+    let expected = [
+        ([2, 18, 3], 0x55b7_ee3c_2427_e533),
+        ([0, 184, 34], 0xef0f_58e9_a1dd_3798),
+        ([146, 237, 169], 0x51a4_cb81_b061_ad80),
+    ];
+    for (component, (samples, hash)) in components.iter().zip(expected.iter()) {
+        assert_eq!(
+            [
+                component.sample(0, 0),
+                component.sample(64, 32),
+                component.sample(127, 63)
+            ],
+            *samples
+        );
+        assert_eq!(checksum(component.samples()), *hash);
+    }
+}
+
+#[test]
+fn test_decode_reversible_sample() {
+    let image = decode_sample("file8.jp2");
+    assert_eq!(image.width(), 700);
+    assert_eq!(image.height(), 400);
+
+    let components = image.components();
+    assert_eq!(components.len(), 1);
+    let component = &components[0];
+    assert_eq!(component.width(), 700);
+    assert_eq!(component.height(), 400);
+    assert_eq!(component.precision(), 8);
+    assert_eq!(component.samples().len(), 700 * 400);
+    assert!(component.samples().iter().all(|&s| (0..=255).contains(&s)));
+
+    // The image is coded losslessly, so every sample is exact
+    assert_eq!(component.sample(0, 0), 9);
+    assert_eq!(component.sample(350, 200), 49);
+    assert_eq!(component.sample(600, 40), 236);
+    assert_eq!(component.sample(699, 399), 109);
+    assert_eq!(checksum(component.samples()), 0x97cb_9153_c046_2be4);
+}
+
+#[test]
+fn test_decode_irreversible_sample() {
+    let image = decode_sample("file1.jp2");
+    assert_eq!(image.width(), 768);
+    assert_eq!(image.height(), 512);
+
+    let components = image.components();
+    assert_eq!(components.len(), 3);
+    for component in components {
+        assert_eq!(component.width(), 768);
+        assert_eq!(component.height(), 512);
+        assert_eq!(component.precision(), 8);
+        assert!(component.samples().iter().all(|&s| (0..=255).contains(&s)));
+    }
+
+    // The sky along the top right of the image is blue
+    let (red, blue) = (&components[0], &components[2]);
+    assert!(blue.sample(600, 40) > red.sample(600, 40) + 64);
+
+    // Samples at the corners, the centre and the sky, and the samples of each
+    // component
+    let expected = [
+        ([0, 76, 255, 0], 0x99fe_3fde_b4b6_0122),
+        ([0, 171, 255, 120], 0xab21_fea0_9da7_0efd),
+        ([0, 236, 255, 217], 0x9ea4_e4db_0ff9_e7e8),
+    ];
+    for (component, (samples, hash)) in components.iter().zip(expected.iter()) {
+        assert_eq!(
+            [
+                component.sample(0, 0),
+                component.sample(384, 256),
+                component.sample(767, 511),
+                component.sample(600, 40),
+            ],
+            *samples
+        );
+        assert_eq!(checksum(component.samples()), *hash);
+    }
+}
+
+#[test]
+fn test_decode_tiles() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // Two tiles side by side, each with the tile of blue.j2k
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream.extend(tile_part(0, 0, 1, &[], data, true));
+    codestream.extend(tile_part(1, 0, 1, &[], data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut reader = Cursor::new(codestream);
+    let image = decode_image(&mut reader, &DecodeOptions::default()).unwrap();
+    assert_halves_are_blue(&image);
+}
+
+#[test]
+fn test_decode_tile_parts_with_tile_header_overrides() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+    let cod = blue_cod(&blue);
+
+    // The main header has three decomposition levels, which the first
+    // tile-part header of each tile overrides with the five levels of blue.j2k
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream[BLUE_COD + 9] = 3;
+
+    // Tile 0 is split into two tile-parts, interleaved with tile 1, the last
+    // tile-part has a comment and runs up to the EOC marker
+    let comment = [0xFF, 0x64, 0x00, 0x06, 0x00, 0x01, b'h', b'i'];
+    codestream.extend(tile_part(0, 0, 2, cod, &[], true));
+    codestream.extend(tile_part(1, 0, 1, cod, data, true));
+    codestream.extend(tile_part(0, 1, 2, &comment, data, false));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut reader = Cursor::new(codestream);
+    let image = decode_image(&mut reader, &DecodeOptions::default()).unwrap();
+    assert_halves_are_blue(&image);
+}
+
+// RGN marker segment with the Maxshift style for a component
+fn rgn(component: u8, shift: u8) -> [u8; 7] {
+    [0xFF, 0x5E, 0x00, 0x05, component, 0x00, shift]
+}
+
+#[test]
+fn test_decode_region_of_interest() {
+    let blue = read_codestream("blue.j2k");
+
+    // Scaling every coefficient up by the ROI shift puts all of them in the
+    // region of interest, which descales to the coefficients of blue.j2k. The
+    // RGN marker segment of component 2 in the tile-part header overrides a
+    // shift in the main header which is too large to decode.
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend_from_slice(&rgn(0, 3));
+    codestream.extend_from_slice(&rgn(2, 40));
+    let header = [rgn(1, 6), rgn(2, 1)].concat();
+    codestream.extend(tile_part(0, 0, 1, &header, blue_tile_data(&blue), true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    assert_is_blue(codestream);
+
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend_from_slice(&rgn(2, 40));
+    codestream.extend(tile_part(0, 0, 1, &[], blue_tile_data(&blue), true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+}
+
+#[test]
+fn test_decode_tile_parts_out_of_order() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream.extend(tile_part(0, 1, 2, &[], data, true));
+    codestream.extend(tile_part(0, 0, 2, &[], &[], true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+
+    // Only the first tile-part header of a tile may have a COD marker segment
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(tile_part(0, 0, 2, &[], &[], true));
+    codestream.extend(tile_part(0, 1, 2, blue_cod(&blue), data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+}
+
+#[test]
+fn test_decode_number_of_tile_parts() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // A TNsot of 0 leaves the number of tile-parts to the other tile-parts
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(tile_part(0, 0, 0, &[], &[], true));
+    codestream.extend(tile_part(0, 1, 2, &[], data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    assert_is_blue(codestream);
+
+    // A truncated codestream may be missing tile-parts
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(tile_part(0, 0, 3, &[], &[], true));
+    codestream.extend(tile_part(0, 1, 3, &[], data, true));
+    assert_is_blue(codestream);
+
+    // A tile-part index beyond the number of tile-parts, a number of
+    // tile-parts which differs between tile-parts of a tile, and a complete
+    // codestream which is missing tile-parts
+    for parts in [[(0, 1), (1, 1)], [(0, 2), (1, 3)], [(0, 3), (1, 3)]].iter() {
+        let mut codestream = blue_main_header(&blue, 128);
+        codestream.extend(tile_part(0, parts[0].0, parts[0].1, &[], &[], true));
+        codestream.extend(tile_part(0, parts[1].0, parts[1].1, &[], data, true));
+        codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+        let mut reader = Cursor::new(codestream);
+        assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+    }
+}
+
+// Byte offsets of the packets in the tile data of blue.j2k, which has a single
+// layer and precinct in layer-resolution level-component-position order, so
+// packet i is of resolution level i / 3 and component i % 3
+const BLUE_PACKETS: [usize; 18] = [
+    0, 12, 23, 33, 64, 93, 125, 224, 315, 418, 785, 1131, 1510, 2799, 4030, 5408, 9363, 12986,
+];
+
+fn blue_packet(data: &[u8], resolution: usize, component: usize) -> &[u8] {
+    let i = resolution * 3 + component;
+    let end = BLUE_PACKETS.get(i + 1).copied().unwrap_or(data.len());
+    &data[BLUE_PACKETS[i]..end]
+}
+
+// blue.j2k with a progression order in COD, optional POC marker segments in
+// the main and tile-part header, and the packets in the order of the
+// resolution levels and components given
+fn blue_progression(
+    progression_order: u8,
+    main_poc: &[u8],
+    tile_poc: &[u8],
+    packets: &[(usize, usize)],
+) -> Vec<u8> {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream[BLUE_COD + 5] = progression_order;
+    codestream.extend_from_slice(main_poc);
+
+    let mut tile_data = vec![];
+    for &(resolution, component) in packets {
+        tile_data.extend_from_slice(blue_packet(data, resolution, component));
+    }
+    codestream.extend(tile_part(0, 0, 1, tile_poc, &tile_data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    codestream
+}
+
+// POC marker segment with progressions of (RSpoc, CSpoc, LYEpoc, REpoc,
+// CEpoc, Ppoc)
+fn poc(progressions: &[(u8, u8, u16, u8, u8, u8)]) -> Vec<u8> {
+    let mut segment = vec![0xFF, 0x5F];
+    segment.extend_from_slice(&(2 + 7 * progressions.len() as u16).to_be_bytes());
+    for &(rs, cs, lye, re, ce, p) in progressions {
+        segment.extend_from_slice(&[rs, cs]);
+        segment.extend_from_slice(&lye.to_be_bytes());
+        segment.extend_from_slice(&[re, ce, p]);
+    }
+    segment
+}
+
+fn assert_is_blue(codestream: Vec<u8>) {
+    let expected = decode_codestream("blue.j2k");
+    let mut reader = Cursor::new(codestream);
+    let image = decode_image(&mut reader, &DecodeOptions::default()).unwrap();
+    for (component, expected) in image.components().iter().zip(expected.components()) {
+        assert_eq!(component.samples(), expected.samples());
+    }
+}
+
+#[test]
+fn test_decode_progression_orders() {
+    let resolution_major: Vec<(usize, usize)> =
+        (0..6).flat_map(|r| (0..3).map(move |c| (r, c))).collect();
+    let component_major: Vec<(usize, usize)> =
+        (0..3).flat_map(|c| (0..6).map(move |r| (r, c))).collect();
+
+    // LRCP, RLCP and RPCL
+    for progression_order in 0..3 {
+        assert_is_blue(blue_progression(
+            progression_order,
+            &[],
+            &[],
+            &resolution_major,
+        ));
+    }
+
+    // PCRL and CPRL
+    for progression_order in 3..5 {
+        assert_is_blue(blue_progression(
+            progression_order,
+            &[],
+            &[],
+            &component_major,
+        ));
+    }
+}
+
+#[test]
+fn test_decode_progression_order_changes() {
+    // Component 0 in CPRL, then resolution levels 0 to 2 of components 1 and 2
+    // in RLCP and resolution levels 3 to 5 in RPCL, the last progression only
+    // has packets which have already been included
+    let progressions = poc(&[
+        (0, 0, 1, 6, 1, 4),
+        (0, 1, 1, 3, 3, 1),
+        (3, 1, 1, 6, 3, 2),
+        (0, 0, 1, 6, 3, 0),
+    ]);
+    let mut packets: Vec<(usize, usize)> = (0..6).map(|r| (r, 0)).collect();
+    for r in 0..6 {
+        packets.push((r, 1));
+        packets.push((r, 2));
+    }
+
+    assert_is_blue(blue_progression(0, &progressions, &[], &packets));
+
+    // The POC marker segment of the tile-part header overrides the main header
+    let main_progressions = poc(&[(0, 0, 1, 6, 3, 0)]);
+    assert_is_blue(blue_progression(
+        0,
+        &main_progressions,
+        &progressions,
+        &packets,
+    ));
+}
+
+// Packets of the single code-block of 2 by 1 samples of a precinct of an
+// unsigned 8-bit component without decomposition levels or quantization, for
+// the samples 3, 24, .., 234 of component 0 and 250, 219, .., 33 of component 1
+const SUBSAMPLED_PACKETS: [&[u8]; 10] = [
+    &[0xCF, 0xB4, 0x0C, 0x08, 0x4D, 0x1F],
+    &[0xCF, 0xB4, 0x0C, 0x06, 0x23, 0x33],
+    &[0xC7, 0xD4, 0x04, 0x06, 0x0F],
+    &[0xC3, 0xE7, 0x06, 0x0B, 0x68, 0x3F],
+    &[0xCF, 0xB4, 0x0C, 0x0A, 0xE6, 0x2F],
+    &[0xCF, 0xB4, 0x0C, 0x02, 0x04, 0xF3],
+    &[0xCF, 0xB4, 0x0C, 0x01, 0x61, 0xAF],
+    &[0xC7, 0xD4, 0x04, 0x03, 0x13],
+    &[0xC7, 0xD4, 0x06, 0x0C, 0x55, 0x27],
+    &[0xCF, 0xB4, 0x0C, 0x09, 0x4B, 0x7F],
+];
+
+#[test]
+fn test_decode_subsampled_progression_orders() {
+    // An image of 24 by 1 with components of XRsiz 2 and 3 and precincts 2
+    // samples wide, so the precincts of component 0 start at 0, 4, .., 20 and
+    // those of component 1 at 0, 6, 12 and 18 on the reference grid
+    let mut header = vec![0xFF, 0x4F, 0xFF, 0x51, 0x00, 0x2C, 0x00, 0x00];
+    for value in [24u32, 1, 0, 0, 24, 1, 0, 0].iter() {
+        header.extend_from_slice(&value.to_be_bytes());
+    }
+    header.extend_from_slice(&[0x00, 0x02, 0x07, 0x02, 0x01, 0x07, 0x03, 0x01]);
+    let cod = [
+        0xFF, 0x52, 0x00, 0x0D, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01,
+    ];
+    let qcd = [0xFF, 0x5C, 0x00, 0x04, 0x40, 0x40];
+
+    let samples: [Vec<i32>; 2] = [
+        (0..12).map(|x| x * 21 + 3).collect(),
+        (0..8).map(|x| 250 - x * 31).collect(),
+    ];
+    let packets = [&SUBSAMPLED_PACKETS[..6], &SUBSAMPLED_PACKETS[6..]];
+
+    // (component, precinct) of each packet in position order, and in
+    // component-position order
+    let positions = [
+        (0, 0),
+        (1, 0),
+        (0, 1),
+        (1, 1),
+        (0, 2),
+        (0, 3),
+        (1, 2),
+        (0, 4),
+        (1, 3),
+        (0, 5),
+    ];
+    let mut components: Vec<(usize, usize)> = (0..6).map(|p| (0, p)).collect();
+    components.extend((0..4).map(|p| (1, p)));
+
+    // RPCL, PCRL and CPRL
+    for (progression_order, order) in
+        [(2, &positions[..]), (3, &positions), (4, &components)].iter()
+    {
+        let mut tile_data = vec![];
+        for &(component, precinct) in order.iter() {
+            tile_data.extend_from_slice(packets[component][precinct]);
+        }
+
+        let mut codestream = header.clone();
+        codestream.extend_from_slice(&cod);
+        codestream[header.len() + 5] = *progression_order;
+        codestream.extend_from_slice(&qcd);
+        codestream.extend(tile_part(0, 0, 1, &[], &tile_data, true));
+        codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+        let mut reader = Cursor::new(codestream);
+        let image = decode_image(&mut reader, &DecodeOptions::default()).unwrap();
+        let components = image.components();
+        assert_eq!(components[0].width(), 12);
+        assert_eq!(components[1].width(), 8);
+        assert_eq!(components[0].samples(), &samples[0][..]);
+        assert_eq!(components[1].samples(), &samples[1][..]);
+    }
+}
+
+#[test]
+fn test_decode_sop_and_eph() {
+    // The same white image in CPRL with SOP marker segments and in RLCP with
+    // EPH markers, where only the first component has coded data
+    let sop = decode_codestream("sop.j2k");
+    let eph = decode_codestream("eph.j2k");
+    for image in [&sop, &eph].iter() {
+        assert_eq!(image.width(), 2);
+        assert_eq!(image.height(), 1);
+        assert_eq!(image.components().len(), 3);
+        for component in image.components() {
+            assert_eq!(component.precision(), 16);
+            assert_eq!(component.samples(), &[65535, 65535]);
+        }
+    }
+}
+
+// SOP marker segment with a packet sequence number
+fn sop(sequence: u16) -> Vec<u8> {
+    let mut segment = vec![0xFF, 0x91, 0x00, 0x04];
+    segment.extend_from_slice(&sequence.to_be_bytes());
+    segment
+}
+
+// blue.j2k with SOP marker segments allowed in COD, with the packets given
+fn blue_with_sop(blue: &[u8], packets: &[Vec<u8>]) -> Image {
+    let mut codestream = blue_main_header(blue, 128);
+    codestream[BLUE_COD + 4] |= 0x02;
+    codestream.extend(tile_part(0, 0, 1, &[], &packets.concat(), true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let mut reader = Cursor::new(codestream);
+    decode_image(&mut reader, &DecodeOptions::default()).expect("image should decode")
+}
+
+#[test]
+fn test_decode_sop_resynchronisation() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+    let packets: Vec<Vec<u8>> = (0..18)
+        .map(|i| [sop(i as u16), blue_packet(data, i / 3, i % 3).to_vec()].concat())
+        .collect();
+
+    let image = blue_with_sop(&blue, &packets);
+    let expected = decode_codestream("blue.j2k");
+    for (component, expected) in image.components().iter().zip(expected.components()) {
+        assert_eq!(component.samples(), expected.samples());
+    }
+
+    // SOP marker segments may be left out of any packet
+    let some: Vec<Vec<u8>> = packets
+        .iter()
+        .enumerate()
+        .map(|(i, packet)| {
+            if i % 3 == 1 {
+                packet[6..].to_vec()
+            } else {
+                packet.clone()
+            }
+        })
+        .collect();
+    let image = blue_with_sop(&blue, &some);
+    for (component, expected) in image.components().iter().zip(expected.components()) {
+        assert_eq!(component.samples(), expected.samples());
+    }
+
+    // A corrupt packet is lost, as if it were an empty packet, and decoding
+    // continues at the SOP marker segment of the next packet
+    let mut empty = packets.clone();
+    empty[10] = [sop(10), vec![0x00]].concat();
+    let expected = blue_with_sop(&blue, &empty);
+
+    let mut lsop = packets.clone();
+    lsop[10][3] = 5;
+
+    let mut nsop = packets.clone();
+    nsop[10][5] = 3;
+
+    let mut missing = packets.clone();
+    missing.remove(10);
+
+    let mut header = packets.clone();
+    header[10][6..9].copy_from_slice(&[0xFF, 0x7F, 0xFF]);
+
+    let mut body = packets.clone();
+    body[10].truncate(200);
+
+    for corrupt in [lsop, nsop, missing, header, body].iter() {
+        let image = blue_with_sop(&blue, corrupt);
+        for (component, expected) in image.components().iter().zip(expected.components()) {
+            assert_eq!(component.samples(), expected.samples());
+        }
+    }
+}
+
+#[test]
+fn test_decode_resilient() {
+    let blue = read_codestream("blue.j2k");
+    let options = DecodeOptions {
+        resilient: true,
+        ..Default::default()
+    };
+
+    let image = decode_image(&mut Cursor::new(&blue), &options).unwrap();
+    assert!(image.concealed_code_blocks().is_empty());
+    let expected = decode_codestream("blue.j2k");
+    for (component, expected) in image.components().iter().zip(expected.components()) {
+        assert_eq!(component.samples(), expected.samples());
+    }
+
+    // blue.j2k was not coded with segmentation symbols, so those decoded at
+    // the end of the cleanup passes are not 1010
+    let mut codestream = blue.clone();
+    codestream[BLUE_COD + 12] |= 0b0010_0000;
+    assert!(decode_image(&mut Cursor::new(&codestream), &DecodeOptions::default()).is_err());
+
+    let image = decode_image(&mut Cursor::new(&codestream), &options).unwrap();
+    let concealed = image.concealed_code_blocks();
+    assert!(!concealed.is_empty());
+    for code_block in concealed {
+        assert_eq!(code_block.tile(), 0);
+        assert!(code_block.component() < 3);
+        assert!(code_block.resolution() <= 5);
+        assert!(code_block.width() <= 64 && code_block.height() <= 64);
+        assert!(code_block.no_passes_decoded() < code_block.no_passes());
+    }
+}
+
+#[test]
+fn test_decode_resilient_tiles_in_order() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // Two tiles with the corrupt segmentation symbols of blue.j2k, which
+    // report the same code-blocks, at different positions in their subbands,
+    // in the order they appear in the codestream however many threads decode
+    // them
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream[BLUE_COD + 12] |= 0b0010_0000;
+    codestream.extend(tile_part(0, 0, 1, &[], data, true));
+    codestream.extend(tile_part(1, 0, 1, &[], data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let options = DecodeOptions {
+        resilient: true,
+        ..Default::default()
+    };
+    let image = decode_image(&mut Cursor::new(&codestream), &options).unwrap();
+
+    // The single code-block of each subband of each resolution level of each
+    // component, with its coding passes and those decoded before the first
+    // corrupt segmentation symbol, the same with or without the rayon feature
+    let no_passes = [
+        19, 10, 13, 13, 16, 16, 19, 19, 19, 19, 19, 19, 19, 16, 19, 16, 22, 10, 13, 13, 13, 16, 16,
+        16, 16, 19, 19, 16, 19, 16, 16, 13, 13, 13, 16, 16, 16, 19, 19, 19, 19, 22, 19, 19, 19, 19,
+        19, 16,
+    ];
+    let no_passes_decoded = [
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0,
+    ];
+    let mut subbands = vec![];
+    for component in 0..3 {
+        subbands.push((component, 0, SubbandOrientation::LL));
+        for resolution in 1..6 {
+            for orientation in [
+                SubbandOrientation::HL,
+                SubbandOrientation::LH,
+                SubbandOrientation::HH,
+            ]
+            .iter()
+            {
+                subbands.push((component, resolution, *orientation));
+            }
+        }
+    }
+
+    let concealed = image.concealed_code_blocks();
+    assert_eq!(concealed.len(), 2 * subbands.len());
+    let (first, second) = concealed.split_at(subbands.len());
+    for (tile, code_blocks) in [first, second].iter().enumerate() {
+        for (i, code_block) in code_blocks.iter().enumerate() {
+            let (component, resolution, orientation) = subbands[i];
+            let scale = 1 << (resolution.max(1) - 1);
+            let (width, height) = (4 * scale, 2 * scale);
+            assert_eq!(code_block.tile(), tile as u16);
+            assert_eq!(
+                (
+                    code_block.component(),
+                    code_block.resolution(),
+                    code_block.orientation()
+                ),
+                (component, resolution, orientation)
+            );
+            assert_eq!(
+                (
+                    code_block.x0(),
+                    code_block.y0(),
+                    code_block.width(),
+                    code_block.height()
+                ),
+                (tile as u32 * width, 0, width, height)
+            );
+            assert_eq!(code_block.no_passes(), no_passes[i]);
+            assert_eq!(code_block.no_passes_decoded(), no_passes_decoded[i]);
+        }
+    }
+
+    // The sum of the samples of each component, and some of its samples
+    let expected = [
+        (622702, [26, 30, 33, 37], [34, 41, 50, 39], [46, 19, 0, 0]),
+        (624870, [26, 30, 33, 37], [34, 31, 29, 32], [28, 37, 46, 46]),
+        (642558, [50, 52, 54, 56], [28, 25, 23, 26], [28, 37, 46, 46]),
+    ];
+    for (component, (sum, first, middle, last)) in image.components().iter().zip(&expected) {
+        let samples = component.samples();
+        assert_eq!(
+            samples.iter().map(|&sample| sample as i64).sum::<i64>(),
+            *sum
+        );
+        assert_eq!(&samples[..4], first);
+        assert_eq!(&samples[256 * 32 + 60..256 * 32 + 64], middle);
+        assert_eq!(&samples[256 * 64 - 4..], last);
+    }
+}
+
+// Length of the packet header of each packet of blue.j2k
+const BLUE_PACKET_HEADERS: [usize; 18] =
+    [3, 3, 3, 9, 9, 9, 9, 9, 9, 9, 9, 9, 10, 11, 10, 12, 13, 12];
+
+// The packet headers of the packets of blue.j2k from the first up to the
+// last, and their packet bodies
+fn blue_packed(data: &[u8], first: usize, last: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut headers = vec![];
+    let mut bodies = vec![];
+    for i in first..last {
+        let packet = blue_packet(data, i / 3, i % 3);
+        headers.extend_from_slice(&packet[..BLUE_PACKET_HEADERS[i]]);
+        bodies.extend_from_slice(&packet[BLUE_PACKET_HEADERS[i]..]);
+    }
+    (headers, bodies)
+}
+
+// PPM or PPT marker segment with an index and its data
+fn packed_packet_headers(marker: u8, index: u8, data: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(3 + data.len() as u16).to_be_bytes());
+    segment.push(index);
+    segment.extend_from_slice(data);
+    segment
+}
+
+#[test]
+fn test_decode_packed_packet_headers_in_tile_part_headers() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // The packet headers of each tile-part are split over two PPT marker
+    // segments which are out of order
+    let mut codestream = blue_main_header(&blue, 128);
+    for (index, (first, last)) in [(0, 7), (7, 18)].iter().enumerate() {
+        let (headers, bodies) = blue_packed(data, *first, *last);
+        let split = BLUE_PACKET_HEADERS[*first];
+        let header = [
+            packed_packet_headers(0x61, 1, &headers[split..]),
+            packed_packet_headers(0x61, 0, &headers[..split]),
+        ]
+        .concat();
+        codestream.extend(tile_part(0, index as u8, 2, &header, &bodies, true));
+    }
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    assert_is_blue(codestream);
+}
+
+#[test]
+fn test_decode_packed_packet_headers_in_main_header() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // The Nppm and Ippm series of both tile-parts is split over two PPM
+    // marker segments in the middle of the packet headers of the second
+    // tile-part, the marker segments are out of order
+    let mut series = vec![];
+    let mut bodies = vec![];
+    for (first, last) in [(0, 7), (7, 18)].iter() {
+        let (headers, tile_part_bodies) = blue_packed(data, *first, *last);
+        series.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        series.extend(headers);
+        bodies.push(tile_part_bodies);
+    }
+    let split = series.len() - 20;
+
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(packed_packet_headers(0x60, 1, &series[split..]));
+    codestream.extend(packed_packet_headers(0x60, 0, &series[..split]));
+    let main_header = codestream.clone();
+    codestream.extend(tile_part(0, 0, 2, &[], &bodies[0], true));
+    codestream.extend(tile_part(0, 1, 2, &[], &bodies[1], true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    assert_is_blue(codestream);
+
+    // There are no packet headers for a third tile-part
+    let mut codestream = main_header.clone();
+    codestream.extend(tile_part(0, 0, 3, &[], &bodies[0], true));
+    codestream.extend(tile_part(0, 1, 3, &[], &bodies[1], true));
+    codestream.extend(tile_part(0, 2, 3, &[], &[], true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+
+    // PPT marker segments are not allowed with PPM marker segments
+    let (headers, _) = blue_packed(data, 0, 7);
+    let mut codestream = main_header;
+    let ppt = packed_packet_headers(0x61, 0, &headers);
+    codestream.extend(tile_part(0, 0, 2, &ppt, &bodies[0], true));
+    codestream.extend(tile_part(0, 1, 2, &[], &bodies[1], true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+}
+
+#[test]
+fn test_decode_packed_packet_headers_and_packets() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // The first tile-part has its packet headers in a PPT marker segment, but
+    // the second has them in its data
+    let (headers, bodies) = blue_packed(data, 0, 7);
+    let mut codestream = blue_main_header(&blue, 128);
+    let ppt = packed_packet_headers(0x61, 0, &headers);
+    codestream.extend(tile_part(0, 0, 2, &ppt, &bodies, true));
+    codestream.extend(tile_part(0, 1, 2, &[], &data[BLUE_PACKETS[7]..], true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+}
+
+#[test]
+fn test_decode_packed_packet_headers_in_main_and_tile_part_headers() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    // The packet headers of the single tile-part are both in a PPM marker
+    // segment of the main header and a PPT marker segment of its header
+    let (headers, bodies) = blue_packed(data, 0, 18);
+    let mut series = (headers.len() as u32).to_be_bytes().to_vec();
+    series.extend_from_slice(&headers);
+
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(packed_packet_headers(0x60, 0, &series));
+    let ppt = packed_packet_headers(0x61, 0, &headers);
+    codestream.extend(tile_part(0, 0, 1, &ppt, &bodies, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let mut reader = Cursor::new(codestream);
+    assert!(decode_image(&mut reader, &DecodeOptions::default()).is_err());
+
+    // Without the PPT marker segment the packet headers are those of PPM
+    let mut codestream = blue_main_header(&blue, 128);
+    codestream.extend(packed_packet_headers(0x60, 0, &series));
+    codestream.extend(tile_part(0, 0, 1, &[], &bodies, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    assert_is_blue(codestream);
+}
+
+fn decode_reduced(codestream: &[u8], reduce: u8) -> Result<Image, Box<dyn std::error::Error>> {
+    let options = DecodeOptions {
+        reduce,
+        ..Default::default()
+    };
+    decode_image(&mut Cursor::new(codestream), &options)
+}
+
+#[test]
+fn test_decode_reduce() {
+    let blue = read_codestream("blue.j2k");
+
+    for reduce in 1..=5 {
+        let image = decode_reduced(&blue, reduce).unwrap();
+        assert_eq!(image.width(), 128 >> reduce);
+        assert_eq!(image.height(), 64 >> reduce);
+        for component in image.components() {
+            assert_eq!(component.width(), 128 >> reduce);
+            assert_eq!(component.height(), 64 >> reduce);
+        }
+    }
+
+    // The NLLL subband, which is the forward RCT and 5-3 transformation of the
+    // lossless image followed by the inverse RCT
+    let image = decode_reduced(&blue, 5).unwrap();
+    let components = image.components();
+    assert_eq!(components[0].samples(), &[7, 9, 7, 9, 9, 7, 8, 8]);
+    assert_eq!(components[1].samples(), &[28, 40, 2, 36, 29, 21, 34, 20]);
+    assert_eq!(
+        components[2].samples(),
+        &[162, 177, 150, 171, 172, 169, 170, 169]
+    );
+
+    // Only five decomposition levels can be discarded
+    assert!(decode_reduced(&blue, 6).is_err());
+}
+
+#[test]
+fn test_decode_reduce_tiles() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream.extend(tile_part(0, 0, 1, &[], data, true));
+    codestream.extend(tile_part(1, 0, 1, &[], data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    let expected = decode_reduced(&blue, 2).unwrap();
+    let image = decode_reduced(&codestream, 2).unwrap();
+    assert_eq!(image.width(), 64);
+    assert_eq!(image.height(), 16);
+    for (component, expected) in image.components().iter().zip(expected.components()) {
+        for y in 0..16 {
+            for x in 0..32 {
+                assert_eq!(component.sample(x, y), expected.sample(x, y));
+                assert_eq!(component.sample(x + 32, y), expected.sample(x, y));
+            }
+        }
+    }
+}
+
+fn decode_area(
+    codestream: &[u8],
+    area: ImageArea,
+    reduce: u8,
+) -> Result<Image, Box<dyn std::error::Error>> {
+    let options = DecodeOptions {
+        reduce,
+        area: Some(area),
+        ..Default::default()
+    };
+    decode_image(&mut Cursor::new(codestream), &options)
+}
+
+// The window of each component is the same as in the image decoded in full
+fn assert_is_window(image: &Image, full: &Image) {
+    for (component, full) in image.components().iter().zip(full.components()) {
+        assert!(component.width() > 0 && component.height() > 0);
+        for y in 0..component.height() {
+            for x in 0..component.width() {
+                assert_eq!(
+                    component.sample(x, y),
+                    full.sample(
+                        component.x0() - full.x0() + x,
+                        component.y0() - full.y0() + y
+                    ),
+                    "{} {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_area() {
+    let blue = read_codestream("blue.j2k");
+    let full = decode_reduced(&blue, 0).unwrap();
+
+    let image = decode_area(&blue, ImageArea::new(37, 11, 101, 50), 0).unwrap();
+    assert_eq!(image.area(), &ImageArea::new(37, 11, 101, 50));
+    assert_eq!(image.width(), 64);
+    assert_eq!(image.height(), 39);
+    for component in image.components() {
+        assert_eq!(component.x0(), 37);
+        assert_eq!(component.y0(), 11);
+        assert_eq!(component.width(), 64);
+        assert_eq!(component.height(), 39);
+    }
+    assert_is_window(&image, &full);
+
+    // Windows are clipped to the image area
+    let image = decode_area(&blue, ImageArea::new(120, 60, 200, 100), 0).unwrap();
+    assert_eq!(image.area(), &ImageArea::new(120, 60, 128, 64));
+    assert_is_window(&image, &full);
+
+    assert!(decode_area(&blue, ImageArea::new(128, 0, 200, 64), 0).is_err());
+}
+
+#[test]
+fn test_decode_area_reduce() {
+    let blue = read_codestream("blue.j2k");
+    let full = decode_reduced(&blue, 2).unwrap();
+
+    let image = decode_area(&blue, ImageArea::new(37, 11, 101, 50), 2).unwrap();
+    assert_eq!(image.area(), &ImageArea::new(10, 3, 26, 13));
+    assert_is_window(&image, &full);
+}
+
+#[test]
+fn test_decode_area_tiles() {
+    let blue = read_codestream("blue.j2k");
+    let data = blue_tile_data(&blue);
+
+    let mut codestream = blue_main_header(&blue, 256);
+    codestream.extend(tile_part(0, 0, 1, &[], data, true));
+    codestream.extend(tile_part(1, 0, 1, &[], data, true));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    let full = decode_reduced(&codestream, 0).unwrap();
+
+    // Across both tiles, and only in the second tile
+    for area in [
+        ImageArea::new(100, 5, 150, 60),
+        ImageArea::new(140, 20, 141, 21),
+    ]
+    .iter()
+    {
+        let image = decode_area(&codestream, *area, 0).unwrap();
+        assert_eq!(image.area(), area);
+        assert_is_window(&image, &full);
+    }
+}
+
+#[test]
+fn test_decode_area_irreversible_sample() {
+    let codestream = read_sample("file1.jp2");
+    let full = decode_sample("file1.jp2");
+
+    let image = decode_area(&codestream, ImageArea::new(300, 200, 333, 251), 0).unwrap();
+    assert_eq!(image.width(), 33);
+    assert_eq!(image.height(), 51);
+    assert_is_window(&image, &full);
+
+    // The synthesis filters reach into the code-blocks on the other side of
+    // the code-block boundaries of the subbands
+    for area in [
+        ImageArea::new(200, 64, 256, 128),
+        ImageArea::new(256, 128, 290, 150),
+    ]
+    .iter()
+    {
+        let image = decode_area(&codestream, *area, 0).unwrap();
+        assert_is_window(&image, &full);
+    }
+}
+
+fn decode_layers(codestream: &[u8], max_layers: u16) -> Image {
+    let options = DecodeOptions {
+        max_layers: Some(max_layers),
+        ..Default::default()
+    };
+    decode_image(&mut Cursor::new(codestream), &options).expect("image should decode")
+}
+
+// Mean absolute difference between the samples of two images
+fn distortion(image: &Image, full: &Image) -> f64 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (component, full) in image.components().iter().zip(full.components()) {
+        for (a, b) in component.samples().iter().zip(full.samples()) {
+            sum += (a - b).abs() as f64;
+            count += 1;
+        }
+    }
+    sum / count as f64
+}
+
+#[test]
+fn test_decode_max_layers() {
+    // 5 quality layers
+    let codestream = read_sample("subsampling_2.jp2");
+    let full = decode_sample("subsampling_2.jp2");
+
+    let mut previous = f64::MAX;
+    for max_layers in 1..5 {
+        let image = decode_layers(&codestream, max_layers);
+        assert_eq!(image.area(), full.area());
+
+        // Every layer improves the quality
+        let distortion = distortion(&image, &full);
+        assert!(
+            distortion < previous,
+            "{} layers: {} >= {}",
+            max_layers,
+            distortion,
+            previous
+        );
+        assert!(distortion > 0.0);
+        previous = distortion;
+    }
+
+    for max_layers in [5, 6, u16::MAX].iter() {
+        let image = decode_layers(&codestream, *max_layers);
+        for (component, full) in image.components().iter().zip(full.components()) {
+            assert_eq!(component.samples(), full.samples());
+        }
+    }
+}
+
+#[test]
+fn test_decode_upsample() {
+    let options = DecodeOptions {
+        upsample: true,
+        ..Default::default()
+    };
+    let codestream = read_sample("file3.jp2");
+    let image = decode_image(&mut Cursor::new(&codestream), &options).unwrap();
+    let subsampled = decode_sample("file3.jp2");
+
+    for (component, subsampled) in image.components().iter().zip(subsampled.components()) {
+        assert_eq!(component.x0(), 0);
+        assert_eq!(component.y0(), 0);
+        assert_eq!(component.width(), 480);
+        assert_eq!(component.height(), 640);
+        assert_eq!(component.horizontal_separation(), 1);
+        assert_eq!(component.vertical_separation(), 1);
+
+        // The samples of subsampled components are at every other point
+        for y in 0..subsampled.height() {
+            for x in 0..subsampled.width() {
+                let (dx, dy) = (
+                    subsampled.horizontal_separation() as u32,
+                    subsampled.vertical_separation() as u32,
+                );
+                assert_eq!(component.sample(x * dx, y * dy), subsampled.sample(x, y));
+            }
+        }
+    }
+
+    // file2.jp2 is the same image without subsampling, with the components in
+    // reverse order
+    let full = decode_sample("file2.jp2");
+    for (component, full) in image
+        .components()
+        .iter()
+        .zip(full.components().iter().rev())
+    {
+        let difference: i32 = component
+            .samples()
+            .iter()
+            .zip(full.samples())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        assert!((difference as f64 / component.samples().len() as f64) < 2.5);
+    }
+}
+
+#[test]
+fn test_decode_component_registration() {
+    let blue = read_codestream("blue.j2k");
+    let image = decode_image(&mut Cursor::new(&blue), &DecodeOptions::default()).unwrap();
+
+    // CRG with the first component registered half a sample to the right
+    let mut codestream = blue.clone();
+    codestream.splice(
+        BLUE_SOT..BLUE_SOT,
+        [
+            0xFF, 0x63, 0x00, 0x0E, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ],
+    );
+    let options = DecodeOptions {
+        upsample: true,
+        ..Default::default()
+    };
+    let registered = decode_image(&mut Cursor::new(&codestream), &options).unwrap();
+
+    let region = Rectangle::new(0, 0, 128, 64);
+    let expected = upsample(
+        image.components()[0].samples(),
+        &region,
+        (1, 1),
+        (32768, 0),
+        &region,
+    );
+    assert_ne!(image.components()[0].samples(), &expected[..]);
+    assert_eq!(registered.components()[0].samples(), &expected[..]);
+    for c in 1..3 {
+        assert_eq!(
+            registered.components()[c].samples(),
+            image.components()[c].samples()
+        );
+    }
+}
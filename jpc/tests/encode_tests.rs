@@ -0,0 +1,276 @@
+mod common;
+
+use std::io::Cursor;
+
+use jpc::{
+    decode_image, decode_jpc, encode_image, Component, DecodeOptions, EncodeOptions, ImageArea,
+    MultipleComponentTransformation, ProgressionOrder, QuantizationStyle, TransformationFilter,
+};
+
+use common::Random;
+
+const PROGRESSION_ORDERS: [ProgressionOrder; 5] = [
+    ProgressionOrder::LRLCPP,
+    ProgressionOrder::RLLCPP,
+    ProgressionOrder::RLPCLP,
+    ProgressionOrder::PCRLLP,
+    ProgressionOrder::CPRLLP,
+];
+
+// Samples of a component with smooth gradients and some noise, from the
+// smallest to the largest value of the precision
+fn samples(width: u32, height: u32, precision: u8, signed: bool, seed: u32) -> Vec<i32> {
+    let mut random = Random::new(seed | 1);
+    let max = (1i64 << precision) - 1;
+    let mut samples = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let state = random.next_u32();
+
+            let gradient = (x * 7 + y * 3) as i64 * max / (7 * width + 3 * height) as i64;
+            let noise = (state % 64) as i64 - 32;
+            let mut sample = (gradient + noise).clamp(0, max);
+            if (x + y) % 17 < 1 {
+                sample = max;
+            }
+            if signed {
+                sample -= 1 << (precision - 1);
+            }
+            samples.push(sample as i32);
+        }
+    }
+    samples
+}
+
+fn components(
+    no_components: u16,
+    width: u32,
+    height: u32,
+    precision: u8,
+    signed: bool,
+) -> Vec<Component> {
+    (0..no_components)
+        .map(|c| {
+            Component::new(
+                width,
+                height,
+                precision,
+                signed,
+                samples(width, height, precision, signed, 1 + c as u32),
+            )
+        })
+        .collect()
+}
+
+fn encode(components: &[Component], options: &EncodeOptions) -> Vec<u8> {
+    let mut codestream = vec![];
+    encode_image(&mut codestream, components, options).expect("image should encode");
+    codestream
+}
+
+// Encode the components and decode them back
+fn round_trip(components: &[Component], options: &EncodeOptions) {
+    let codestream = encode(components, options);
+    let image = decode_image(&mut Cursor::new(&codestream), &DecodeOptions::default())
+        .expect("codestream should decode");
+
+    assert_eq!(image.components().len(), components.len());
+    for (decoded, component) in image.components().iter().zip(components) {
+        assert_eq!(decoded.x0(), component.x0());
+        assert_eq!(decoded.y0(), component.y0());
+        assert_eq!(decoded.width(), component.width());
+        assert_eq!(decoded.height(), component.height());
+        assert_eq!(decoded.precision(), component.precision());
+        assert_eq!(decoded.values_are_signed(), component.values_are_signed());
+        assert!(
+            decoded.samples() == component.samples(),
+            "samples differ with {:?}",
+            options
+        );
+    }
+}
+
+#[test]
+fn test_encode_greyscale() {
+    round_trip(&components(1, 37, 29, 8, false), &EncodeOptions::default());
+    round_trip(&components(1, 64, 64, 16, false), &EncodeOptions::default());
+    round_trip(&components(1, 1, 1, 8, false), &EncodeOptions::default());
+}
+
+#[test]
+fn test_encode_rgb() {
+    round_trip(&components(3, 41, 23, 8, false), &EncodeOptions::default());
+    round_trip(&components(3, 33, 35, 16, false), &EncodeOptions::default());
+
+    // The component transformation only applies to the first three
+    // components
+    round_trip(&components(4, 19, 21, 8, false), &EncodeOptions::default());
+}
+
+#[test]
+fn test_encode_signed() {
+    round_trip(&components(1, 23, 17, 12, true), &EncodeOptions::default());
+    round_trip(&components(3, 17, 23, 16, true), &EncodeOptions::default());
+    round_trip(&components(1, 16, 16, 1, false), &EncodeOptions::default());
+}
+
+#[test]
+fn test_encode_options() {
+    let components = components(3, 45, 38, 8, false);
+    for progression_order in PROGRESSION_ORDERS.iter() {
+        for tile_size in [None, Some((16, 16)), Some((32, 7))].iter() {
+            for code_block_size in [(4, 4), (32, 8), (64, 64)].iter() {
+                for no_decomposition_levels in [0, 1, 3, 6].iter() {
+                    let options = EncodeOptions {
+                        tile_size: *tile_size,
+                        code_block_size: *code_block_size,
+                        no_decomposition_levels: *no_decomposition_levels,
+                        progression_order: *progression_order,
+                    };
+                    round_trip(&components, &options);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_all_decomposition_levels() {
+    // Table A.10 allows 32 decomposition levels, far more than the size of
+    // the image needs
+    let components = components(3, 45, 38, 8, false);
+    for tile_size in [None, Some((16, 16))].iter() {
+        let options = EncodeOptions {
+            tile_size: *tile_size,
+            code_block_size: (4, 4),
+            no_decomposition_levels: 32,
+            ..EncodeOptions::default()
+        };
+        round_trip(&components, &options);
+    }
+}
+
+#[test]
+fn test_encode_offset() {
+    // A window of a decoded image keeps its position on the reference grid
+    let codestream = encode(&components(3, 50, 40, 8, false), &EncodeOptions::default());
+    let options = DecodeOptions {
+        area: Some(ImageArea::new(13, 7, 45, 31)),
+        ..DecodeOptions::default()
+    };
+    let image = decode_image(&mut Cursor::new(&codestream), &options).unwrap();
+    assert_eq!(image.components()[0].x0(), 13);
+
+    for progression_order in PROGRESSION_ORDERS.iter() {
+        let options = EncodeOptions {
+            tile_size: Some((8, 8)),
+            code_block_size: (8, 4),
+            no_decomposition_levels: 3,
+            progression_order: *progression_order,
+        };
+        round_trip(image.components(), &options);
+    }
+}
+
+#[test]
+fn test_encode_header() {
+    let options = EncodeOptions {
+        tile_size: Some((32, 16)),
+        code_block_size: (32, 16),
+        no_decomposition_levels: 4,
+        progression_order: ProgressionOrder::RLPCLP,
+    };
+    let codestream = encode(&components(3, 70, 33, 8, false), &options);
+    assert_eq!(&codestream[..2], &[0xFF, 0x4F]);
+    assert_eq!(&codestream[codestream.len() - 2..], &[0xFF, 0xD9]);
+
+    let decoded = decode_jpc(&mut Cursor::new(&codestream)).unwrap();
+    let header = decoded.header();
+
+    let siz = header.image_and_tile_size_marker_segment();
+    assert_eq!(siz.decoder_capabilities(), 0);
+    assert_eq!(siz.reference_grid_width(), 70);
+    assert_eq!(siz.reference_grid_height(), 33);
+    assert_eq!(siz.reference_tile_width(), 32);
+    assert_eq!(siz.reference_tile_height(), 16);
+    assert_eq!(siz.no_components(), 3);
+    assert_eq!(siz.precision(2).unwrap(), 8);
+    assert!(!siz.values_are_signed(2).unwrap());
+
+    let cod = header.coding_style_marker_segment();
+    assert_eq!(cod.progression_order(), ProgressionOrder::RLPCLP);
+    assert_eq!(cod.no_layers(), 1);
+    assert_eq!(
+        cod.multiple_component_transformation(),
+        MultipleComponentTransformation::Multiple
+    );
+    let parameters = cod.coding_style_parameters();
+    assert_eq!(parameters.no_decomposition_levels(), 4);
+    assert_eq!(parameters.code_block_width(), 32);
+    assert_eq!(parameters.code_block_height(), 16);
+    assert_eq!(parameters.code_block_style(), 0);
+    assert_eq!(
+        parameters.transformation(),
+        TransformationFilter::Reversible
+    );
+
+    // Without quantization, an exponent of the precision plus the gain of
+    // each subband
+    let qcd = header.quantization_default_marker_segment();
+    assert_eq!(qcd.quantization_style(), QuantizationStyle::No { guard: 2 });
+    assert_eq!(
+        qcd.quantization_exponents(),
+        vec![8, 9, 9, 10, 9, 9, 10, 9, 9, 10, 9, 9, 10]
+    );
+}
+
+#[test]
+fn test_encode_errors() {
+    let options = EncodeOptions::default();
+    let mut codestream = vec![];
+
+    // No components
+    assert!(encode_image(&mut codestream, &[], &options).is_err());
+
+    // Components of different sizes
+    let mut different = components(1, 8, 8, 8, false);
+    different.extend(components(1, 8, 9, 8, false));
+    assert!(encode_image(&mut codestream, &different, &options).is_err());
+
+    // Too few samples
+    let short = vec![Component::new(8, 8, 8, false, vec![0; 63])];
+    assert!(encode_image(&mut codestream, &short, &options).is_err());
+
+    // Precision out of range
+    for precision in [0, 17].iter() {
+        let component = vec![Component::new(4, 4, *precision, false, vec![0; 16])];
+        assert!(encode_image(&mut codestream, &component, &options).is_err());
+    }
+
+    let components = components(1, 8, 8, 8, false);
+    for code_block_size in [(2, 64), (48, 64), (2048, 4), (128, 64)].iter() {
+        let options = EncodeOptions {
+            code_block_size: *code_block_size,
+            ..EncodeOptions::default()
+        };
+        assert!(encode_image(&mut codestream, &components, &options).is_err());
+    }
+
+    let options = EncodeOptions {
+        no_decomposition_levels: 33,
+        ..EncodeOptions::default()
+    };
+    assert!(encode_image(&mut codestream, &components, &options).is_err());
+
+    let options = EncodeOptions {
+        tile_size: Some((0, 8)),
+        ..EncodeOptions::default()
+    };
+    assert!(encode_image(&mut codestream, &components, &options).is_err());
+
+    let options = EncodeOptions {
+        progression_order: ProgressionOrder::Reserved { value: 5 },
+        ..EncodeOptions::default()
+    };
+    assert!(encode_image(&mut codestream, &components, &options).is_err());
+}
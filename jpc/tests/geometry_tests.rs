@@ -0,0 +1,250 @@
+mod common;
+
+use std::io::Cursor;
+
+use jpc::geometry::{layout, reduce, resolution, subband, Rectangle};
+use jpc::{decode_jpc, ContiguousCodestream, SubbandOrientation};
+
+use common::read_codestream;
+
+// blue.j2k with the image offset to (3, 5), the second component subsampled
+// by two in both directions and precincts of 8 by 8 at resolution level 0 and
+// 16 by 16 above it
+fn blue_with_precincts() -> ContiguousCodestream {
+    let mut codestream = read_codestream("blue.j2k");
+
+    // XOsiz and YOsiz
+    codestream[0x13] = 3;
+    codestream[0x17] = 5;
+
+    // XRsiz and YRsiz of the second component
+    codestream[0x2E] = 2;
+    codestream[0x2F] = 2;
+
+    // Scod with defined precincts, and the precinct sizes of the six
+    // resolution levels after the SPcod of the COD marker segment
+    codestream[0x36] += 6;
+    codestream[0x37] = 0x01;
+    codestream.splice(0x41..0x41, [0x33, 0x44, 0x44, 0x44, 0x44, 0x44]);
+
+    decode_jpc(&mut Cursor::new(codestream)).unwrap()
+}
+
+#[test]
+fn test_layout_highest_resolution() {
+    let codestream = blue_with_precincts();
+    let header = codestream.header();
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+
+    let resolution = layout(siz, parameters, 0, 0, 5).unwrap();
+    assert_eq!(resolution.resolution(), 5);
+    assert_eq!(resolution.region(), &Rectangle::new(3, 5, 128, 64));
+    assert_eq!(resolution.reference_grid(), &Rectangle::new(3, 5, 128, 64));
+    assert_eq!(resolution.precinct_width_exponent(), 4);
+    assert_eq!(resolution.precinct_height_exponent(), 4);
+
+    // The code-blocks of 64 by 64 are bounded by the precincts of the
+    // subbands, which are half the size of those of the resolution level
+    assert_eq!(resolution.code_block_width_exponent(), 3);
+    assert_eq!(resolution.code_block_height_exponent(), 3);
+
+    let subbands = resolution.subbands();
+    let orientations: Vec<SubbandOrientation> = subbands
+        .iter()
+        .map(|subband| subband.orientation())
+        .collect();
+    assert_eq!(
+        orientations,
+        vec![
+            SubbandOrientation::HL,
+            SubbandOrientation::LH,
+            SubbandOrientation::HH
+        ]
+    );
+    assert_eq!(subbands[0].decomposition_level(), 1);
+    assert_eq!(subbands[0].region(), &Rectangle::new(1, 3, 64, 32));
+    assert_eq!(subbands[0].reference_grid(), &Rectangle::new(3, 6, 128, 64));
+
+    // Precincts are anchored at the origin, so the first ones are clipped by
+    // the image offset
+    assert_eq!(resolution.no_precincts(), (8, 4));
+    let precincts = resolution.precincts();
+    assert_eq!(precincts.len(), 32);
+    assert_eq!(precincts[0].index(), 0);
+    assert_eq!(precincts[0].region(), &Rectangle::new(3, 5, 16, 16));
+    assert_eq!(precincts[0].reference_grid(), &Rectangle::new(3, 5, 16, 16));
+    assert_eq!(precincts[1].region(), &Rectangle::new(16, 5, 32, 16));
+
+    let hl = &precincts[0].subbands()[0];
+    assert_eq!(hl.orientation(), SubbandOrientation::HL);
+    assert_eq!(hl.region(), &Rectangle::new(1, 3, 8, 8));
+    assert_eq!(hl.no_code_blocks(), (1, 1));
+    assert_eq!(hl.code_blocks()[0].region(), &Rectangle::new(1, 3, 8, 8));
+    assert_eq!(
+        hl.code_blocks()[0].reference_grid(),
+        &Rectangle::new(3, 6, 17, 16)
+    );
+    assert_eq!(
+        precincts[1].subbands()[0].region(),
+        &Rectangle::new(8, 3, 16, 8)
+    );
+}
+
+#[test]
+fn test_layout_lowest_resolution() {
+    let codestream = blue_with_precincts();
+    let header = codestream.header();
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+
+    let resolution = layout(siz, parameters, 0, 0, 0).unwrap();
+    assert_eq!(resolution.region(), &Rectangle::new(1, 1, 4, 2));
+    assert_eq!(
+        resolution.reference_grid(),
+        &Rectangle::new(32, 32, 128, 64)
+    );
+    assert_eq!(resolution.no_precincts(), (1, 1));
+
+    let subbands = resolution.subbands();
+    assert_eq!(subbands.len(), 1);
+    assert_eq!(subbands[0].orientation(), SubbandOrientation::LL);
+    assert_eq!(subbands[0].decomposition_level(), 5);
+    assert_eq!(subbands[0].region(), resolution.region());
+
+    let code_blocks = resolution.precincts()[0].subbands()[0].code_blocks();
+    assert_eq!(code_blocks.len(), 1);
+    assert_eq!(code_blocks[0].region(), &Rectangle::new(1, 1, 4, 2));
+}
+
+#[test]
+fn test_layout_subsampled_component() {
+    let codestream = blue_with_precincts();
+    let header = codestream.header();
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+
+    let resolution = layout(siz, parameters, 0, 1, 5).unwrap();
+    assert_eq!(resolution.region(), &Rectangle::new(2, 3, 64, 32));
+    assert_eq!(resolution.reference_grid(), &Rectangle::new(4, 6, 128, 64));
+    assert_eq!(resolution.no_precincts(), (4, 2));
+}
+
+#[test]
+fn test_layout_partition() {
+    // The code-blocks of the precincts of a resolution level cover each of
+    // its subbands exactly once
+    let blue = decode_jpc(&mut Cursor::new(read_codestream("blue.j2k"))).unwrap();
+    for codestream in [blue, blue_with_precincts()].iter() {
+        let header = codestream.header();
+        let siz = header.image_and_tile_size_marker_segment();
+        let parameters = header
+            .coding_style_marker_segment()
+            .coding_style_parameters();
+
+        for (c, r) in (0..3).flat_map(|c| (0..=5).map(move |r| (c, r))) {
+            let resolution = layout(siz, parameters, 0, c, r).unwrap();
+            for (s, subband) in resolution.subbands().iter().enumerate() {
+                let mut area = 0;
+                for precinct in resolution.precincts() {
+                    let part = &precinct.subbands()[s];
+                    assert_eq!(part.orientation(), subband.orientation());
+                    let (wide, high) = part.no_code_blocks();
+                    assert_eq!(part.code_blocks().len(), (wide * high) as usize);
+
+                    for code_block in part.code_blocks() {
+                        let region = code_block.region();
+                        assert_eq!(&region.intersection(subband.region()), region);
+                        area += region.width() * region.height();
+                    }
+                }
+                assert_eq!(area, subband.region().width() * subband.region().height());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_layout_out_of_range() {
+    let codestream = decode_jpc(&mut Cursor::new(read_codestream("blue.j2k"))).unwrap();
+    let header = codestream.header();
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+
+    assert!(layout(siz, parameters, 1, 0, 0).is_err());
+    assert!(layout(siz, parameters, 0, 3, 0).is_err());
+    assert!(layout(siz, parameters, 0, 0, 6).is_err());
+}
+
+#[test]
+fn test_subband_all_decomposition_levels() {
+    // At the 32 decomposition levels of Table A.10, 2^NL no longer fits in
+    // the coordinates
+    let tile_component = Rectangle::new(0, 0, u32::MAX, 38);
+    assert_eq!(
+        subband(&tile_component, 32, 0, SubbandOrientation::LL),
+        Rectangle::new(0, 0, 1, 1)
+    );
+    assert_eq!(
+        subband(&tile_component, 32, 1, SubbandOrientation::HL),
+        Rectangle::new(0, 0, 1, 1)
+    );
+    assert_eq!(
+        subband(&tile_component, 32, 1, SubbandOrientation::LH),
+        Rectangle::new(0, 0, 1, 0)
+    );
+    assert_eq!(
+        subband(&tile_component, 32, 2, SubbandOrientation::HL),
+        Rectangle::new(0, 0, 2, 1)
+    );
+}
+
+#[test]
+fn test_reduce_all_decomposition_levels() {
+    let rectangle = Rectangle::new(3, 5, u32::MAX, 64);
+    assert_eq!(reduce(&rectangle, 0), rectangle);
+    assert_eq!(reduce(&rectangle, 31), Rectangle::new(1, 1, 2, 1));
+    assert_eq!(reduce(&rectangle, 32), Rectangle::new(1, 1, 1, 1));
+    assert_eq!(
+        reduce(&Rectangle::new(0, 0, 45, 38), 32),
+        Rectangle::new(0, 0, 1, 1)
+    );
+
+    assert_eq!(resolution(&rectangle, 32, 0), Rectangle::new(1, 1, 1, 1));
+    assert_eq!(resolution(&rectangle, 32, 32), rectangle);
+}
+
+#[test]
+fn test_layout_all_decomposition_levels() {
+    // blue.j2k with the 32 decomposition levels of Table A.10 in the SPcod of
+    // the COD marker segment
+    let mut bytes = read_codestream("blue.j2k");
+    bytes[0x3C] = 32;
+
+    let codestream = decode_jpc(&mut Cursor::new(bytes)).unwrap();
+    let header = codestream.header();
+    let siz = header.image_and_tile_size_marker_segment();
+    let parameters = header
+        .coding_style_marker_segment()
+        .coding_style_parameters();
+
+    // The single coefficient of resolution level 0 stands for the whole
+    // tile-component
+    let resolution = layout(siz, parameters, 0, 0, 0).unwrap();
+    assert_eq!(resolution.region(), &Rectangle::new(0, 0, 1, 1));
+    assert_eq!(resolution.reference_grid(), &Rectangle::new(0, 0, 128, 64));
+
+    let resolution = layout(siz, parameters, 0, 0, 1).unwrap();
+    let subbands = resolution.subbands();
+    assert_eq!(subbands[0].decomposition_level(), 32);
+    assert!(subbands[0].region().is_empty());
+    assert_eq!(subbands[0].reference_grid(), &Rectangle::default());
+}
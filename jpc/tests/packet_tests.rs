@@ -0,0 +1,385 @@
+mod common;
+
+use jpc::code_block::is_terminated;
+use jpc::packet::{CodeBlockContribution, Precinct};
+
+use common::Random;
+
+#[test]
+fn test_packet_header_zero_length() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    let header = precinct.decode_packet_header(&[0x00], 0).unwrap();
+    assert!(header.is_empty());
+    assert_eq!(header.length(), 1);
+    assert_eq!(header.body_length(), 0);
+}
+
+#[test]
+fn test_packet_header_single_code_block() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+
+    // 1       non-zero length packet
+    // 1       included in layer 0 (inclusion tag tree)
+    // 0001    three zero bit-planes (zero bit-plane tag tree)
+    // 1100    three coding passes
+    // 0       no Lblock increment
+    // 1010    length of 10 bytes in Lblock + floor(log2(3)) = 4 bits
+    let header = precinct.decode_packet_header(&[0xC7, 0x14], 0).unwrap();
+    assert_eq!(header.length(), 2);
+    assert_eq!(header.contributions().len(), 1);
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.subband(), 0);
+    assert_eq!(contribution.code_block(), 0);
+    assert_eq!(contribution.zero_bit_planes(), Some(3));
+    assert_eq!(contribution.no_passes(), 3);
+    assert_eq!(contribution.segment_lengths(), &[10]);
+    assert_eq!(header.body_length(), 10);
+
+    // 1       non-zero length packet
+    // 1       included again, signalled with a single bit
+    // 0       one coding pass
+    // 0       no Lblock increment
+    // 101     length of 5 bytes in Lblock + floor(log2(1)) = 3 bits
+    let header = precinct.decode_packet_header(&[0xCA], 1).unwrap();
+    assert_eq!(header.length(), 1);
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.zero_bit_planes(), None);
+    assert_eq!(contribution.no_passes(), 1);
+    assert_eq!(contribution.segment_lengths(), &[5]);
+}
+
+#[test]
+fn test_packet_header_inclusion_tag_tree() {
+    // Two code-blocks side by side, only the second included in layer 0.
+    let mut precinct = Precinct::new(&[(2, 1)]);
+
+    // 1       non-zero length packet
+    // 1       root of the inclusion tag tree is 0
+    // 0       first code-block not included in layer 0
+    // 1       second code-block included in layer 0
+    // 1       root of the zero bit-plane tag tree is 0
+    // 1       second code-block has no zero bit-planes
+    // 0       one coding pass
+    // 10      Lblock increased to 4
+    // 0001    length of 1 byte
+    let header = precinct
+        .decode_packet_header(&[0b1101_1101, 0b0000_1000], 0)
+        .unwrap();
+    assert_eq!(header.length(), 2);
+    assert_eq!(header.contributions().len(), 1);
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.code_block(), 1);
+    assert_eq!(contribution.zero_bit_planes(), Some(0));
+    assert_eq!(contribution.segment_lengths(), &[1]);
+}
+
+#[test]
+fn test_packet_header_bit_stuffing() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+
+    // 1       non-zero length packet
+    // 1       included in layer 0
+    // 1       no zero bit-planes
+    // 1111 11111 0000000   37 coding passes
+    // 0       no Lblock increment
+    // 11110001 length in 3 + 5 bits
+    //
+    // The first byte is 0xFF, so the next byte only holds 7 bits after the
+    // stuffed zero bit.
+    let header = precinct
+        .decode_packet_header(&[0xFF, 0b0111_1000, 0b0000_0111, 0b1000_1000], 0)
+        .unwrap();
+    assert_eq!(header.length(), 4);
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.zero_bit_planes(), Some(0));
+    assert_eq!(contribution.no_passes(), 37);
+    assert_eq!(contribution.segment_lengths(), &[0b1111_0001]);
+}
+
+#[test]
+fn test_packet_header_eph() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    let header = precinct
+        .decode_packet_header(&[0x00, 0xFF, 0x92], 0)
+        .unwrap();
+    assert_eq!(header.length(), 3);
+}
+
+#[test]
+fn test_packet_header_truncated() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    assert!(precinct.decode_packet_header(&[0xC7], 0).is_err());
+}
+
+#[test]
+fn test_packet_header_length_bits() {
+    // 1       non-zero length packet
+    // 1       included in layer 0
+    // 1       no zero bit-planes
+    // 10      two coding passes
+    // 1...10  28 Lblock increments
+    // 0...01  length of 1 byte in Lblock + floor(log2(2)) = 32 bits
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    let header = precinct
+        .decode_packet_header(&[0xF7, 0xFF, 0x7F, 0xFF, 0x60, 0x00, 0x00, 0x00, 0x10], 0)
+        .unwrap();
+    assert_eq!(header.contributions()[0].segment_lengths(), &[1]);
+
+    // With 29 Lblock increments the length would need 33 bits
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    let header = [0xF7, 0xFF, 0x7F, 0xFF, 0x70, 0x00, 0x00, 0x00, 0x00];
+    assert!(precinct.decode_packet_header(&header, 0).is_err());
+
+    // A corrupt packet header of only 1 bits
+    let mut header = vec![0xEF];
+    header.extend_from_slice(&[0xFF; 60]);
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    assert!(precinct.decode_packet_header(&header, 0).is_err());
+}
+
+// Packs bits into bytes, the last byte padded with 0 bits
+fn pack_bits(bits: &[u8]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &bit)| byte | bit << (7 - i))
+        })
+        .collect()
+}
+
+#[test]
+fn test_packet_header_zero_bit_planes() {
+    // 1       non-zero length packet
+    // 1       included in layer 0
+    // 0...01  255 zero bit-planes
+    // 0       one coding pass
+    // 0       no Lblock increment
+    // 001     length of 1 byte in Lblock + floor(log2(1)) = 3 bits
+    let mut bits = vec![1, 1];
+    bits.extend_from_slice(&[0; 255]);
+    bits.extend_from_slice(&[1, 0, 0, 0, 0, 1]);
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    let header = precinct.decode_packet_header(&pack_bits(&bits), 0).unwrap();
+    assert_eq!(header.contributions()[0].zero_bit_planes(), Some(255));
+
+    // 256 zero bit-planes do not fit the 8 bits of the count
+    let mut bits = vec![1, 1];
+    bits.extend_from_slice(&[0; 256]);
+    bits.extend_from_slice(&[1, 0, 0, 0, 0, 1]);
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    assert!(precinct.decode_packet_header(&pack_bits(&bits), 0).is_err());
+}
+
+#[test]
+fn test_packet_header_termination_on_each_coding_pass() {
+    let mut precinct = Precinct::with_code_block_style(&[(1, 1)], 0b0000_0100);
+
+    // 1       non-zero length packet
+    // 1       included in layer 0
+    // 1       no zero bit-planes
+    // 1100    three coding passes
+    // 0       no Lblock increment
+    // 010     length of 2 bytes of the first segment in Lblock bits
+    // 000     length of 0 bytes of the second segment
+    // 101     length of 5 bytes of the third segment
+    let header = precinct
+        .decode_packet_header(&[0xF8, 0x42, 0x80], 0)
+        .unwrap();
+    assert_eq!(header.length(), 3);
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.no_passes(), 3);
+    assert_eq!(contribution.segment_lengths(), &[2, 0, 5]);
+    assert!(!contribution.is_continued());
+    assert_eq!(header.body_length(), 7);
+
+    // 1       non-zero length packet
+    // 1       included again
+    // 10      two coding passes
+    // 0       no Lblock increment
+    // 001     length of 1 byte of the fourth segment
+    // 011     length of 3 bytes of the fifth segment
+    let header = precinct.decode_packet_header(&[0xE1, 0x60], 1).unwrap();
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.segment_lengths(), &[1, 3]);
+    assert!(!contribution.is_continued());
+}
+
+#[test]
+fn test_packet_header_selective_arithmetic_coding_bypass() {
+    let mut precinct = Precinct::with_code_block_style(&[(1, 1)], 0b0000_0001);
+
+    // 1       non-zero length packet
+    // 1       included in layer 0
+    // 1       no zero bit-planes
+    // 1111 00101   11 coding passes
+    // 0       no Lblock increment
+    // 010100  length of 20 bytes of the ten arithmetically coded passes in
+    //         Lblock + floor(log2(10)) = 6 bits
+    // 001     length of 1 byte of the first raw pass
+    let header = precinct
+        .decode_packet_header(&[0xFE, 0x52, 0x84], 0)
+        .unwrap();
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.no_passes(), 11);
+    assert_eq!(contribution.segment_lengths(), &[20, 1]);
+    assert!(!contribution.is_continued());
+
+    // 1       non-zero length packet
+    // 1       included again
+    // 10      two coding passes
+    // 0       no Lblock increment
+    // 011     length of 3 bytes of the second raw pass, which continues the
+    //         raw segment of the previous packet
+    // 100     length of 4 bytes of the arithmetically coded cleanup pass
+    let header = precinct.decode_packet_header(&[0xE3, 0x80], 1).unwrap();
+    let contribution = &header.contributions()[0];
+    assert_eq!(contribution.segment_lengths(), &[3, 4]);
+    assert!(contribution.is_continued());
+    assert_eq!(header.body_length(), 7);
+}
+
+#[test]
+fn test_encode_packet_header_zero_length() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, None, 0);
+    assert_eq!(precinct.encode_packet_header(&[], 0).unwrap(), vec![0x00]);
+}
+
+#[test]
+fn test_encode_packet_header_single_code_block() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, Some(0), 3);
+
+    let contribution = CodeBlockContribution::new(0, 0, 3, vec![10]);
+    let header = precinct.encode_packet_header(&[contribution], 0).unwrap();
+    assert_eq!(header, vec![0xC7, 0x14]);
+
+    let contribution = CodeBlockContribution::new(0, 0, 1, vec![5]);
+    let header = precinct.encode_packet_header(&[contribution], 1).unwrap();
+    assert_eq!(header, vec![0xCA]);
+}
+
+#[test]
+fn test_encode_packet_header_bit_stuffing() {
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, Some(0), 0);
+
+    let contribution = CodeBlockContribution::new(0, 0, 37, vec![0b1111_0001]);
+    let header = precinct.encode_packet_header(&[contribution], 0).unwrap();
+    assert_eq!(header, vec![0xFF, 0b0111_1000, 0b0000_0111, 0b1000_1000]);
+}
+
+#[test]
+fn test_encode_packet_header_round_trip() {
+    // Two subbands of 3x2 and 2x2 code-blocks, each first included in a layer
+    // from 0 to 3 or never, with a few coding passes and segment lengths of
+    // up to 2000 bytes in each later layer
+    let no_code_blocks = [(3, 2), (2, 2)];
+    let mut generator = Random::new(0x1234_5678);
+    let mut random = |n: u32| generator.next_u32() % n;
+
+    for code_block_style in [0, 0b0000_0001, 0b0000_0100, 0b0000_0101].iter() {
+        let bypass = code_block_style & 0b0000_0001 != 0;
+        let termination = code_block_style & 0b0000_0100 != 0;
+
+        let mut encoder = Precinct::with_code_block_style(&no_code_blocks, *code_block_style);
+        let mut decoder = Precinct::with_code_block_style(&no_code_blocks, *code_block_style);
+
+        let mut first_layers = vec![];
+        for (s, (wide, high)) in no_code_blocks.iter().enumerate() {
+            for i in 0..(wide * high) as usize {
+                let layer = match random(5) {
+                    4 => None,
+                    layer => Some(layer as u16),
+                };
+                let zero_bit_planes = random(12) as u8;
+                encoder.set_code_block(s, i, layer, zero_bit_planes);
+                first_layers.push((s, i, layer, zero_bit_planes, 0u8));
+            }
+        }
+
+        for layer in 0..6 {
+            let mut contributions = vec![];
+            for (s, i, first_layer, _, no_passes) in first_layers.iter_mut() {
+                let included = match first_layer {
+                    Some(first_layer) => {
+                        layer == *first_layer || (layer > *first_layer && random(3) > 0)
+                    }
+                    None => false,
+                };
+                if !included {
+                    continue;
+                }
+
+                let new_passes = 1 + random(40) as u8;
+                let mut segment_lengths = vec![];
+                for pass in *no_passes..*no_passes + new_passes {
+                    if pass == *no_passes + new_passes - 1
+                        || is_terminated(pass, bypass, termination)
+                    {
+                        segment_lengths.push(random(2000));
+                    }
+                }
+                *no_passes += new_passes;
+                contributions.push(CodeBlockContribution::new(
+                    *s,
+                    *i,
+                    new_passes,
+                    segment_lengths,
+                ));
+            }
+
+            let header = encoder.encode_packet_header(&contributions, layer).unwrap();
+            let decoded = decoder.decode_packet_header(&header, layer).unwrap();
+            assert_eq!(decoded.length(), header.len());
+            assert_eq!(decoded.contributions().len(), contributions.len());
+
+            for (decoded, contribution) in decoded.contributions().iter().zip(&contributions) {
+                assert_eq!(decoded.subband(), contribution.subband());
+                assert_eq!(decoded.code_block(), contribution.code_block());
+                assert_eq!(decoded.no_passes(), contribution.no_passes());
+                assert_eq!(decoded.segment_lengths(), contribution.segment_lengths());
+
+                let (_, _, first_layer, zero_bit_planes, _) = first_layers
+                    .iter()
+                    .find(|(s, i, ..)| (*s, *i) == (decoded.subband(), decoded.code_block()))
+                    .unwrap();
+                if *first_layer == Some(layer) {
+                    assert_eq!(decoded.zero_bit_planes(), Some(*zero_bit_planes));
+                } else {
+                    assert_eq!(decoded.zero_bit_planes(), None);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_packet_header_errors() {
+    // A code-block included before the layer it was set to
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, Some(1), 0);
+    let contribution = CodeBlockContribution::new(0, 0, 1, vec![1]);
+    assert!(precinct.encode_packet_header(&[contribution], 0).is_err());
+
+    // A code-block left out of the layer it was set to
+    let mut precinct = Precinct::new(&[(2, 1)]);
+    precinct.set_code_block(0, 0, Some(0), 0);
+    precinct.set_code_block(0, 1, Some(0), 0);
+    let contribution = CodeBlockContribution::new(0, 0, 1, vec![1]);
+    assert!(precinct.encode_packet_header(&[contribution], 0).is_err());
+
+    // Two segment lengths for a single codeword segment
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, Some(0), 0);
+    let contribution = CodeBlockContribution::new(0, 0, 2, vec![1, 2]);
+    assert!(precinct.encode_packet_header(&[contribution], 0).is_err());
+
+    // More coding passes than a packet header can signal
+    let mut precinct = Precinct::new(&[(1, 1)]);
+    precinct.set_code_block(0, 0, Some(0), 0);
+    let contribution = CodeBlockContribution::new(0, 0, 165, vec![1]);
+    assert!(precinct.encode_packet_header(&[contribution], 0).is_err());
+}
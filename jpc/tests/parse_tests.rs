@@ -35,11 +35,11 @@ fn test_blue() {
     assert_eq!(siz.reference_tile_height(), 64);
     assert_eq!(siz.no_components(), 3);
     assert_eq!(siz.precision(0).unwrap(), 8);
-    assert_eq!(siz.values_are_signed(0).unwrap(), false);
+    assert!(!siz.values_are_signed(0).unwrap());
     assert_eq!(siz.precision(1).unwrap(), 8);
-    assert_eq!(siz.values_are_signed(1).unwrap(), false);
+    assert!(!siz.values_are_signed(1).unwrap());
     assert_eq!(siz.precision(2).unwrap(), 8);
-    assert_eq!(siz.values_are_signed(2).unwrap(), false);
+    assert!(!siz.values_are_signed(2).unwrap());
     assert_eq!(siz.horizontal_separation(0).unwrap(), 1);
     assert_eq!(siz.horizontal_separation(1).unwrap(), 1);
     assert_eq!(siz.horizontal_separation(2).unwrap(), 1);
@@ -0,0 +1,111 @@
+use jpc::quantization::{
+    dequantize, dequantize_reversible, Quantization, DEFAULT_RECONSTRUCTION_BIAS,
+};
+use jpc::{QuantizationStyle, SubbandOrientation};
+
+#[test]
+fn test_no_quantization() {
+    let exponents = [9, 10, 10, 11, 8, 8, 9];
+    let quantization =
+        Quantization::new(QuantizationStyle::No { guard: 1 }, &exponents, &[0; 7], 2).unwrap();
+
+    assert_eq!(quantization.guard_bits(), 1);
+    assert_eq!(quantization.exponent(0, SubbandOrientation::LL), 9);
+    assert_eq!(quantization.exponent(1, SubbandOrientation::LH), 10);
+    assert_eq!(quantization.exponent(1, SubbandOrientation::HH), 11);
+    assert_eq!(quantization.exponent(2, SubbandOrientation::HL), 8);
+    assert_eq!(quantization.magnitude_bits(2, SubbandOrientation::HH), 9);
+    assert_eq!(quantization.step_size(2, SubbandOrientation::HH, 8), 1.0);
+}
+
+#[test]
+fn test_scalar_derived_quantization() {
+    let quantization = Quantization::new(
+        QuantizationStyle::ScalarDerived { guard: 2 },
+        &[10],
+        &[1024],
+        2,
+    )
+    .unwrap();
+
+    // ε_b = ε_0 - NL + n_b, μ_b = μ_0
+    assert_eq!(quantization.exponent(0, SubbandOrientation::LL), 10);
+    assert_eq!(quantization.exponent(1, SubbandOrientation::HL), 10);
+    assert_eq!(quantization.exponent(2, SubbandOrientation::HH), 9);
+    assert_eq!(quantization.mantissa(2, SubbandOrientation::HH), 1024);
+    assert_eq!(quantization.magnitude_bits(0, SubbandOrientation::LL), 11);
+    assert_eq!(quantization.magnitude_bits(2, SubbandOrientation::LH), 10);
+
+    // Δ_b = 2^(R_b - ε_b) * (1 + μ_b / 2^11)
+    assert_eq!(quantization.step_size(0, SubbandOrientation::LL, 10), 1.5);
+    assert_eq!(quantization.step_size(2, SubbandOrientation::HH, 10), 12.0);
+}
+
+#[test]
+fn test_scalar_expounded_quantization() {
+    let exponents = [8, 9, 9, 9];
+    let mantissas = [0, 512, 512, 1536];
+    let quantization = Quantization::new(
+        QuantizationStyle::ScalarExpounded { guard: 1 },
+        &exponents,
+        &mantissas,
+        1,
+    )
+    .unwrap();
+
+    assert_eq!(quantization.step_size(0, SubbandOrientation::LL, 8), 1.0);
+    assert_eq!(quantization.step_size(1, SubbandOrientation::LH, 8), 1.25);
+    assert_eq!(quantization.step_size(1, SubbandOrientation::HH, 8), 3.5);
+}
+
+#[test]
+fn test_quantization_missing_values() {
+    let result = Quantization::new(
+        QuantizationStyle::ScalarExpounded { guard: 1 },
+        &[8, 9],
+        &[0, 0],
+        1,
+    );
+    assert!(result.is_err());
+
+    let result = Quantization::new(QuantizationStyle::ScalarDerived { guard: 1 }, &[2], &[0], 5);
+    assert!(result.is_err());
+
+    let result = Quantization::new(QuantizationStyle::Reserved { value: 3 }, &[8], &[0], 0);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_dequantize() {
+    let coefficients = [0, 5, -5, 8];
+
+    let values = dequantize(&coefficients, 2.0, 4, 4, DEFAULT_RECONSTRUCTION_BIAS);
+    assert_eq!(values, vec![0.0, 11.0, -11.0, 17.0]);
+
+    let values = dequantize(&coefficients, 2.0, 4, 4, 0.0);
+    assert_eq!(values, vec![0.0, 10.0, -10.0, 16.0]);
+
+    // Two bit-planes not decoded, the bias is scaled to the interval of 4
+    let values = dequantize(&[0, 4, -8], 1.0, 4, 2, DEFAULT_RECONSTRUCTION_BIAS);
+    assert_eq!(values, vec![0.0, 6.0, -10.0]);
+}
+
+#[test]
+fn test_dequantize_reversible() {
+    // Every bit-plane decoded, the coefficients are exact
+    let coefficients = [0, 5, -5, 8];
+    let values = dequantize_reversible(&coefficients, 4, 4, DEFAULT_RECONSTRUCTION_BIAS);
+    assert_eq!(values, coefficients.to_vec());
+
+    // Two bit-planes not decoded, the coefficients are at the midpoint of the
+    // interval of 4
+    let values = dequantize_reversible(&[0, 4, -8], 4, 2, DEFAULT_RECONSTRUCTION_BIAS);
+    assert_eq!(values, vec![0, 6, -10]);
+
+    // One bit-plane not decoded, the bias is rounded down
+    let values = dequantize_reversible(&[2, -2], 4, 3, 0.75);
+    assert_eq!(values, vec![3, -3]);
+
+    let values = dequantize_reversible(&[0, 4, -8], 4, 2, 0.0);
+    assert_eq!(values, vec![0, 4, -8]);
+}
@@ -0,0 +1,60 @@
+use jpc::code_block::CodeBlockDecoder;
+use jpc::quantization::{dequantize_reversible, DEFAULT_RECONSTRUCTION_BIAS};
+use jpc::region_of_interest::{descale, split};
+use jpc::SubbandOrientation;
+
+#[test]
+fn test_descale() {
+    // With a shift of 4 the background is below 16 and the region of interest
+    // is scaled down by 16
+    let mut coefficients = [0, 1, -15, 16, -16, 35, -100, 255];
+    descale(&mut coefficients, 4);
+    assert_eq!(coefficients, [0, 1, -15, 1, -1, 2, -6, 15]);
+}
+
+#[test]
+fn test_descale_without_shift() {
+    let mut coefficients = [0, 1, -15, 16, -16, 35, -100, 255];
+    descale(&mut coefficients, 0);
+    assert_eq!(coefficients, [0, 1, -15, 16, -16, 35, -100, 255]);
+}
+
+#[test]
+fn test_split() {
+    let coefficients = [0, 1, -15, 16, -16, 35, -100, 255];
+    let (background, region) = split(&coefficients, 4);
+    assert_eq!(background, vec![0, 1, -15, 0, 0, 0, 0, 0]);
+    assert_eq!(region, vec![0, 0, 0, 1, -1, 2, -6, 15]);
+
+    let (background, region) = split(&coefficients, 0);
+    assert_eq!(background, coefficients.to_vec());
+    assert_eq!(region, vec![0; 8]);
+}
+
+#[test]
+fn test_split_truncated() {
+    // A code-block with 4 magnitude bits and a shift of 4 of the region of
+    // interest 9, -13, 0, 0 and the background 0, 0, 12, -5, truncated after
+    // the 4 bit-planes of the region of interest and 1 of the background
+    let (magnitude_bits, shift) = (4, 4);
+    let segment: &[u8] = &[0x03, 0x2C, 0x6D, 0x11, 0xA7];
+
+    let mut decoder = CodeBlockDecoder::new(2, 2, SubbandOrientation::LL, 0);
+    decoder
+        .decode(&[segment], 13, 0, magnitude_bits + shift)
+        .unwrap();
+    assert_eq!(decoder.no_bit_planes_decoded(), 5);
+
+    // The region of interest is exact, while the background is missing 3
+    // bit-planes and is reconstructed at the midpoint of its interval
+    let (background, region) = split(&decoder.coefficients(), shift);
+    let region = dequantize_reversible(&region, magnitude_bits, 5, DEFAULT_RECONSTRUCTION_BIAS);
+    assert_eq!(region, vec![9, -13, 0, 0]);
+    let background = dequantize_reversible(
+        &background,
+        magnitude_bits + shift,
+        5,
+        DEFAULT_RECONSTRUCTION_BIAS,
+    );
+    assert_eq!(background, vec![0, 0, 12, 0]);
+}
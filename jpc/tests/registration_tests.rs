@@ -0,0 +1,63 @@
+use jpc::geometry::Rectangle;
+use jpc::registration::upsample;
+
+#[test]
+fn test_upsample() {
+    // Points between two samples are interpolated, and the points after the
+    // last sample take its value
+    let upsampled = upsample(
+        &[0, 100],
+        &Rectangle::new(0, 0, 2, 1),
+        (2, 1),
+        (0, 0),
+        &Rectangle::new(0, 0, 4, 1),
+    );
+    assert_eq!(upsampled, vec![0, 50, 100, 100]);
+
+    let upsampled = upsample(
+        &[0, 100, 200, 300],
+        &Rectangle::new(0, 0, 2, 2),
+        (2, 2),
+        (0, 0),
+        &Rectangle::new(0, 0, 3, 3),
+    );
+    assert_eq!(upsampled, vec![0, 50, 100, 100, 150, 200, 200, 250, 300]);
+}
+
+#[test]
+fn test_upsample_component_offset() {
+    // The first sample of the component is at x = 1, which is the point 2 of
+    // the reference grid
+    let upsampled = upsample(
+        &[0, 100],
+        &Rectangle::new(1, 0, 3, 1),
+        (2, 1),
+        (0, 0),
+        &Rectangle::new(2, 0, 6, 1),
+    );
+    assert_eq!(upsampled, vec![0, 50, 100, 100]);
+}
+
+#[test]
+fn test_upsample_component_registration() {
+    // With a horizontal offset of half the separation, each sample is
+    // registered one point of the reference grid to the right
+    let upsampled = upsample(
+        &[0, 100],
+        &Rectangle::new(0, 0, 2, 1),
+        (2, 1),
+        (32768, 0),
+        &Rectangle::new(0, 0, 4, 1),
+    );
+    assert_eq!(upsampled, vec![0, 0, 50, 100]);
+
+    // and a quarter of the separation vertically
+    let upsampled = upsample(
+        &[0, 100],
+        &Rectangle::new(0, 0, 1, 2),
+        (1, 4),
+        (0, 16384),
+        &Rectangle::new(0, 0, 1, 6),
+    );
+    assert_eq!(upsampled, vec![0, 0, 25, 50, 75, 100]);
+}
@@ -0,0 +1,121 @@
+use jpc::transformation::{
+    forward_dc_level_shift, forward_irreversible, forward_reversible, inverse_dc_level_shift,
+    inverse_dc_level_shift_irreversible, inverse_irreversible, inverse_reversible,
+};
+
+#[test]
+fn test_inverse_reversible() {
+    // Forward transformations of (100, 50, 25) and (0, 255, 0), see G.2.1
+    let mut c0 = vec![56, 127];
+    let mut c1 = vec![-25, -255];
+    let mut c2 = vec![50, -255];
+    inverse_reversible(&mut c0, &mut c1, &mut c2);
+    assert_eq!(c0, vec![100, 0]);
+    assert_eq!(c1, vec![50, 255]);
+    assert_eq!(c2, vec![25, 0]);
+}
+
+#[test]
+fn test_inverse_irreversible() {
+    // Forward transformations of (128, 128, 128) and (255, 0, 0), see G.3.1
+    let mut c0 = vec![128.0, 76.245];
+    let mut c1 = vec![0.0, -43.031_25];
+    let mut c2 = vec![0.0, 127.5];
+    inverse_irreversible(&mut c0, &mut c1, &mut c2);
+
+    let expected = [(128.0, 128.0, 128.0), (255.0, 0.0, 0.0)];
+    for (i, &(i0, i1, i2)) in expected.iter().enumerate() {
+        assert!((c0[i] - i0).abs() < 0.01, "{}", c0[i]);
+        assert!((c1[i] - i1).abs() < 0.01, "{}", c1[i]);
+        assert!((c2[i] - i2).abs() < 0.01, "{}", c2[i]);
+    }
+}
+
+#[test]
+fn test_inverse_dc_level_shift() {
+    let mut samples = vec![-128, 0, 127, -200, 200];
+    inverse_dc_level_shift(&mut samples, 8, false);
+    assert_eq!(samples, vec![0, 128, 255, 0, 255]);
+
+    let mut samples = vec![-128, 0, 127, -200, 200];
+    inverse_dc_level_shift(&mut samples, 8, true);
+    assert_eq!(samples, vec![-128, 0, 127, -128, 127]);
+
+    let mut samples = vec![-40000, 0, 40000];
+    inverse_dc_level_shift(&mut samples, 16, false);
+    assert_eq!(samples, vec![0, 32768, 65535]);
+}
+
+#[test]
+fn test_inverse_dc_level_shift_irreversible() {
+    let samples = [-0.6, 0.4, 126.4, 127.6, -300.0];
+    assert_eq!(
+        inverse_dc_level_shift_irreversible(&samples, 8, false),
+        vec![127, 128, 254, 255, 0]
+    );
+    assert_eq!(
+        inverse_dc_level_shift_irreversible(&samples, 8, true),
+        vec![-1, 0, 126, 127, -128]
+    );
+}
+
+#[test]
+fn test_forward_reversible() {
+    let mut c0 = vec![100, 0];
+    let mut c1 = vec![50, 255];
+    let mut c2 = vec![25, 0];
+    forward_reversible(&mut c0, &mut c1, &mut c2);
+    assert_eq!(c0, vec![56, 127]);
+    assert_eq!(c1, vec![-25, -255]);
+    assert_eq!(c2, vec![50, -255]);
+}
+
+#[test]
+fn test_reversible_round_trip() {
+    let mut c0: Vec<i32> = (-128..128).collect();
+    let mut c1: Vec<i32> = (-128..128).map(|i| (i * 37) % 128).collect();
+    let mut c2: Vec<i32> = (-128..128).rev().collect();
+    let (i0, i1, i2) = (c0.clone(), c1.clone(), c2.clone());
+
+    forward_reversible(&mut c0, &mut c1, &mut c2);
+    inverse_reversible(&mut c0, &mut c1, &mut c2);
+    assert_eq!(c0, i0);
+    assert_eq!(c1, i1);
+    assert_eq!(c2, i2);
+}
+
+#[test]
+fn test_forward_irreversible() {
+    let mut c0 = vec![128.0, 255.0];
+    let mut c1 = vec![128.0, 0.0];
+    let mut c2 = vec![128.0, 0.0];
+    forward_irreversible(&mut c0, &mut c1, &mut c2);
+
+    let expected = [(128.0, 0.0, 0.0), (76.245, -43.031_25, 127.5)];
+    for (i, &(y0, y1, y2)) in expected.iter().enumerate() {
+        assert!((c0[i] - y0).abs() < 0.01, "{}", c0[i]);
+        assert!((c1[i] - y1).abs() < 0.01, "{}", c1[i]);
+        assert!((c2[i] - y2).abs() < 0.01, "{}", c2[i]);
+    }
+
+    inverse_irreversible(&mut c0, &mut c1, &mut c2);
+    assert!((c0[1] - 255.0).abs() < 0.01, "{}", c0[1]);
+    assert!(c1[1].abs() < 0.01, "{}", c1[1]);
+    assert!(c2[1].abs() < 0.01, "{}", c2[1]);
+}
+
+#[test]
+fn test_forward_dc_level_shift() {
+    let mut samples = vec![0, 128, 255];
+    forward_dc_level_shift(&mut samples, 8, false);
+    assert_eq!(samples, vec![-128, 0, 127]);
+
+    let mut samples = vec![-128, 0, 127];
+    forward_dc_level_shift(&mut samples, 8, true);
+    assert_eq!(samples, vec![-128, 0, 127]);
+
+    let mut samples = vec![0, 65535];
+    forward_dc_level_shift(&mut samples, 16, false);
+    inverse_dc_level_shift(&mut samples, 16, false);
+    assert_eq!(samples, vec![0, 65535]);
+}
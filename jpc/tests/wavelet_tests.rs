@@ -0,0 +1,214 @@
+mod common;
+
+use jpc::transformation;
+use jpc::wavelet::{
+    forward_irreversible, forward_reversible, inverse_irreversible, inverse_reversible,
+};
+
+use common::Random;
+
+// Samples of a tile-component, pseudo-random in the range of 8 bit samples
+fn samples(n: usize) -> Vec<i32> {
+    let mut random = Random::new(0x2545_F491);
+    (0..n)
+        .map(|_| (random.next_u32() % 256) as i32 - 128)
+        .collect()
+}
+
+#[test]
+fn test_inverse_reversible_no_decomposition_levels() {
+    let mut data = vec![1, -2, 3, 4];
+    inverse_reversible(&mut data, 0, 0, 2, 2, 0);
+    assert_eq!(data, vec![1, -2, 3, 4]);
+}
+
+#[test]
+fn test_inverse_reversible_row() {
+    // Forward transform of [1, 2, 3, 4] gives low-pass [1, 3] and high-pass
+    // [0, 1], a single row only has a low-pass vertical subband.
+    let mut data = vec![1, 3, 0, 1];
+    inverse_reversible(&mut data, 0, 0, 4, 1, 1);
+    assert_eq!(data, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_inverse_reversible_odd_origin() {
+    // Starting at an odd coordinate the first sample is high-pass, forward
+    // transform of [1, 2, 3, 4] gives low-pass [2, 4] and high-pass [-1, 0].
+    let mut data = vec![2, 4, -1, 0];
+    inverse_reversible(&mut data, 1, 0, 5, 1, 1);
+    assert_eq!(data, vec![1, 2, 3, 4]);
+
+    let mut data = vec![2, 4, -1, 0];
+    inverse_reversible(&mut data, 0, 3, 1, 7, 1);
+    assert_eq!(data, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn test_inverse_reversible_single_odd_sample() {
+    let mut data = vec![6];
+    inverse_reversible(&mut data, 1, 0, 2, 1, 1);
+    assert_eq!(data, vec![3]);
+}
+
+#[test]
+fn test_inverse_reversible_constant() {
+    // Only the LL subband of the second decomposition level is non-zero
+    let (width, height) = (7, 5);
+    let mut data = vec![0; width * height];
+    data[0] = 42;
+    data[1] = 42;
+    inverse_reversible(&mut data, 3, 1, 3 + width as u32, 1 + height as u32, 2);
+    assert!(data.iter().all(|&sample| sample == 42));
+}
+
+#[test]
+fn test_inverse_irreversible_constant() {
+    let (width, height) = (9, 6);
+    let mut data = vec![0.0; width * height];
+    data[..3].fill(100.0);
+    inverse_irreversible(&mut data, 0, 1, width as u32, 1 + height as u32, 2);
+    for sample in data {
+        assert!((sample - 100.0).abs() < 0.01, "{}", sample);
+    }
+}
+
+#[test]
+fn test_inverse_irreversible_row() {
+    // An impulse in the high-pass subband reconstructs the 9-7 synthesis
+    // high-pass filter
+    let mut data = vec![0.0; 16];
+    data[8 + 3] = 1.0;
+    inverse_irreversible(&mut data, 0, 0, 16, 1, 1);
+    let expected = [
+        0.0, 0.0, 0.0, 0.0267, 0.0169, -0.0782, -0.2669, 0.6029, -0.2669, -0.0782, 0.0169, 0.0267,
+        0.0, 0.0, 0.0, 0.0,
+    ];
+    for (sample, expected) in data.iter().zip(expected) {
+        assert!((sample - expected).abs() < 0.0001, "{:?}", data);
+    }
+}
+
+#[test]
+fn test_forward_reversible_row() {
+    let mut data = vec![1, 2, 3, 4];
+    forward_reversible(&mut data, 0, 0, 4, 1, 1);
+    assert_eq!(data, vec![1, 3, 0, 1]);
+
+    // Starting at an odd coordinate the first sample is high-pass
+    let mut data = vec![1, 2, 3, 4];
+    forward_reversible(&mut data, 1, 0, 5, 1, 1);
+    assert_eq!(data, vec![2, 4, -1, 0]);
+
+    let mut data = vec![1, 2, 3, 4];
+    forward_reversible(&mut data, 0, 3, 1, 7, 1);
+    assert_eq!(data, vec![2, 4, -1, 0]);
+}
+
+#[test]
+fn test_forward_reversible_single_odd_sample() {
+    let mut data = vec![3];
+    forward_reversible(&mut data, 1, 0, 2, 1, 1);
+    assert_eq!(data, vec![6]);
+}
+
+#[test]
+fn test_forward_reversible_constant() {
+    // Only the LL subband of the last decomposition level is non-zero, which
+    // has ceil(10 / 4) - ceil(3 / 4) by ceil(6 / 4) - ceil(1 / 4) coefficients
+    let (width, height) = (7, 5);
+    let mut data = vec![42; width * height];
+    forward_reversible(&mut data, 3, 1, 3 + width as u32, 1 + height as u32, 2);
+    let mut expected = vec![0; width * height];
+    expected[0] = 42;
+    expected[1] = 42;
+    assert_eq!(data, expected);
+}
+
+#[test]
+fn test_reversible_round_trip() {
+    // Tile-components at every parity of the origin, including ones with a
+    // single row or column at some decomposition levels
+    for &(tcx0, tcy0, width, height) in [
+        (0, 0, 16, 16),
+        (1, 0, 13, 9),
+        (0, 1, 9, 13),
+        (3, 5, 17, 11),
+        (7, 2, 1, 6),
+        (2, 7, 6, 1),
+        (5, 5, 3, 2),
+    ]
+    .iter()
+    {
+        for no_decomposition_levels in 0..=5 {
+            let (tcx1, tcy1) = (tcx0 + width, tcy0 + height);
+            let samples = samples((width * height) as usize);
+
+            let mut data = samples.clone();
+            forward_reversible(&mut data, tcx0, tcy0, tcx1, tcy1, no_decomposition_levels);
+            inverse_reversible(&mut data, tcx0, tcy0, tcx1, tcy1, no_decomposition_levels);
+            assert_eq!(
+                data, samples,
+                "{}x{} at ({}, {}) with {} levels",
+                width, height, tcx0, tcy0, no_decomposition_levels
+            );
+        }
+    }
+}
+
+#[test]
+fn test_reversible_component_round_trip() {
+    // Forward then inverse RCT and 5-3 transformation of three components
+    // reproduce them exactly
+    let (tcx0, tcy0, tcx1, tcy1) = (5, 3, 28, 22);
+    let n = ((tcx1 - tcx0) * (tcy1 - tcy0)) as usize;
+    let samples = samples(3 * n);
+    let (c0, rest) = samples.split_at(n);
+    let (c1, c2) = rest.split_at(n);
+    let (mut y0, mut y1, mut y2) = (c0.to_vec(), c1.to_vec(), c2.to_vec());
+
+    transformation::forward_reversible(&mut y0, &mut y1, &mut y2);
+    for component in [&mut y0, &mut y1, &mut y2] {
+        forward_reversible(component, tcx0, tcy0, tcx1, tcy1, 3);
+    }
+    for component in [&mut y0, &mut y1, &mut y2] {
+        inverse_reversible(component, tcx0, tcy0, tcx1, tcy1, 3);
+    }
+    transformation::inverse_reversible(&mut y0, &mut y1, &mut y2);
+
+    assert_eq!(y0, c0);
+    assert_eq!(y1, c1);
+    assert_eq!(y2, c2);
+}
+
+#[test]
+fn test_irreversible_round_trip() {
+    for &(tcx0, tcy0, width, height) in [(0, 0, 16, 16), (3, 5, 17, 11), (7, 2, 1, 6)].iter() {
+        let (tcx1, tcy1) = (tcx0 + width, tcy0 + height);
+        let samples: Vec<f32> = samples((width * height) as usize)
+            .into_iter()
+            .map(|sample| sample as f32)
+            .collect();
+
+        let mut data = samples.clone();
+        forward_irreversible(&mut data, tcx0, tcy0, tcx1, tcy1, 4);
+        inverse_irreversible(&mut data, tcx0, tcy0, tcx1, tcy1, 4);
+        for (sample, expected) in data.iter().zip(samples.iter()) {
+            assert!((sample - expected).abs() < 0.01, "{} {}", sample, expected);
+        }
+    }
+}
+
+#[test]
+fn test_forward_irreversible_constant() {
+    // The low-pass filter has a gain of one, and the high-pass subbands of a
+    // constant are zero
+    let mut data = vec![100.0; 8];
+    forward_irreversible(&mut data, 0, 0, 8, 1, 1);
+    for sample in data[..4].iter() {
+        assert!((sample - 100.0).abs() < 0.01, "{:?}", data);
+    }
+    for sample in data[4..].iter() {
+        assert!(sample.abs() < 0.01, "{:?}", data);
+    }
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::io::Cursor;
+
+use jpc::decode_jpc;
+use jpc::writer::CodestreamWriter;
+
+use common::{read_codestream, read_sample};
+
+// Byte offsets of the COD marker, the COM marker and the SOT marker in
+// blue.j2k
+const BLUE_COD: usize = 0x33;
+const BLUE_COM: usize = 0x56;
+const BLUE_SOT: usize = 0x7D;
+
+fn round_trip(codestream: &[u8]) -> Vec<u8> {
+    let decoded = decode_jpc(&mut Cursor::new(codestream)).expect("codestream should decode");
+    let mut writer = CodestreamWriter::new(vec![]);
+    writer
+        .write_codestream(&decoded)
+        .expect("codestream should be written");
+    writer.into_inner()
+}
+
+// A marker segment with the given marker and parameters
+fn segment(marker: u8, parameters: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&(parameters.len() as u16 + 2).to_be_bytes());
+    segment.extend_from_slice(parameters);
+    segment
+}
+
+// A tile-part with a header of marker segments and Psot of the given length
+fn tile_part(
+    tile_part_index: u8,
+    no_tile_parts: u8,
+    header: &[u8],
+    data: &[u8],
+    psot: bool,
+) -> Vec<u8> {
+    let length = if psot {
+        12 + header.len() as u32 + 2 + data.len() as u32
+    } else {
+        0
+    };
+
+    let mut part = vec![0xFF, 0x90, 0x00, 0x0A, 0x00, 0x00];
+    part.extend_from_slice(&length.to_be_bytes());
+    part.push(tile_part_index);
+    part.push(no_tile_parts);
+    part.extend_from_slice(header);
+    part.extend_from_slice(&[0xFF, 0x93]);
+    part.extend_from_slice(data);
+    part
+}
+
+// The parameters of a QCC marker segment of a component with the
+// quantization of a QCD marker segment
+fn qcc(component: u8, qcd: &[u8]) -> Vec<u8> {
+    let mut parameters = vec![component];
+    parameters.extend_from_slice(&qcd[4..]);
+    parameters
+}
+
+#[test]
+fn test_write_codestreams() {
+    for filename in ["blue.j2k", "sop.j2k", "eph.j2k"].iter() {
+        let codestream = read_codestream(filename);
+        assert_eq!(round_trip(&codestream), codestream, "{}", filename);
+    }
+}
+
+#[test]
+fn test_write_samples() {
+    // file8.jp2 has the QCD marker segment before the COD marker segment,
+    // which is written back in the same order
+    for filename in [
+        "file1.jp2",
+        "file2.jp2",
+        "file3.jp2",
+        "file4.jp2",
+        "file5.jp2",
+        "file6.jp2",
+        "file7.jp2",
+        "file8.jp2",
+        "file9.jp2",
+        "subsampling_1.jp2",
+        "subsampling_2.jp2",
+        "zoo2.jp2",
+    ]
+    .iter()
+    {
+        let codestream = read_sample(filename);
+        assert_eq!(round_trip(&codestream), codestream, "{}", filename);
+    }
+}
+
+#[test]
+fn test_write_main_header_marker_segments() {
+    let blue = read_codestream("blue.j2k");
+
+    // Every marker segment of Table A.2 with PPM left out, as the packet
+    // headers of blue.j2k are in the tile-part
+    let mut codestream = blue[..BLUE_COM].to_vec();
+    // COC of component 1 with precincts defined
+    codestream.extend(segment(
+        0x53,
+        &[1, 1, 5, 4, 4, 0, 1, 0x77, 0x88, 0x88, 0x88, 0x88, 0x88],
+    ));
+    // QCC of component 2 with the quantization of QCD
+    codestream.extend(segment(0x5D, &qcc(2, &blue[BLUE_COD + 14..BLUE_COM])));
+    // RGN of component 0
+    codestream.extend(segment(0x5E, &[0, 0, 3]));
+    // POC with two progressions, the last up to component 256
+    codestream.extend(segment(0x5F, &[0, 0, 0, 1, 6, 3, 1, 0, 0, 0, 1, 6, 0, 0]));
+    // TLM with 8 bit Ttlm and 32 bit Ptlm
+    codestream.extend(segment(0x55, &[0, 0x50, 0, 0, 0, 0x12, 0x34]));
+    // PLM with two tile-parts, the lengths of the first continue in the next
+    // PLM marker segment
+    codestream.extend(segment(0x57, &[0, 2, 0x81, 0x00, 1, 0x83]));
+    codestream.extend(segment(0x57, &[1, 0x7F, 1, 0x05]));
+    // CRG of the three components
+    codestream.extend(segment(
+        0x63,
+        &[0, 0, 0, 0, 0x80, 0, 0x80, 0, 0x40, 0, 0x40, 0],
+    ));
+    codestream.extend_from_slice(&blue[BLUE_COM..]);
+
+    let decoded = decode_jpc(&mut Cursor::new(&codestream)).unwrap();
+    let header = decoded.header();
+
+    let tlm = header.tile_part_lengths_segment().as_ref().unwrap();
+    assert_eq!(tlm.index(), 0);
+    assert_eq!(tlm.tile_part_lengths(), vec![(0, 0x1234)]);
+
+    let plm = header.packet_lengths_segments();
+    assert_eq!(plm.len(), 2);
+    assert_eq!(plm[0].index(), 0);
+    assert_eq!(plm[0].data(), &[2, 0x81, 0x00, 1, 0x83]);
+    assert_eq!(plm[1].index(), 1);
+    assert_eq!(plm[1].data(), &[0x7F, 1, 0x05]);
+
+    assert_eq!(round_trip(&codestream), codestream);
+}
+
+#[test]
+fn test_write_tile_part_marker_segments() {
+    let blue = read_codestream("blue.j2k");
+    let cod = &blue[BLUE_COD..BLUE_COD + 14];
+    let qcd = &blue[BLUE_COD + 14..BLUE_COM];
+    let data = &blue[BLUE_SOT + 14..blue.len() - 2];
+    let (first, second) = data.split_at(data.len() / 2);
+
+    // The first tile-part header overrides COD and has every marker segment
+    // of Table A.3 but PPT, and the second has the later ones again, with
+    // Psot zero as the last tile-part
+    let mut header = vec![];
+    header.extend(segment(0x64, &[0, 1, b'a']));
+    header.extend_from_slice(cod);
+    header.extend(segment(0x53, &[1, 0, 5, 4, 4, 0, 1]));
+    header.extend_from_slice(qcd);
+    header.extend(segment(0x5D, &qcc(0, qcd)));
+    header.extend(segment(0x5E, &[2, 0, 1]));
+    header.extend(segment(0x5F, &[0, 0, 0, 1, 6, 3, 1]));
+    header.extend(segment(0x58, &[0, 0x81, 0x10, 0x05]));
+
+    let mut later = vec![];
+    later.extend(segment(0x58, &[1, 0x7F]));
+    later.extend(segment(0x5F, &[1, 0, 0, 1, 6, 3, 0]));
+    later.extend(segment(0x64, &[0, 1, b'b']));
+
+    let mut codestream = blue[..BLUE_SOT].to_vec();
+    codestream.extend(tile_part(0, 2, &header, first, true));
+    codestream.extend(tile_part(1, 2, &later, second, false));
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+
+    assert_eq!(round_trip(&codestream), codestream);
+}
+
+#[test]
+fn test_write_lengths() {
+    let blue = read_codestream("blue.j2k");
+    let decoded = decode_jpc(&mut Cursor::new(&blue)).unwrap();
+    let header = decoded.header();
+
+    let mut writer = CodestreamWriter::new(vec![]);
+    writer
+        .write_siz(header.image_and_tile_size_marker_segment())
+        .unwrap();
+    writer
+        .write_cod(header.coding_style_marker_segment())
+        .unwrap();
+    let bytes = writer.into_inner();
+
+    // Lsiz is 38 + 3 * Csiz and Lcod is 12 without precincts
+    assert_eq!(&bytes[..4], &[0xFF, 0x51, 0x00, 47]);
+    assert_eq!(&bytes[49..53], &[0xFF, 0x52, 0x00, 12]);
+    assert_eq!(bytes.len(), 49 + 14);
+    assert_eq!(&bytes[..], &blue[2..BLUE_COD + 14]);
+}